@@ -1,8 +1,65 @@
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use criterion::{criterion_group, criterion_main, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use taxonomy::{json::load, Taxonomy};
+use taxonomy::json::{load, load_value, save, JsonFormat};
+use taxonomy::{ncbi, Taxonomy};
+
+/// Roughly the number of nodes in the full NCBI taxonomy
+const NCBI_NODES: usize = 2_500_000;
+
+/// The system allocator, keeping track of the memory in use and its peak so that the
+/// loaders can also be compared on how much memory they need.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+fn track_allocation(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            track_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            track_allocation(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Returns how many bytes `f` needed at most on top of what was already allocated.
+fn peak_memory<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK_ALLOCATED.store(before, Ordering::Relaxed);
+    let result = f();
+    let peak = PEAK_ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(result);
+    peak
+}
 
 fn str_taxonomy(c: &mut Criterion) {
     let build_json = include_str!("../tests/data/ncbi_subset_tax.json");
@@ -32,5 +89,113 @@ fn u32_taxonomy(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, str_taxonomy, u32_taxonomy);
+/// Generates a `nodes.dmp` and a `names.dmp` with the same shape as the full NCBI ones.
+fn synthetic_ncbi_dump(n_nodes: usize) -> (Vec<u8>, Vec<u8>) {
+    let ranks = [
+        "superkingdom",
+        "phylum",
+        "class",
+        "order",
+        "family",
+        "genus",
+        "species",
+        "no rank",
+    ];
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut nodes = String::with_capacity(n_nodes * 80);
+    let mut names = String::with_capacity(n_nodes * 90);
+    for tax_id in 1..=n_nodes {
+        // parents always come before their children and the tree stays fairly shallow
+        let parent_id = rng.gen_range(1..=(tax_id / 8).max(1));
+        nodes.push_str(&format!(
+            "{}\t|\t{}\t|\t{}\t|\t\t|\t{}\t|\t1\t|\t11\t|\t1\t|\t0\t|\t1\t|\t1\t|\t0\t|\t\t|\t\t|\t\t|\t0\t|\t0\t|\t1\t|\n",
+            tax_id,
+            parent_id,
+            ranks[tax_id % ranks.len()],
+            tax_id % 12,
+        ));
+        names.push_str(&format!(
+            "{}\t|\tOrganism {}\t|\t\t|\tscientific name\t|\n",
+            tax_id, tax_id
+        ));
+        if rng.gen_bool(0.5) {
+            names.push_str(&format!(
+                "{}\t|\tSynonym {}\t|\tSynonym {} <{}>\t|\tsynonym\t|\n",
+                tax_id, tax_id, tax_id, parent_id
+            ));
+        }
+    }
+    (nodes.into_bytes(), names.into_bytes())
+}
+
+fn ncbi_loading(c: &mut Criterion) {
+    let (nodes, names) = synthetic_ncbi_dump(NCBI_NODES);
+
+    let mut group = c.benchmark_group("ncbi");
+    group.sample_size(10);
+    group.bench_function("load full-size dump", |b| {
+        b.iter(|| ncbi::load_from_readers(&nodes[..], &names[..]).unwrap());
+    });
+    group.finish();
+}
+
+/// Generates a node link JSON taxonomy with `n_nodes` nodes, all of them with some extra data.
+fn synthetic_node_link_json(n_nodes: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut nodes = Vec::with_capacity(n_nodes);
+    let mut links = Vec::with_capacity(n_nodes);
+    for ix in 0..n_nodes {
+        nodes.push(format!(
+            r#"{{"id":{},"name":"Organism {}","rank":"species","readcount":{}}}"#,
+            ix + 1,
+            ix + 1,
+            rng.gen_range(0..10_000)
+        ));
+        if ix > 0 {
+            let parent = rng.gen_range(0..=(ix / 8));
+            links.push(format!(r#"{{"source":{},"target":{}}}"#, ix, parent));
+        }
+    }
+    format!(
+        r#"{{"directed":true,"multigraph":false,"graph":[],"nodes":[{}],"links":[{}]}}"#,
+        nodes.join(","),
+        links.join(",")
+    )
+}
+
+fn json_loading(c: &mut Criterion) {
+    let node_link = synthetic_node_link_json(NCBI_NODES / 10);
+    let taxonomy = load(node_link.as_bytes(), None).unwrap();
+    let mut tree = Vec::new();
+    save::<_, &str, _>(&mut tree, &taxonomy, JsonFormat::Tree, None).unwrap();
+
+    let mut group = c.benchmark_group("json");
+    group.sample_size(10);
+    for (format, json) in [("node link", node_link.as_bytes()), ("tree", &tree[..])] {
+        let stream_peak = peak_memory(|| load(json, None).unwrap());
+        let value_peak =
+            peak_memory(|| load_value(&serde_json::from_reader(json).unwrap(), None).unwrap());
+        println!(
+            "json/{}: peak memory {:.1} MB streamed, {:.1} MB through a Value",
+            format,
+            stream_peak as f64 / 1e6,
+            value_peak as f64 / 1e6
+        );
+        group.bench_function(format!("stream {}", format), |b| {
+            b.iter(|| load(json, None).unwrap());
+        });
+        group.bench_function(format!("value {}", format), |b| {
+            b.iter(|| load_value(&serde_json::from_reader(json).unwrap(), None).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    str_taxonomy,
+    u32_taxonomy,
+    ncbi_loading,
+    json_loading
+);
 criterion_main!(benches);
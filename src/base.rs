@@ -11,6 +11,62 @@ use crate::taxonomy::Taxonomy;
 
 pub type InternalIndex = usize;
 
+/// The name class used for the main name of a node.
+pub const SCIENTIFIC_NAME: &str = "scientific name";
+
+/// One of the names of a node, e.g. a synonym or a common name in NCBI `names.dmp`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TaxName {
+    pub name: String,
+    /// A unique variant of the name if the name is shared by several nodes, empty otherwise
+    pub unique_name: String,
+    /// The class of the name, e.g. "synonym", "common name" or "scientific name"
+    pub class: String,
+}
+
+/// A division from NCBI `division.dmp`, e.g. `BCT` for Bacteria.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Division {
+    pub code: String,
+    pub name: String,
+    pub comments: String,
+}
+
+/// A genetic code from NCBI `gencode.dmp`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GeneticCode {
+    pub abbreviation: String,
+    pub name: String,
+    /// The amino acid of each of the 64 codons, in TCAG order
+    pub translation_table: String,
+    /// Which of the 64 codons can be start codons (`M`), in TCAG order
+    pub start_codons: String,
+}
+
+/// The columns of a NCBI `nodes.dmp` line besides the tax ID, parent tax ID and rank.
+/// The last 5 are only present in the `new_taxdump` files.
+///
+/// They are returned by [Taxonomy::node_info]. The division and genetic codes they refer to
+/// are available with [GeneralTaxonomy::division] and [GeneralTaxonomy::genetic_code].
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub embl_code: String,
+    pub division_id: Option<u32>,
+    pub inherited_division: bool,
+    pub genetic_code_id: Option<u32>,
+    pub inherited_genetic_code: bool,
+    pub mitochondrial_genetic_code_id: Option<u32>,
+    pub inherited_mitochondrial_genetic_code: bool,
+    pub genbank_hidden: bool,
+    pub hidden_subtree_root: bool,
+    pub comments: String,
+    pub plastid_genetic_code_id: Option<u32>,
+    pub inherited_plastid_genetic_code: Option<bool>,
+    pub specified_species: Option<bool>,
+    pub hydrogenosome_genetic_code_id: Option<u32>,
+    pub inherited_hydrogenosome_genetic_code: Option<bool>,
+}
+
 /// The type that is returned when loading any taxonomies through that library.
 /// It include 2 implementations of the [Taxonomy] trait: one using strings as ids
 /// (easier to use but slower) and one using internal indices (harder to use but faster).
@@ -20,10 +76,40 @@ pub struct GeneralTaxonomy {
     pub tax_ids: Vec<String>,
     pub parent_ids: Vec<InternalIndex>,
     pub parent_distances: Vec<f32>,
+    /// The support value (e.g. bootstrap or posterior probability) of each node, only set
+    /// for the internal nodes of phylogenetic trees. These are also returned by
+    /// [Taxonomy::support].
+    #[serde(default)]
+    pub support_values: Vec<Option<f32>>,
     pub names: Vec<String>,
     pub ranks: Vec<TaxRank>,
     // Only used by the JSON format
     pub data: Vec<HashMap<String, Value>>,
+    /// Tax IDs that have been merged into another node, eg from NCBI `merged.dmp`.
+    /// Maps the obsolete tax ID to the tax ID it was merged into.
+    #[serde(default)]
+    pub merged_ids: HashMap<String, String>,
+    /// Tax IDs that have been deleted from the taxonomy, eg from NCBI `delnodes.dmp`.
+    #[serde(default)]
+    pub deleted_ids: HashSet<String>,
+    /// All the names of a node by tax ID, including the scientific one, eg from NCBI `names.dmp`.
+    /// Nodes that only have their main name in `names` do not need to be in there.
+    #[serde(default)]
+    pub all_names: HashMap<String, Vec<TaxName>>,
+    /// The extra `nodes.dmp` columns of each node, if it came from NCBI. These are also
+    /// returned by [Taxonomy::node_info].
+    #[serde(default)]
+    pub ncbi_nodes: Vec<Option<NodeInfo>>,
+    /// The NCBI divisions by ID, from `division.dmp`
+    #[serde(default)]
+    pub divisions: HashMap<u32, Division>,
+    /// The NCBI genetic codes by ID, from `gencode.dmp`
+    #[serde(default)]
+    pub genetic_codes: HashMap<u32, GeneticCode>,
+    /// The tax IDs that were made up for the nodes that didn't have any, see
+    /// [GeneralTaxonomy::synthesize_ids].
+    #[serde(default)]
+    pub synthesized_ids: HashSet<String>,
 
     // Lookup tables that can dramatically speed up some operations
     pub(crate) tax_id_lookup: HashMap<String, InternalIndex>,
@@ -37,9 +123,17 @@ impl Default for GeneralTaxonomy {
             tax_ids: vec!["1".to_string()],
             parent_ids: vec![0],
             parent_distances: vec![1.],
+            support_values: vec![None],
             ranks: vec![TaxRank::Unspecified],
             names: vec!["root".to_string()],
             data: vec![HashMap::new()],
+            merged_ids: HashMap::new(),
+            deleted_ids: HashSet::new(),
+            all_names: HashMap::new(),
+            ncbi_nodes: vec![None],
+            divisions: HashMap::new(),
+            genetic_codes: HashMap::new(),
+            synthesized_ids: HashSet::new(),
 
             tax_id_lookup: HashMap::new(),
             children_lookup: Vec::new(),
@@ -61,7 +155,10 @@ impl GeneralTaxonomy {
         for (ix, tax_id) in self.tax_ids.iter().enumerate() {
             self.tax_id_lookup.insert(tax_id.clone(), ix);
         }
+        self.index_children();
+    }
 
+    fn index_children(&mut self) {
         for v in self.children_lookup.iter_mut() {
             v.clear();
         }
@@ -122,7 +219,7 @@ impl GeneralTaxonomy {
         let mut tax_ids = self.tax_ids.clone();
         tax_ids.sort_unstable();
         let mut dupes = HashSet::new();
-        let mut last = tax_ids.get(0).unwrap();
+        let mut last = tax_ids.first().unwrap();
 
         for i in 1..tax_ids.len() {
             let cur = tax_ids.get(i).unwrap();
@@ -143,12 +240,28 @@ impl GeneralTaxonomy {
         Ok(())
     }
 
+    /// Converts a tax ID to its internal index.
+    /// Tax IDs that have been merged into another node resolve to that node and
+    /// deleted tax IDs return an [ErrorKind::DeletedTaxId] error.
     #[inline]
     pub fn to_internal_index(&self, tax_id: &str) -> TaxonomyResult<InternalIndex> {
-        self.tax_id_lookup.get(tax_id).map_or_else(
-            || Err(Error::new(ErrorKind::NoSuchTaxId(tax_id.to_owned()))),
-            |t| Ok(*t),
-        )
+        self.tax_id_lookup
+            .get(tax_id)
+            .or_else(|| {
+                self.merged_ids
+                    .get(tax_id)
+                    .and_then(|new_id| self.tax_id_lookup.get(new_id))
+            })
+            .map_or_else(
+                || {
+                    if self.deleted_ids.contains(tax_id) {
+                        Err(Error::new(ErrorKind::DeletedTaxId(tax_id.to_owned())))
+                    } else {
+                        Err(Error::new(ErrorKind::NoSuchTaxId(tax_id.to_owned())))
+                    }
+                },
+                |t| Ok(*t),
+            )
     }
 
     #[inline]
@@ -169,6 +282,20 @@ impl GeneralTaxonomy {
         ranks: Option<Vec<TaxRank>>,
         distances: Option<Vec<f32>>,
         data: Option<Vec<HashMap<String, Value>>>,
+    ) -> TaxonomyResult<Self> {
+        Self::from_arrays_with_lookup(tax_ids, parent_ids, names, ranks, distances, data, None)
+    }
+
+    /// Same as [GeneralTaxonomy::from_arrays] but uses the given tax ID to index lookup
+    /// instead of building it again if the loader already had to build one.
+    pub(crate) fn from_arrays_with_lookup(
+        tax_ids: Vec<String>,
+        parent_ids: Vec<InternalIndex>,
+        names: Option<Vec<String>>,
+        ranks: Option<Vec<TaxRank>>,
+        distances: Option<Vec<f32>>,
+        data: Option<Vec<HashMap<String, Value>>>,
+        tax_id_lookup: Option<HashMap<String, InternalIndex>>,
     ) -> TaxonomyResult<Self> {
         let size = tax_ids.len();
         let adj_names = names.unwrap_or_else(|| vec![String::new(); tax_ids.len()]);
@@ -201,23 +328,69 @@ impl GeneralTaxonomy {
                 "Mismatched number of tax ids and extra data".to_owned(),
             )));
         }
+        if tax_id_lookup
+            .as_ref()
+            .is_some_and(|lookup| size != lookup.len())
+        {
+            return Err(Error::new(ErrorKind::InvalidTaxonomy(
+                "Mismatched number of tax ids and tax id lookup".to_owned(),
+            )));
+        }
 
         let mut tax = GeneralTaxonomy {
             tax_ids,
             parent_ids,
             parent_distances: adj_distances,
+            support_values: vec![None; size],
             names: adj_names,
             ranks: adj_ranks,
             data: adj_data,
+            merged_ids: HashMap::new(),
+            deleted_ids: HashSet::new(),
+            all_names: HashMap::new(),
+            ncbi_nodes: vec![None; size],
+            divisions: HashMap::new(),
+            genetic_codes: HashMap::new(),
+            synthesized_ids: HashSet::new(),
 
-            tax_id_lookup: HashMap::with_capacity(size),
+            tax_id_lookup: HashMap::new(),
             children_lookup: vec![Vec::new(); size],
         };
-        tax.index();
+        if let Some(lookup) = tax_id_lookup {
+            tax.tax_id_lookup = lookup;
+            tax.index_children();
+        } else {
+            tax.index();
+        }
         tax.validate()?;
         Ok(tax)
     }
 
+    /// Returns the tax ID that `tax_id` was merged into, if it was merged.
+    pub fn merged_into(&self, tax_id: &str) -> Option<&str> {
+        self.merged_ids.get(tax_id).map(|x| x.as_str())
+    }
+
+    /// Returns whether `tax_id` has been deleted from the taxonomy.
+    pub fn is_deleted(&self, tax_id: &str) -> bool {
+        self.deleted_ids.contains(tax_id)
+    }
+
+    /// Records that `old_tax_id` was merged into `new_tax_id` so that looking up
+    /// `old_tax_id` returns the `new_tax_id` node.
+    pub fn add_merged_id(&mut self, old_tax_id: &str, new_tax_id: &str) -> TaxonomyResult<()> {
+        let new_idx = self.to_internal_index(new_tax_id)?;
+        if self.tax_id_lookup.contains_key(old_tax_id) {
+            return Err(Error::new(ErrorKind::OperationNotAllowed(format!(
+                "Tax ID {} is still present in the taxonomy",
+                old_tax_id
+            ))));
+        }
+        self.merged_ids
+            .insert(old_tax_id.to_string(), self.tax_ids[new_idx].clone());
+        Ok(())
+    }
+
     /// Retrieves all external IDs given a name
     pub fn find_all_by_name(&self, name: &str) -> Vec<&str> {
         let name_indices = self
@@ -234,6 +407,115 @@ impl GeneralTaxonomy {
             .collect()
     }
 
+    /// Retrieves all external IDs that have that name in one of the given name classes,
+    /// e.g. `["synonym", "common name"]`. The main name of a node is its `scientific name`.
+    pub fn find_all_by_name_class(&self, name: &str, classes: &[&str]) -> Vec<&str> {
+        let scientific = classes.contains(&SCIENTIFIC_NAME);
+        self.tax_ids
+            .iter()
+            .enumerate()
+            .filter(|(idx, tax_id)| {
+                (scientific && self.names[*idx] == name)
+                    || self.all_names.get(*tax_id).is_some_and(|names| {
+                        names.iter().any(|n| {
+                            n.name == name
+                                && n.class != SCIENTIFIC_NAME
+                                && classes.contains(&n.class.as_str())
+                        })
+                    })
+            })
+            .map(|(_, tax_id)| tax_id.as_str())
+            .collect()
+    }
+
+    /// Retrieves the names of that class for the given tax ID, e.g. all of its synonyms.
+    /// The `scientific name` is always the main name of the node.
+    pub fn names_of(&self, tax_id: &str, class: &str) -> TaxonomyResult<Vec<&str>> {
+        let idx = self.to_internal_index(tax_id)?;
+        if class == SCIENTIFIC_NAME {
+            return Ok(vec![&self.names[idx]]);
+        }
+        Ok(self
+            .all_names
+            .get(&self.tax_ids[idx])
+            .map(|names| {
+                names
+                    .iter()
+                    .filter(|n| n.class == class)
+                    .map(|n| n.name.as_str())
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Returns the NCBI division of the given tax ID, if known.
+    pub fn division(&self, tax_id: &str) -> TaxonomyResult<Option<&Division>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Ok(self
+            .ncbi_nodes
+            .get(idx)
+            .and_then(|info| info.as_ref()?.division_id)
+            .and_then(|id| self.divisions.get(&id)))
+    }
+
+    /// Returns the NCBI genetic code of the given tax ID, if known.
+    pub fn genetic_code(&self, tax_id: &str) -> TaxonomyResult<Option<&GeneticCode>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Ok(self
+            .ncbi_nodes
+            .get(idx)
+            .and_then(|info| info.as_ref()?.genetic_code_id)
+            .and_then(|id| self.genetic_codes.get(&id)))
+    }
+
+    /// Returns the NCBI mitochondrial genetic code of the given tax ID, if known.
+    pub fn mitochondrial_genetic_code(&self, tax_id: &str) -> TaxonomyResult<Option<&GeneticCode>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Ok(self
+            .ncbi_nodes
+            .get(idx)
+            .and_then(|info| info.as_ref()?.mitochondrial_genetic_code_id)
+            .and_then(|id| self.genetic_codes.get(&id)))
+    }
+
+    /// Gives a tax ID to the nodes that have an empty one, e.g. the unlabelled nodes of
+    /// phylogenetic trees, and records them in `synthesized_ids`.
+    ///
+    /// If `use_names` is set the name of the node is used if it has one that isn't already a
+    /// tax ID, otherwise the tax ID is `prefix` followed by a counter (e.g. `node1`, `node2`)
+    /// that skips the tax IDs already in use. The nodes are numbered in order so loading the
+    /// same tree always gives the same tax IDs.
+    pub fn synthesize_ids(&mut self, prefix: &str, use_names: bool) {
+        let mut used: HashSet<String> = self
+            .tax_ids
+            .iter()
+            .filter(|x| !x.is_empty())
+            .cloned()
+            .collect();
+        let mut counter = 0;
+        for idx in 0..self.tax_ids.len() {
+            if !self.tax_ids[idx].is_empty() {
+                continue;
+            }
+            let name = &self.names[idx];
+            let tax_id = if use_names && !name.is_empty() && !used.contains(name) {
+                name.clone()
+            } else {
+                loop {
+                    counter += 1;
+                    let tax_id = format!("{}{}", prefix, counter);
+                    if !used.contains(&tax_id) {
+                        break tax_id;
+                    }
+                }
+            };
+            used.insert(tax_id.clone());
+            self.synthesized_ids.insert(tax_id.clone());
+            self.tax_ids[idx] = tax_id;
+        }
+        self.index();
+    }
+
     /// Add a new node to the taxonomy.
     pub fn add(&mut self, parent_id: &str, tax_id: &str) -> TaxonomyResult<()> {
         let parent_idx = self.to_internal_index(parent_id)?;
@@ -242,9 +524,11 @@ impl GeneralTaxonomy {
         self.tax_ids.push(tax_id.to_string());
         self.parent_ids.push(parent_idx);
         self.parent_distances.push(1.0);
+        self.support_values.push(None);
         self.ranks.push(TaxRank::Unspecified);
         self.names.push(String::new());
         self.data.push(HashMap::new());
+        self.ncbi_nodes.push(None);
 
         // update the cached lookup tables
         self.tax_id_lookup.insert(tax_id.to_string(), new_idx);
@@ -287,12 +571,20 @@ impl GeneralTaxonomy {
 
         // and delete the node from all the other tables
         // (note we do this last so we still have the tax id above)
+        self.all_names.remove(&self.tax_ids[idx]);
+        self.synthesized_ids.remove(&self.tax_ids[idx]);
         self.tax_ids.remove(idx);
         self.parent_ids.remove(idx);
         self.parent_distances.remove(idx);
+        if idx < self.support_values.len() {
+            self.support_values.remove(idx);
+        }
         self.ranks.remove(idx);
         self.names.remove(idx);
         self.data.remove(idx);
+        if idx < self.ncbi_nodes.len() {
+            self.ncbi_nodes.remove(idx);
+        }
 
         // all parent indices pointing to positions after the removed node must be
         // decremented by 1, because the array shifted. This applies to every element
@@ -350,14 +642,61 @@ impl<'t> Taxonomy<'t, &'t str> for GeneralTaxonomy {
         )))
     }
 
-    fn name(&'t self, tax_id: &str) -> TaxonomyResult<&str> {
+    // `lineage` and `lca` go through internal indices so that merged tax IDs
+    // are reported as the node they were merged into
+    fn lineage(&'t self, tax_id: &'t str) -> TaxonomyResult<Vec<&'t str>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::lineage(self, idx)?
+            .into_iter()
+            .map(|x| self.from_internal_index(x))
+            .collect()
+    }
+
+    fn lca(&'t self, id1: &'t str, id2: &'t str) -> TaxonomyResult<&'t str> {
+        let idx1 = self.to_internal_index(id1)?;
+        let idx2 = self.to_internal_index(id2)?;
+        self.from_internal_index(Taxonomy::<InternalIndex>::lca(self, idx1, idx2)?)
+    }
+
+    fn name(&'t self, tax_id: &str) -> TaxonomyResult<&'t str> {
         let idx = self.to_internal_index(tax_id)?;
         Ok(&self.names[idx])
     }
 
     fn data(&'t self, tax_id: &str) -> TaxonomyResult<Cow<'t, HashMap<String, Value>>> {
         let idx = self.to_internal_index(tax_id)?;
-        Ok(Cow::Borrowed(&self.data[idx]))
+        Taxonomy::<InternalIndex>::data(self, idx)
+    }
+
+    fn all_names(&'t self, tax_id: &str) -> TaxonomyResult<Cow<'t, [TaxName]>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::all_names(self, idx)
+    }
+
+    fn support(&'t self, tax_id: &str) -> TaxonomyResult<Option<f32>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::support(self, idx)
+    }
+
+    fn node_info(&'t self, tax_id: &str) -> TaxonomyResult<Option<Cow<'t, NodeInfo>>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::node_info(self, idx)
+    }
+
+    fn division_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, Division>>> {
+        Taxonomy::<InternalIndex>::division_by_id(self, id)
+    }
+
+    fn genetic_code_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, GeneticCode>>> {
+        Taxonomy::<InternalIndex>::genetic_code_by_id(self, id)
+    }
+
+    fn merged_tax_ids(&'t self) -> TaxonomyResult<Vec<(&'t str, &'t str)>> {
+        Taxonomy::<InternalIndex>::merged_tax_ids(self)
+    }
+
+    fn deleted_tax_ids(&'t self) -> TaxonomyResult<Vec<&'t str>> {
+        Taxonomy::<InternalIndex>::deleted_tax_ids(self)
     }
 
     fn rank(&'t self, tax_id: &str) -> TaxonomyResult<TaxRank> {
@@ -410,7 +749,7 @@ impl<'t> Taxonomy<'t, InternalIndex> for GeneralTaxonomy {
         Ok(Some((self.parent_ids[idx], self.parent_distances[idx])))
     }
 
-    fn name(&'t self, idx: InternalIndex) -> TaxonomyResult<&str> {
+    fn name(&'t self, idx: InternalIndex) -> TaxonomyResult<&'t str> {
         if let Some(name) = self.names.get(idx) {
             Ok(name)
         } else {
@@ -426,6 +765,57 @@ impl<'t> Taxonomy<'t, InternalIndex> for GeneralTaxonomy {
         }
     }
 
+    fn all_names(&'t self, idx: InternalIndex) -> TaxonomyResult<Cow<'t, [TaxName]>> {
+        if let Some(tax_id) = self.tax_ids.get(idx) {
+            Ok(self
+                .all_names
+                .get(tax_id)
+                .map_or(Cow::Borrowed(&[][..]), |names| Cow::Borrowed(&names[..])))
+        } else {
+            Err(Error::new(ErrorKind::NoSuchInternalIndex(idx)))
+        }
+    }
+
+    fn support(&'t self, idx: InternalIndex) -> TaxonomyResult<Option<f32>> {
+        if idx < self.tax_ids.len() {
+            Ok(self.support_values.get(idx).copied().flatten())
+        } else {
+            Err(Error::new(ErrorKind::NoSuchInternalIndex(idx)))
+        }
+    }
+
+    fn node_info(&'t self, idx: InternalIndex) -> TaxonomyResult<Option<Cow<'t, NodeInfo>>> {
+        if idx < self.tax_ids.len() {
+            Ok(self
+                .ncbi_nodes
+                .get(idx)
+                .and_then(|info| info.as_ref())
+                .map(Cow::Borrowed))
+        } else {
+            Err(Error::new(ErrorKind::NoSuchInternalIndex(idx)))
+        }
+    }
+
+    fn division_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, Division>>> {
+        Ok(self.divisions.get(&id).map(Cow::Borrowed))
+    }
+
+    fn genetic_code_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, GeneticCode>>> {
+        Ok(self.genetic_codes.get(&id).map(Cow::Borrowed))
+    }
+
+    fn merged_tax_ids(&'t self) -> TaxonomyResult<Vec<(&'t str, &'t str)>> {
+        Ok(self
+            .merged_ids
+            .iter()
+            .map(|(old_id, new_id)| (old_id.as_str(), new_id.as_str()))
+            .collect())
+    }
+
+    fn deleted_tax_ids(&'t self) -> TaxonomyResult<Vec<&'t str>> {
+        Ok(self.deleted_ids.iter().map(|x| x.as_str()).collect())
+    }
+
     fn rank(&'t self, idx: InternalIndex) -> TaxonomyResult<TaxRank> {
         if let Some(rank) = self.ranks.get(idx) {
             Ok(*rank)
@@ -484,6 +874,42 @@ mod tests {
         assert_eq!(tax.parent("562").unwrap(), Some(("2", 1.0)));
     }
 
+    #[test]
+    fn can_synthesize_ids() {
+        let mut tax = GeneralTaxonomy::from_arrays(
+            vec![
+                "".to_string(),
+                "a".to_string(),
+                "".to_string(),
+                "".to_string(),
+            ],
+            vec![0, 0, 0, 1],
+            Some(vec![
+                "".to_string(),
+                "A".to_string(),
+                "a".to_string(),
+                "Node".to_string(),
+            ]),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let mut tax2 = tax.clone();
+
+        tax.synthesize_ids("node", false);
+        assert_eq!(tax.tax_ids, vec!["node1", "a", "node2", "node3"]);
+        assert_eq!(tax.parent("node3").unwrap(), Some(("a", 1.)));
+        assert_eq!(tax.synthesized_ids.len(), 3);
+        assert!(!tax.synthesized_ids.contains("a"));
+        tax.remove("node3").unwrap();
+        assert_eq!(tax.synthesized_ids.len(), 2);
+
+        // names are used unless they are already tax IDs
+        tax2.synthesize_ids("Node", true);
+        assert_eq!(tax2.tax_ids, vec!["Node1", "a", "Node2", "Node"]);
+    }
+
     #[test]
     fn can_find_all_by_name() {
         let tax = create_test_taxonomy();
@@ -493,6 +919,24 @@ mod tests {
         assert_eq!(res, vec!["101", "102"]);
     }
 
+    #[test]
+    fn can_follow_merged_ids() {
+        let mut tax = create_test_taxonomy();
+        tax.add_merged_id("561", "562").unwrap();
+        // Merging into a merged id resolves to the final node
+        tax.add_merged_id("560", "561").unwrap();
+        assert_eq!(tax.merged_into("560"), Some("562"));
+        assert_eq!(tax.merged_into("562"), None);
+        assert_eq!(tax.name("561").unwrap(), "Escherichia coli");
+        assert_eq!(tax.lineage("560").unwrap(), vec!["562", "2", "1"]);
+        assert_eq!(tax.lca("561", "1000").unwrap(), "1");
+        assert_eq!(tax.lca("561", "2").unwrap(), "2");
+
+        // Can't alias an existing node or point to an unknown one
+        assert!(tax.add_merged_id("2", "562").is_err());
+        assert!(tax.add_merged_id("3", "4").is_err());
+    }
+
     #[test]
     fn can_add_node() {
         let mut tax = create_test_taxonomy();
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use crate::base::InternalIndex;
+use crate::errors::TaxonomyResult;
+use crate::gtdb::GTDB_RANKS;
+use crate::{GeneralTaxonomy, TaxRank, Taxonomy};
+
+/// Which way to translate with a [Crosswalk].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    GtdbToNcbi,
+    NcbiToGtdb,
+}
+
+/// The node a node was translated into, with the number of genomes supporting it and the
+/// fraction of the genomes of the translated node that it represents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Translation {
+    pub tax_id: String,
+    pub genomes: usize,
+    pub confidence: f32,
+}
+
+/// The result of [Crosswalk::translate_abundances].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TranslatedAbundances {
+    /// The total abundance of each node of the other taxonomy
+    pub abundances: HashMap<String, f64>,
+    /// The mean confidence of the translations into each node, weighted by abundance
+    pub confidences: HashMap<String, f32>,
+    /// The abundance of the nodes that could not be translated
+    pub untranslated: HashMap<String, f64>,
+}
+
+/// A many-to-many mapping between the nodes of the GTDB and NCBI taxonomies, at each
+/// GTDB rank (domain to species), built from the genomes they have in common.
+///
+/// Each link between a GTDB and a NCBI node is supported by the number of genomes that are
+/// below both of them.
+#[derive(Clone, Debug, Default)]
+pub struct Crosswalk {
+    gtdb_to_ncbi: HashMap<String, HashMap<String, usize>>,
+    ncbi_to_gtdb: HashMap<String, HashMap<String, usize>>,
+}
+
+/// Returns the ancestor of that node at that rank, treating the NCBI superkingdoms as domains.
+fn ancestor_at_rank(
+    tax: &GeneralTaxonomy,
+    idx: InternalIndex,
+    rank: TaxRank,
+) -> TaxonomyResult<Option<InternalIndex>> {
+    let mut ancestor = tax.parent_at_rank(idx, rank)?;
+    if ancestor.is_none() && rank == TaxRank::Domain {
+        ancestor = tax.parent_at_rank(idx, TaxRank::Superkingdom)?;
+    }
+    Ok(ancestor.map(|(a, _)| a))
+}
+
+impl Crosswalk {
+    /// Build the crosswalk out of the genomes of a GTDB taxonomy loaded with
+    /// [crate::gtdb::load_metadata] that have a `ncbi_taxid`. Genomes with tax IDs that
+    /// are not in the NCBI taxonomy are ignored and merged tax IDs are followed.
+    pub fn new(ncbi: &GeneralTaxonomy, gtdb: &GeneralTaxonomy) -> TaxonomyResult<Self> {
+        let mut crosswalk = Crosswalk::default();
+
+        for gtdb_idx in 0..Taxonomy::<InternalIndex>::len(gtdb) {
+            let data = gtdb.data(gtdb_idx)?;
+            let ncbi_tax_id = match data.get("ncbi_taxid") {
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(serde_json::Value::Number(n)) => n.to_string(),
+                _ => continue,
+            };
+            let ncbi_idx = match ncbi.to_internal_index(&ncbi_tax_id) {
+                Ok(idx) => idx,
+                Err(_) => continue,
+            };
+
+            for (rank, _) in GTDB_RANKS {
+                let gtdb_node = ancestor_at_rank(gtdb, gtdb_idx, rank)?;
+                let ncbi_node = ancestor_at_rank(ncbi, ncbi_idx, rank)?;
+                if let (Some(gtdb_node), Some(ncbi_node)) = (gtdb_node, ncbi_node) {
+                    let gtdb_id = gtdb.from_internal_index(gtdb_node)?;
+                    let ncbi_id = ncbi.from_internal_index(ncbi_node)?;
+                    *crosswalk
+                        .gtdb_to_ncbi
+                        .entry(gtdb_id.to_string())
+                        .or_default()
+                        .entry(ncbi_id.to_string())
+                        .or_default() += 1;
+                    *crosswalk
+                        .ncbi_to_gtdb
+                        .entry(ncbi_id.to_string())
+                        .or_default()
+                        .entry(gtdb_id.to_string())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        Ok(crosswalk)
+    }
+
+    /// Returns all the nodes of the other taxonomy, at the same rank, that share genomes with
+    /// that node, along with the number of genomes. The best supported ones come first.
+    pub fn mappings(&self, tax_id: &str, direction: Direction) -> Vec<(&str, usize)> {
+        let links = match direction {
+            Direction::GtdbToNcbi => &self.gtdb_to_ncbi,
+            Direction::NcbiToGtdb => &self.ncbi_to_gtdb,
+        };
+        let mut mappings: Vec<(&str, usize)> = links
+            .get(tax_id)
+            .map(|m| m.iter().map(|(k, v)| (k.as_str(), *v)).collect())
+            .unwrap_or_default();
+        // sort by tax id on ties so the result doesn't depend on the hashing
+        mappings.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        mappings
+    }
+
+    /// Translate a node into the node of the other taxonomy supported by the most genomes,
+    /// or [None] if it isn't in the crosswalk.
+    pub fn translate(&self, tax_id: &str, direction: Direction) -> Option<Translation> {
+        let mappings = self.mappings(tax_id, direction);
+        let total: usize = mappings.iter().map(|(_, n)| n).sum();
+        mappings.first().map(|(best, genomes)| Translation {
+            tax_id: best.to_string(),
+            genomes: *genomes,
+            confidence: *genomes as f32 / total as f32,
+        })
+    }
+
+    /// Translate an abundance table, keyed by tax ID: the abundance of each node goes to
+    /// the node it translates into with [Crosswalk::translate].
+    pub fn translate_abundances(
+        &self,
+        abundances: &HashMap<String, f64>,
+        direction: Direction,
+    ) -> TranslatedAbundances {
+        let mut result = TranslatedAbundances::default();
+        for (tax_id, abundance) in abundances {
+            match self.translate(tax_id, direction) {
+                Some(translation) => {
+                    *result
+                        .abundances
+                        .entry(translation.tax_id.clone())
+                        .or_default() += abundance;
+                    *result.confidences.entry(translation.tax_id).or_default() +=
+                        translation.confidence * *abundance as f32;
+                }
+                None => {
+                    *result.untranslated.entry(tax_id.clone()).or_default() += abundance;
+                }
+            }
+        }
+        for (tax_id, confidence) in result.confidences.iter_mut() {
+            let abundance = result.abundances[tax_id] as f32;
+            if abundance > 0.0 {
+                *confidence /= abundance;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gtdb, ncbi};
+    use std::fs::File;
+
+    fn crosswalk() -> Crosswalk {
+        let ncbi = ncbi::load("tests/data/").unwrap();
+        let mut file = File::open("tests/data/gtdb_metadata_sample.tsv").unwrap();
+        let gtdb = gtdb::load_metadata(&mut file, &gtdb::DEFAULT_METADATA_COLUMNS).unwrap();
+        Crosswalk::new(&ncbi, &gtdb).unwrap()
+    }
+
+    #[test]
+    fn can_map_gtdb_and_ncbi() {
+        let crosswalk = crosswalk();
+        assert_eq!(
+            crosswalk.mappings("562", Direction::NcbiToGtdb),
+            [("s__Escherichia coli", 2), ("s__Escherichia albertii", 1)]
+        );
+        assert_eq!(
+            crosswalk.mappings("s__Escherichia albertii", Direction::GtdbToNcbi),
+            [("562", 1)]
+        );
+        // superkingdoms are domains
+        assert_eq!(
+            crosswalk.mappings("d__Bacteria", Direction::GtdbToNcbi),
+            [("2", 3)]
+        );
+        // the other genomes are not in the test NCBI taxonomy
+        assert!(crosswalk
+            .mappings("s__Salmonella enterica", Direction::GtdbToNcbi)
+            .is_empty());
+    }
+
+    #[test]
+    fn can_translate_nodes() {
+        let crosswalk = crosswalk();
+        assert_eq!(
+            crosswalk.translate("562", Direction::NcbiToGtdb),
+            Some(Translation {
+                tax_id: "s__Escherichia coli".to_string(),
+                genomes: 2,
+                confidence: 2.0 / 3.0,
+            })
+        );
+        let translation = crosswalk
+            .translate("g__Escherichia", Direction::GtdbToNcbi)
+            .unwrap();
+        assert_eq!(translation.tax_id, "561");
+        assert_eq!(translation.confidence, 1.0);
+        assert_eq!(crosswalk.translate("unknown", Direction::NcbiToGtdb), None);
+    }
+
+    #[test]
+    fn can_translate_abundances() {
+        let crosswalk = crosswalk();
+        let abundances: HashMap<String, f64> = [
+            ("s__Escherichia coli".to_string(), 3.0),
+            ("s__Escherichia albertii".to_string(), 1.0),
+            ("c__Gammaproteobacteria".to_string(), 2.0),
+            ("s__Salmonella enterica".to_string(), 0.5),
+        ]
+        .into_iter()
+        .collect();
+        let result = crosswalk.translate_abundances(&abundances, Direction::GtdbToNcbi);
+        assert_eq!(result.abundances["562"], 4.0);
+        assert_eq!(result.confidences["562"], 1.0);
+        assert_eq!(result.abundances["1236"], 2.0);
+        assert_eq!(
+            result.untranslated,
+            [("s__Salmonella enterica".to_string(), 0.5)]
+                .into_iter()
+                .collect()
+        );
+    }
+}
@@ -1,10 +1,57 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
 
 use crate::errors::TaxonomyResult;
 use crate::{GeneralTaxonomy, Taxonomy};
 
+/// Copies the tables that aren't per node into the taxonomy made of the nodes that were kept,
+/// leaving out the entries that only applied to the removed nodes.
+fn copy_shared_tables<'t, T>(
+    tax: &'t impl Taxonomy<'t, T>,
+    new_tax: &mut GeneralTaxonomy,
+) -> TaxonomyResult<()>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    for info in new_tax.ncbi_nodes.iter().flatten() {
+        if let Some(id) = info.division_id {
+            if let Entry::Vacant(entry) = new_tax.divisions.entry(id) {
+                if let Some(division) = tax.division_by_id(id)? {
+                    entry.insert(division.into_owned());
+                }
+            }
+        }
+        let code_ids = [
+            info.genetic_code_id,
+            info.mitochondrial_genetic_code_id,
+            info.plastid_genetic_code_id,
+            info.hydrogenosome_genetic_code_id,
+        ];
+        for id in code_ids.into_iter().flatten() {
+            if let Entry::Vacant(entry) = new_tax.genetic_codes.entry(id) {
+                if let Some(code) = tax.genetic_code_by_id(id)? {
+                    entry.insert(code.into_owned());
+                }
+            }
+        }
+    }
+    // the merged tax IDs only resolve if the node they were merged into is still there
+    for (old_id, new_id) in tax.merged_tax_ids()? {
+        if new_tax.tax_id_lookup.contains_key(new_id) {
+            new_tax
+                .merged_ids
+                .insert(old_id.to_string(), new_id.to_string());
+        }
+    }
+    for tax_id in tax.deleted_tax_ids()? {
+        new_tax.deleted_ids.insert(tax_id.to_string());
+    }
+    Ok(())
+}
+
 /// Return a tree with these tax_ids and their children removed.
 ///
 /// Note this uses internal indices so you'll have to convert "string"
@@ -19,6 +66,9 @@ pub fn prune_away<'t, T: 't + Clone + Copy + Debug + Display + Eq + Hash + Parti
     let mut names = Vec::new();
     let mut ranks = Vec::new();
     let mut data = Vec::new();
+    let mut support_values = Vec::new();
+    let mut ncbi_nodes = Vec::new();
+    let mut all_names = HashMap::new();
 
     let tax_set: HashSet<T> = tax_ids.iter().cloned().collect();
     let mut dropping: u8 = 0;
@@ -39,6 +89,12 @@ pub fn prune_away<'t, T: 't + Clone + Copy + Debug + Display + Eq + Hash + Parti
                 names.push(tax.name(node)?.to_string());
                 ranks.push(tax.rank(node)?);
                 data.push((*tax.data(node)?).to_owned());
+                support_values.push(tax.support(node)?);
+                ncbi_nodes.push(tax.node_info(node)?.map(Cow::into_owned));
+                let node_names = tax.all_names(node)?;
+                if !node_names.is_empty() {
+                    all_names.insert(node.to_string(), node_names.into_owned());
+                }
 
                 cur_lineage.push(new_ids.len());
             } else {
@@ -46,27 +102,32 @@ pub fn prune_away<'t, T: 't + Clone + Copy + Debug + Display + Eq + Hash + Parti
             }
         }
     }
-    GeneralTaxonomy::from_arrays(
+    let mut new_tax = GeneralTaxonomy::from_arrays(
         new_ids,
         parent_ids,
         Some(names),
         Some(ranks),
         Some(dists),
         Some(data),
-    )
+    )?;
+    new_tax.support_values = support_values;
+    new_tax.ncbi_nodes = ncbi_nodes;
+    new_tax.all_names = all_names;
+    copy_shared_tables(tax, &mut new_tax)?;
+    Ok(new_tax)
 }
 
 /// Return a tree containing only the given tax_ids and their parents.
 ///
 /// Note this uses internal indices so you'll have to convert "string"
 /// keys using `to_internal_index` if you have those.
-pub fn prune_to<'t, T: 't>(
+pub fn prune_to<'t, T>(
     tax: &'t impl Taxonomy<'t, T>,
     tax_ids: &[T],
     include_children: bool,
 ) -> TaxonomyResult<GeneralTaxonomy>
 where
-    T: Clone + Copy + Debug + Display + Eq + Hash + PartialEq,
+    T: 't + Clone + Copy + Debug + Display + Eq + Hash + PartialEq,
 {
     let mut good_ids: HashSet<T> = tax_ids.iter().cloned().collect();
     for (node, pre) in tax.traverse(tax.root())? {
@@ -90,6 +151,9 @@ where
     let mut names = Vec::new();
     let mut ranks = Vec::new();
     let mut data = Vec::new();
+    let mut support_values = Vec::new();
+    let mut ncbi_nodes = Vec::new();
+    let mut all_names = HashMap::new();
 
     let mut cur_lineage = Vec::new();
     for (node, pre) in tax.traverse(tax.root())? {
@@ -101,6 +165,12 @@ where
                 names.push(tax.name(node)?.to_string());
                 ranks.push(tax.rank(node)?);
                 data.push((*tax.data(node)?).to_owned());
+                support_values.push(tax.support(node)?);
+                ncbi_nodes.push(tax.node_info(node)?.map(Cow::into_owned));
+                let node_names = tax.all_names(node)?;
+                if !node_names.is_empty() {
+                    all_names.insert(node.to_string(), node_names.into_owned());
+                }
             }
             cur_lineage.push(new_ids.len());
         } else {
@@ -108,14 +178,90 @@ where
         }
     }
 
-    GeneralTaxonomy::from_arrays(
+    let mut new_tax = GeneralTaxonomy::from_arrays(
+        new_ids,
+        parent_ids,
+        Some(names),
+        Some(ranks),
+        Some(dists),
+        Some(data),
+    )?;
+    new_tax.support_values = support_values;
+    new_tax.ncbi_nodes = ncbi_nodes;
+    new_tax.all_names = all_names;
+    copy_shared_tables(tax, &mut new_tax)?;
+    Ok(new_tax)
+}
+
+/// Return a tree where the internal nodes with a support value below `threshold` are
+/// removed, their children being attached to their parent (with the distance to the removed
+/// node added to theirs) to form polytomies.
+///
+/// The nodes without support value, the leaves and the root are always kept.
+pub fn collapse_low_support<'t, T>(
+    tax: &'t impl Taxonomy<'t, T>,
+    threshold: f32,
+) -> TaxonomyResult<GeneralTaxonomy>
+where
+    T: 't + Clone + Copy + Debug + Display + Eq + Hash + PartialEq,
+{
+    let mut new_ids = Vec::new();
+    let mut parent_ids = Vec::new();
+    let mut dists = Vec::new();
+    let mut names = Vec::new();
+    let mut ranks = Vec::new();
+    let mut data = Vec::new();
+    let mut support_values = Vec::new();
+    let mut ncbi_nodes = Vec::new();
+    let mut all_names = HashMap::new();
+
+    // for each ancestor, its new index (+ 1) and the distance added by the collapsed ones
+    let mut cur_lineage: Vec<(usize, f32)> = Vec::new();
+    let root = tax.root();
+    for (node, pre) in tax.traverse(root)? {
+        if !pre {
+            cur_lineage.pop();
+            continue;
+        }
+        let (parent_ix, extra_dist) = cur_lineage.last().copied().unwrap_or((0, 0.0));
+        let dist = tax.parent(node)?.map(|x| x.1).unwrap_or(0.0) + extra_dist;
+        let support = tax.support(node)?;
+        let collapsed = node != root
+            && support.is_some_and(|s| s < threshold)
+            && !tax.children(node)?.is_empty();
+        if collapsed {
+            cur_lineage.push((parent_ix, dist));
+            continue;
+        }
+
+        new_ids.push(node.to_string());
+        parent_ids.push(parent_ix.saturating_sub(1));
+        dists.push(dist);
+        names.push(tax.name(node)?.to_string());
+        ranks.push(tax.rank(node)?);
+        data.push((*tax.data(node)?).to_owned());
+        support_values.push(support);
+        ncbi_nodes.push(tax.node_info(node)?.map(Cow::into_owned));
+        let node_names = tax.all_names(node)?;
+        if !node_names.is_empty() {
+            all_names.insert(node.to_string(), node_names.into_owned());
+        }
+        cur_lineage.push((new_ids.len(), 0.0));
+    }
+
+    let mut new_tax = GeneralTaxonomy::from_arrays(
         new_ids,
         parent_ids,
         Some(names),
         Some(ranks),
         Some(dists),
         Some(data),
-    )
+    )?;
+    new_tax.support_values = support_values;
+    new_tax.ncbi_nodes = ncbi_nodes;
+    new_tax.all_names = all_names;
+    copy_shared_tables(tax, &mut new_tax)?;
+    Ok(new_tax)
 }
 
 #[cfg(test)]
@@ -142,4 +288,24 @@ mod tests {
         assert_eq!(Taxonomy::<&str>::len(&pruned), 9);
         assert_eq!(pruned.parent("131567").unwrap().unwrap().0, "1");
     }
+
+    #[test]
+    fn test_collapse_low_support() {
+        let newick_str =
+            b"(((A:0.25,B:0.5)C:0.5[&&NHX:B=40],(D:1,E:1)F:1[&&NHX:B=90])G:0.25[&&NHX:B=10],H:1)I;";
+        let tax = crate::newick::load(&mut newick_str.as_ref()).unwrap();
+        let collapsed = collapse_low_support::<&str>(&tax, 50.).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&collapsed), 7);
+        assert!(collapsed.to_internal_index("C").is_err());
+        assert!(collapsed.to_internal_index("G").is_err());
+        // the distances of the collapsed nodes are added up
+        assert_eq!(collapsed.parent("A").unwrap(), Some(("I", 1.)));
+        assert_eq!(collapsed.parent("F").unwrap(), Some(("I", 1.25)));
+        assert_eq!(collapsed.parent("D").unwrap(), Some(("F", 1.)));
+        assert_eq!(collapsed.support("F").unwrap(), Some(90.));
+        assert_eq!(collapsed.children("I").unwrap().len(), 4);
+
+        let pruned = prune_to(&collapsed, &["D"], false).unwrap();
+        assert_eq!(pruned.support("F").unwrap(), Some(90.));
+    }
 }
@@ -4,9 +4,14 @@ use std::fmt;
 #[derive(Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     UnknownRank(String),
-    ImportError { line: usize, msg: String },
+    ImportError {
+        line: usize,
+        msg: String,
+    },
     InvalidTaxonomy(String),
     NoSuchTaxId(String),
+    /// The tax ID used to exist but has been deleted, eg it is listed in NCBI `delnodes.dmp`
+    DeletedTaxId(String),
     NoSuchInternalIndex(InternalIndex),
     OperationNotAllowed(String),
 }
@@ -27,6 +32,7 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.kind {
             ErrorKind::NoSuchTaxId(s) => write!(f, "Tax ID {} not found in taxonomy", s),
+            ErrorKind::DeletedTaxId(s) => write!(f, "Tax ID {} has been deleted", s),
             ErrorKind::NoSuchInternalIndex(s) => write!(f, "Index {} not found in taxonomy", s),
             ErrorKind::UnknownRank(r) => write!(f, "Rank {} is unknown", r),
             ErrorKind::ImportError { line, msg } => {
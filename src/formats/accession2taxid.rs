@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::base::GeneralTaxonomy;
+use crate::errors::{Error, ErrorKind, TaxonomyResult};
+
+/// Maps sequence accessions to tax IDs, out of NCBI `accession2taxid` files such as
+/// `nucl_gb.accession2taxid.gz`.
+///
+/// Accessions are stored without their version (`NC_000913` for `NC_000913.3`) so lookups
+/// work with or without it. Tax IDs are checked against a taxonomy when loading: merged
+/// tax IDs are stored as the tax ID they were merged into and the accessions of tax IDs
+/// that are not in the taxonomy are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct AccessionIndex {
+    /// The distinct tax IDs, accessions refer to them by their position
+    tax_ids: Vec<String>,
+    /// The position of each tax ID in `tax_ids`, including the merged ones
+    tax_id_lookup: HashMap<String, u32>,
+    accessions: HashMap<String, u32>,
+    /// The tax IDs of the files that are not in the taxonomy, e.g. deleted ones
+    pub unknown_tax_ids: HashSet<String>,
+}
+
+/// Removes the `.1` version from an accession, if there is one.
+fn strip_version(accession: &str) -> &str {
+    match accession.rsplit_once('.') {
+        Some((base, version))
+            if !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => accession,
+    }
+}
+
+impl AccessionIndex {
+    /// Loads an `accession2taxid` file, plain or gzipped, out of a `reader`.
+    /// See [AccessionIndex::add] for the details.
+    pub fn load<R: Read>(reader: R, tax: &GeneralTaxonomy) -> TaxonomyResult<Self> {
+        let mut index = AccessionIndex::default();
+        index.add(reader, tax)?;
+        Ok(index)
+    }
+
+    /// Adds the content of an `accession2taxid` file, plain or gzipped, to the index.
+    /// The file is streamed and must start with a header that has a `taxid` column and an
+    /// `accession` or `accession.version` column, like the NCBI files.
+    pub fn add<R: Read>(&mut self, reader: R, tax: &GeneralTaxonomy) -> TaxonomyResult<()> {
+        let mut reader = BufReader::new(reader);
+        let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let mut reader: Box<dyn BufRead> = if is_gzip {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let header: Vec<&str> = line.trim_end().split('\t').collect();
+        let column = |name: &str| header.iter().position(|x| *x == name);
+        let accession_col = column("accession").or_else(|| column("accession.version"));
+        let (accession_col, tax_id_col) = match (accession_col, column("taxid")) {
+            (Some(a), Some(t)) => (a, t),
+            _ => {
+                return Err(Error::new(ErrorKind::ImportError {
+                    line: 1,
+                    msg: "Missing accession or taxid column in accession2taxid header".to_owned(),
+                }))
+            }
+        };
+
+        let mut line_number = 1;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            line_number += 1;
+            let mut accession = None;
+            let mut tax_id = None;
+            for (ix, field) in line.trim_end().split('\t').enumerate() {
+                if ix == accession_col {
+                    accession = Some(field);
+                } else if ix == tax_id_col {
+                    tax_id = Some(field);
+                }
+            }
+            let (accession, tax_id) = match (accession, tax_id) {
+                (Some(a), Some(t)) if !a.is_empty() => (a, t),
+                _ => {
+                    return Err(Error::new(ErrorKind::ImportError {
+                        line: line_number,
+                        msg: "Not enough fields in accession2taxid file".to_owned(),
+                    }))
+                }
+            };
+            if let Some(ix) = self.resolve_tax_id(tax_id, tax) {
+                self.accessions
+                    .insert(strip_version(accession).to_string(), ix);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the position of the final tax ID in `tax_ids`, or [None] if the taxonomy
+    /// doesn't have it.
+    fn resolve_tax_id(&mut self, tax_id: &str, tax: &GeneralTaxonomy) -> Option<u32> {
+        // most tax IDs are used by a lot of accessions so this is usually enough
+        if let Some(ix) = self.tax_id_lookup.get(tax_id) {
+            return Some(*ix);
+        }
+        if self.unknown_tax_ids.contains(tax_id) {
+            return None;
+        }
+
+        let idx = match tax.to_internal_index(tax_id) {
+            Ok(idx) => idx,
+            Err(_) => {
+                self.unknown_tax_ids.insert(tax_id.to_string());
+                return None;
+            }
+        };
+        let final_tax_id = &tax.tax_ids[idx];
+        let ix = match self.tax_id_lookup.get(final_tax_id) {
+            Some(ix) => *ix,
+            None => {
+                let ix = self.tax_ids.len() as u32;
+                self.tax_ids.push(final_tax_id.clone());
+                self.tax_id_lookup.insert(final_tax_id.clone(), ix);
+                ix
+            }
+        };
+        self.tax_id_lookup.insert(tax_id.to_string(), ix);
+        Some(ix)
+    }
+
+    /// Returns the tax ID of the given accession, with or without its version.
+    pub fn tax_id(&self, accession: &str) -> Option<&str> {
+        self.accessions
+            .get(strip_version(accession))
+            .map(|ix| self.tax_ids[*ix as usize].as_str())
+    }
+
+    /// Returns the number of accessions in the index.
+    pub fn len(&self) -> usize {
+        self.accessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accessions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::ncbi;
+
+    #[test]
+    fn can_load_accession2taxid() {
+        let tax = ncbi::load("tests/data/").unwrap();
+        let content = std::fs::read("tests/data/nucl.accession2taxid").unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&content).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        for data in [&content, &gzipped] {
+            let index = AccessionIndex::load(&data[..], &tax).unwrap();
+            assert_eq!(index.len(), 3);
+            assert_eq!(index.tax_id("NC_000913.3"), Some("562"));
+            assert_eq!(index.tax_id("NC_000913"), Some("562"));
+            assert_eq!(index.tax_id("NC_000913.1"), Some("562"));
+            // merged into 562
+            assert_eq!(index.tax_id("U00096.2"), Some("562"));
+            assert_eq!(index.tax_id("NC_001416.1"), Some("10239"));
+            // deleted and unknown tax IDs are skipped
+            assert_eq!(index.tax_id("XX000001.1"), None);
+            assert_eq!(index.tax_id("XX000002.1"), None);
+            assert_eq!(
+                index.unknown_tax_ids,
+                ["2795424", "999999999"]
+                    .into_iter()
+                    .map(|x| x.to_string())
+                    .collect()
+            );
+        }
+    }
+
+    #[test]
+    fn can_load_accession2taxid_versions_only() {
+        let tax = ncbi::load("tests/data/").unwrap();
+        let content = "accession.version\ttaxid\nNC_000913.3\t562\nNC_001416.1\t10239\n";
+        let mut index = AccessionIndex::load(content.as_bytes(), &tax).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.tax_id("NC_000913"), Some("562"));
+
+        index
+            .add("accession\ttaxid\nAB000001\t2\n".as_bytes(), &tax)
+            .unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.tax_id("AB000001.4"), Some("2"));
+    }
+
+    #[test]
+    fn rejects_invalid_accession2taxid() {
+        let tax = ncbi::load("tests/data/").unwrap();
+        assert!(AccessionIndex::load("accession\tgi\nA\t1\n".as_bytes(), &tax).is_err());
+        assert!(AccessionIndex::load("accession\ttaxid\nA\n".as_bytes(), &tax).is_err());
+        assert!(AccessionIndex::load("".as_bytes(), &tax)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn can_strip_versions() {
+        assert_eq!(strip_version("NC_000913.3"), "NC_000913");
+        assert_eq!(strip_version("NC_000913"), "NC_000913");
+        assert_eq!(strip_version("pdb|1ABC.A"), "pdb|1ABC.A");
+        assert_eq!(strip_version("NC_000913."), "NC_000913.");
+    }
+}
@@ -0,0 +1,893 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::ops::{Deref, Range};
+use std::path::Path;
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::base::{Division, GeneralTaxonomy, GeneticCode, InternalIndex, NodeInfo, TaxName};
+use crate::errors::{Error, ErrorKind, TaxonomyResult};
+use crate::rank::TaxRank;
+use crate::Taxonomy;
+
+const MAGIC: &[u8; 8] = b"TAXONOMY";
+/// The version of the binary format, increased on every incompatible change.
+pub const FORMAT_VERSION: u32 = 2;
+
+// The sections of a file, in the order they are written
+const PARENTS: usize = 0;
+const DISTANCES: usize = 1;
+const SUPPORTS: usize = 2;
+const RANKS: usize = 3;
+const TAX_IDS: usize = 4;
+const NAMES: usize = 5;
+const CHILD_OFFSETS: usize = 6;
+const CHILDREN: usize = 7;
+const TAX_ID_INDEX: usize = 8;
+const MERGED_OLD_IDS: usize = 9;
+const MERGED_NEW_IDS: usize = 10;
+const DELETED_IDS: usize = 11;
+const DATA: usize = 12;
+const NODE_INFOS: usize = 13;
+const ALL_NAMES: usize = 14;
+const EXTRAS: usize = 15;
+const SECTION_COUNT: usize = 16;
+
+/// Magic, version, checksum, node count, section count and then the offset and length
+/// of each section
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + SECTION_COUNT * 16;
+/// The checksum covers everything that comes after it
+const CHECKSUM_END: usize = 16;
+
+/// The small tables that are deserialized when opening a file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Extras {
+    /// The ranks used in the taxonomy, the `RANKS` section indexes into it
+    ranks: Vec<TaxRank>,
+    divisions: HashMap<u32, Division>,
+    genetic_codes: HashMap<u32, GeneticCode>,
+    synthesized_ids: HashSet<String>,
+}
+
+fn invalid_file(msg: &str) -> Error {
+    Error::new(ErrorKind::ImportError {
+        line: 0,
+        msg: format!("Invalid binary taxonomy: {}", msg),
+    })
+}
+
+fn corrupted() -> Error {
+    Error::new(ErrorKind::InvalidTaxonomy(
+        "Corrupted binary taxonomy".to_owned(),
+    ))
+}
+
+fn to_u32(value: usize) -> TaxonomyResult<u32> {
+    u32::try_from(value).map_err(|_| {
+        Error::new(ErrorKind::InvalidTaxonomy(
+            "Taxonomy is too large for the binary format".to_owned(),
+        ))
+    })
+}
+
+/// The `i`-th group of `N` bytes, if the indices don't overflow and it is all there.
+fn bytes_at<const N: usize>(bytes: &[u8], i: usize) -> Option<[u8; N]> {
+    let start = i.checked_mul(N)?;
+    bytes.get(start..start.checked_add(N)?)?.try_into().ok()
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> Option<u16> {
+    bytes_at(bytes, i).map(u16::from_le_bytes)
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> Option<u32> {
+    bytes_at(bytes, i).map(u32::from_le_bytes)
+}
+
+fn u64_at(bytes: &[u8], i: usize) -> Option<u64> {
+    bytes_at(bytes, i).map(u64::from_le_bytes)
+}
+
+fn f32_at(bytes: &[u8], i: usize) -> Option<f32> {
+    u32_at(bytes, i).map(f32::from_bits)
+}
+
+/// Builds a section of variable-length entries: their number, the offset of each of them
+/// (plus the end of the last one) and then their concatenated content.
+fn column_section<I, B>(entries: I) -> Vec<u8>
+where
+    I: ExactSizeIterator<Item = B>,
+    B: AsRef<[u8]>,
+{
+    let count = entries.len();
+    let mut offsets = Vec::with_capacity((count + 2) * 8);
+    let mut content = Vec::new();
+    offsets.extend((count as u64).to_le_bytes());
+    offsets.extend(0u64.to_le_bytes());
+    for entry in entries {
+        content.extend_from_slice(entry.as_ref());
+        offsets.extend((content.len() as u64).to_le_bytes());
+    }
+    offsets.extend(content);
+    offsets
+}
+
+/// Serializes a JSON column, where nodes without a value have an empty entry.
+fn json_column_section<T: Serialize>(
+    entries: impl ExactSizeIterator<Item = Option<T>>,
+) -> TaxonomyResult<Vec<u8>> {
+    let entries = entries
+        .map(|entry| match entry {
+            Some(value) => serde_json::to_vec(&value),
+            None => Ok(Vec::new()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(column_section(entries.iter()))
+}
+
+/// Writes a taxonomy to the binary format.
+///
+/// The file starts with a header (magic bytes, format version, CRC32 checksum of the rest
+/// of the file, number of nodes and position of each section) followed by the sections,
+/// all in little-endian:
+/// - the parent index, distance to the parent, support (NaN if missing) and rank of each node
+///   as fixed-size columns
+/// - the tax IDs, names, data and NCBI-specific infos of each node as variable-length columns
+/// - the children of each node and the nodes sorted by tax ID, so that lookups can be done
+///   without building any index when loading
+/// - the merged and deleted tax IDs, sorted
+///
+/// All the fields of the [GeneralTaxonomy] are kept so [load] returns an identical taxonomy.
+pub fn save<W: Write>(writer: &mut W, tax: &GeneralTaxonomy) -> TaxonomyResult<()> {
+    let n_nodes = tax.tax_ids.len();
+    to_u32(n_nodes)?;
+    let mut sections: Vec<Vec<u8>> = vec![Vec::new(); SECTION_COUNT];
+
+    sections[PARENTS] = tax
+        .parent_ids
+        .iter()
+        .flat_map(|x| (*x as u32).to_le_bytes())
+        .collect();
+    sections[DISTANCES] = tax
+        .parent_distances
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    sections[SUPPORTS] = (0..n_nodes)
+        .map(|idx| tax.support_values.get(idx).copied().flatten())
+        .flat_map(|x| x.unwrap_or(f32::NAN).to_le_bytes())
+        .collect();
+
+    let mut ranks: Vec<TaxRank> = Vec::new();
+    for rank in &tax.ranks {
+        let rank_id = match ranks.iter().position(|r| r == rank) {
+            Some(ix) => ix,
+            None => {
+                ranks.push(*rank);
+                ranks.len() - 1
+            }
+        };
+        sections[RANKS].extend((rank_id as u16).to_le_bytes());
+    }
+
+    sections[TAX_IDS] = column_section(tax.tax_ids.iter());
+    sections[NAMES] = column_section(tax.names.iter());
+
+    let mut n_children = 0;
+    for children in &tax.children_lookup {
+        sections[CHILD_OFFSETS].extend(to_u32(n_children)?.to_le_bytes());
+        sections[CHILDREN].extend(children.iter().flat_map(|x| (*x as u32).to_le_bytes()));
+        n_children += children.len();
+    }
+    sections[CHILD_OFFSETS].extend(to_u32(n_children)?.to_le_bytes());
+
+    let mut by_tax_id: Vec<usize> = (0..n_nodes).collect();
+    by_tax_id.sort_unstable_by(|a, b| tax.tax_ids[*a].cmp(&tax.tax_ids[*b]));
+    sections[TAX_ID_INDEX] = by_tax_id
+        .into_iter()
+        .flat_map(|x| (x as u32).to_le_bytes())
+        .collect();
+
+    let mut merged: Vec<(&String, &String)> = tax.merged_ids.iter().collect();
+    merged.sort_unstable();
+    sections[MERGED_OLD_IDS] = column_section(merged.iter().map(|x| x.0));
+    sections[MERGED_NEW_IDS] = column_section(merged.iter().map(|x| x.1));
+    let mut deleted: Vec<&String> = tax.deleted_ids.iter().collect();
+    deleted.sort_unstable();
+    sections[DELETED_IDS] = column_section(deleted.into_iter());
+
+    sections[DATA] =
+        json_column_section(
+            tax.data
+                .iter()
+                .map(|data| if data.is_empty() { None } else { Some(data) }),
+        )?;
+    sections[NODE_INFOS] = json_column_section(
+        (0..tax.tax_ids.len()).map(|idx| tax.ncbi_nodes.get(idx).and_then(|x| x.as_ref())),
+    )?;
+    sections[ALL_NAMES] = json_column_section(tax.tax_ids.iter().map(|x| tax.all_names.get(x)))?;
+    sections[EXTRAS] = serde_json::to_vec(&Extras {
+        ranks,
+        divisions: tax.divisions.clone(),
+        genetic_codes: tax.genetic_codes.clone(),
+        synthesized_ids: tax.synthesized_ids.clone(),
+    })?;
+
+    // every section starts on an 8 bytes boundary
+    let mut section_table = Vec::with_capacity(SECTION_COUNT * 16);
+    let mut offset = HEADER_LEN;
+    for section in &sections {
+        section_table.extend((offset as u64).to_le_bytes());
+        section_table.extend((section.len() as u64).to_le_bytes());
+        offset += section.len().div_ceil(8) * 8;
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend((n_nodes as u64).to_le_bytes());
+    header.extend((SECTION_COUNT as u64).to_le_bytes());
+    header.extend(section_table);
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header);
+    for section in &sections {
+        hasher.update(section);
+        hasher.update(&[0; 8][..(8 - section.len() % 8) % 8]);
+    }
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&hasher.finalize().to_le_bytes())?;
+    writer.write_all(&header)?;
+    for section in &sections {
+        writer.write_all(section)?;
+        writer.write_all(&[0; 8][..(8 - section.len() % 8) % 8])?;
+    }
+    Ok(())
+}
+
+/// Reads a taxonomy in the binary format, as written by [save], out of a `reader`.
+pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let tax = BinaryTaxonomy::from_bytes(bytes)?;
+    tax.verify()?;
+    tax.to_general_taxonomy()
+}
+
+enum Bytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mapped(m) => m,
+            Bytes::Owned(v) => v,
+        }
+    }
+}
+
+/// A read-only taxonomy in the binary format written by [save].
+///
+/// Opening it only checks the header: nodes are read directly from the file when they are
+/// accessed, so it is almost instant even for the whole NCBI taxonomy. The checksum has to
+/// read the whole file so it is only checked by [BinaryTaxonomy::verify].
+/// Use [BinaryTaxonomy::to_general_taxonomy] to get a taxonomy that can be edited.
+pub struct BinaryTaxonomy {
+    bytes: Bytes,
+    len: usize,
+    sections: Vec<Range<usize>>,
+    extras: Extras,
+}
+
+impl BinaryTaxonomy {
+    /// Memory-maps the file at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> TaxonomyResult<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the file is only ever read and every read is bounds-checked. As with any
+        // memory-mapped file, it must not be modified while the taxonomy is in use.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::new(Bytes::Mapped(mmap))
+    }
+
+    /// Uses the content of a file already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> TaxonomyResult<Self> {
+        Self::new(Bytes::Owned(bytes))
+    }
+
+    fn new(bytes: Bytes) -> TaxonomyResult<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(invalid_file("not a binary taxonomy"));
+        }
+        let version = u32_at(&bytes, 2).unwrap_or_default();
+        if version != FORMAT_VERSION {
+            return Err(invalid_file(&format!(
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            )));
+        }
+        let len = u64_at(&bytes, 2)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or_else(|| invalid_file("too many nodes"))?;
+        if u64_at(&bytes, 3) != Some(SECTION_COUNT as u64) {
+            return Err(invalid_file("unexpected number of sections"));
+        }
+
+        let mut sections = Vec::with_capacity(SECTION_COUNT);
+        for ix in 0..SECTION_COUNT {
+            let start = u64_at(&bytes, 4 + 2 * ix).and_then(|x| usize::try_from(x).ok());
+            let size = u64_at(&bytes, 5 + 2 * ix).and_then(|x| usize::try_from(x).ok());
+            match (
+                start,
+                start
+                    .zip(size)
+                    .and_then(|(start, size)| start.checked_add(size)),
+            ) {
+                (Some(start), Some(end)) if start >= HEADER_LEN && end <= bytes.len() => {
+                    sections.push(start..end)
+                }
+                _ => return Err(invalid_file("section out of bounds")),
+            }
+        }
+
+        let mut tax = BinaryTaxonomy {
+            bytes,
+            len,
+            sections,
+            extras: Extras::default(),
+        };
+        // the node count comes from the file so the sizes can overflow
+        let column_size = |count: usize, width: usize| count.checked_mul(width);
+        let fixed_sizes = [
+            (PARENTS, column_size(len, 4)),
+            (DISTANCES, column_size(len, 4)),
+            (SUPPORTS, column_size(len, 4)),
+            (RANKS, column_size(len, 2)),
+            (
+                CHILD_OFFSETS,
+                len.checked_add(1).and_then(|n| column_size(n, 4)),
+            ),
+            (TAX_ID_INDEX, column_size(len, 4)),
+        ];
+        for (section, size) in fixed_sizes {
+            if Some(tax.section(section).len()) != size {
+                return Err(invalid_file("unexpected section size"));
+            }
+        }
+        for section in [TAX_IDS, NAMES, DATA, NODE_INFOS, ALL_NAMES] {
+            if tax.column_len(section) != Some(len) {
+                return Err(invalid_file("unexpected number of nodes"));
+            }
+        }
+        if tax.column_len(MERGED_OLD_IDS) != tax.column_len(MERGED_NEW_IDS) {
+            return Err(invalid_file("unexpected number of merged tax ids"));
+        }
+        tax.extras = serde_json::from_slice(tax.section(EXTRAS))?;
+        // `root` can't return an error so its tax ID is checked upfront
+        if len > 0 {
+            tax.column_str(TAX_IDS, 0)?;
+        }
+        Ok(tax)
+    }
+
+    /// Checks the checksum of the whole file, to detect files that were truncated or
+    /// corrupted after being written.
+    pub fn verify(&self) -> TaxonomyResult<()> {
+        let checksum = u32_at(&self.bytes, 3).unwrap_or_default();
+        if crc32fast::hash(&self.bytes[CHECKSUM_END..]) != checksum {
+            return Err(invalid_file("checksum mismatch"));
+        }
+        Ok(())
+    }
+
+    fn section(&self, section: usize) -> &[u8] {
+        &self.bytes[self.sections[section].clone()]
+    }
+
+    fn column_len(&self, section: usize) -> Option<usize> {
+        u64_at(self.section(section), 0).map(|x| x as usize)
+    }
+
+    fn column_entry(&self, section: usize, ix: usize) -> Option<&[u8]> {
+        let bytes = self.section(section);
+        let count = self.column_len(section)?;
+        if ix >= count {
+            return None;
+        }
+        let base = count.checked_add(2)?.checked_mul(8)?;
+        let start = u64_at(bytes, ix + 1)? as usize;
+        let end = u64_at(bytes, ix + 2)? as usize;
+        bytes.get(base.checked_add(start)?..base.checked_add(end)?)
+    }
+
+    fn column_str(&self, section: usize, ix: usize) -> TaxonomyResult<&str> {
+        let entry = self.column_entry(section, ix).ok_or_else(corrupted)?;
+        std::str::from_utf8(entry).map_err(|_| corrupted())
+    }
+
+    fn column_json<T: for<'de> Deserialize<'de>>(
+        &self,
+        section: usize,
+        ix: usize,
+    ) -> TaxonomyResult<Option<T>> {
+        let entry = self.column_entry(section, ix).ok_or_else(corrupted)?;
+        if entry.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(entry)?))
+    }
+
+    /// Binary search of a key in a sorted list of `len` entries.
+    fn binary_search<'a, F>(&'a self, len: usize, key: &str, entry: F) -> Option<usize>
+    where
+        F: Fn(usize) -> Option<&'a [u8]>,
+    {
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match entry(mid)?.cmp(key.as_bytes()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn find_tax_id(&self, tax_id: &str) -> Option<InternalIndex> {
+        let index = self.section(TAX_ID_INDEX);
+        let pos = self.binary_search(self.len, tax_id, |ix| {
+            self.column_entry(TAX_IDS, u32_at(index, ix)? as usize)
+        })?;
+        u32_at(index, pos).map(|x| x as usize)
+    }
+
+    fn find_in_column(&self, section: usize, key: &str) -> Option<usize> {
+        let len = self.column_len(section)?;
+        self.binary_search(len, key, |ix| self.column_entry(section, ix))
+    }
+
+    fn check_index(&self, idx: InternalIndex) -> TaxonomyResult<()> {
+        if idx >= self.len {
+            return Err(Error::new(ErrorKind::NoSuchInternalIndex(idx)));
+        }
+        Ok(())
+    }
+
+    /// Converts a tax ID to its internal index, following merged tax IDs like
+    /// [GeneralTaxonomy::to_internal_index].
+    pub fn to_internal_index(&self, tax_id: &str) -> TaxonomyResult<InternalIndex> {
+        if let Some(idx) = self.find_tax_id(tax_id) {
+            return Ok(idx);
+        }
+        let merged_into = self
+            .find_in_column(MERGED_OLD_IDS, tax_id)
+            .and_then(|ix| self.column_entry(MERGED_NEW_IDS, ix))
+            .and_then(|new_id| std::str::from_utf8(new_id).ok())
+            .and_then(|new_id| self.find_tax_id(new_id));
+        if let Some(idx) = merged_into {
+            Ok(idx)
+        } else if self.find_in_column(DELETED_IDS, tax_id).is_some() {
+            Err(Error::new(ErrorKind::DeletedTaxId(tax_id.to_owned())))
+        } else {
+            Err(Error::new(ErrorKind::NoSuchTaxId(tax_id.to_owned())))
+        }
+    }
+
+    pub fn from_internal_index(&self, idx: InternalIndex) -> TaxonomyResult<&str> {
+        if idx >= self.len {
+            return Err(Error::new(ErrorKind::NoSuchTaxId(format!(
+                "Internal ID: {}",
+                idx
+            ))));
+        }
+        self.column_str(TAX_IDS, idx)
+    }
+
+    fn parent_index(&self, idx: InternalIndex) -> TaxonomyResult<InternalIndex> {
+        self.check_index(idx)?;
+        match u32_at(self.section(PARENTS), idx) {
+            Some(parent) if (parent as usize) < self.len => Ok(parent as usize),
+            _ => Err(corrupted()),
+        }
+    }
+
+    fn distance(&self, idx: InternalIndex) -> TaxonomyResult<f32> {
+        self.check_index(idx)?;
+        f32_at(self.section(DISTANCES), idx).ok_or_else(corrupted)
+    }
+
+    fn support_value(&self, idx: InternalIndex) -> TaxonomyResult<Option<f32>> {
+        self.check_index(idx)?;
+        let support = f32_at(self.section(SUPPORTS), idx).ok_or_else(corrupted)?;
+        Ok(if support.is_nan() {
+            None
+        } else {
+            Some(support)
+        })
+    }
+
+    /// Reads the whole taxonomy into a [GeneralTaxonomy].
+    pub fn to_general_taxonomy(&self) -> TaxonomyResult<GeneralTaxonomy> {
+        let mut tax_ids = Vec::with_capacity(self.len);
+        let mut parent_ids = Vec::with_capacity(self.len);
+        let mut distances = Vec::with_capacity(self.len);
+        let mut supports = Vec::with_capacity(self.len);
+        let mut names = Vec::with_capacity(self.len);
+        let mut ranks = Vec::with_capacity(self.len);
+        let mut data = Vec::with_capacity(self.len);
+        let mut all_names = HashMap::new();
+        let mut ncbi_nodes = Vec::with_capacity(self.len);
+        for idx in 0..self.len {
+            let tax_id = self.column_str(TAX_IDS, idx)?.to_string();
+            parent_ids.push(self.parent_index(idx)?);
+            distances.push(self.distance(idx)?);
+            supports.push(self.support_value(idx)?);
+            names.push(self.column_str(NAMES, idx)?.to_string());
+            ranks.push(Taxonomy::<InternalIndex>::rank(self, idx)?);
+            data.push(self.column_json(DATA, idx)?.unwrap_or_default());
+            if let Some(node_names) = self.column_json::<Vec<TaxName>>(ALL_NAMES, idx)? {
+                all_names.insert(tax_id.clone(), node_names);
+            }
+            ncbi_nodes.push(self.column_json::<NodeInfo>(NODE_INFOS, idx)?);
+            tax_ids.push(tax_id);
+        }
+
+        let mut tax = GeneralTaxonomy::from_arrays(
+            tax_ids,
+            parent_ids,
+            Some(names),
+            Some(ranks),
+            Some(distances),
+            Some(data),
+        )?;
+        for ix in 0..self.column_len(MERGED_OLD_IDS).unwrap_or_default() {
+            tax.merged_ids.insert(
+                self.column_str(MERGED_OLD_IDS, ix)?.to_string(),
+                self.column_str(MERGED_NEW_IDS, ix)?.to_string(),
+            );
+        }
+        let deleted: TaxonomyResult<HashSet<String>> =
+            (0..self.column_len(DELETED_IDS).unwrap_or_default())
+                .map(|ix| self.column_str(DELETED_IDS, ix).map(|x| x.to_string()))
+                .collect();
+        tax.deleted_ids = deleted?;
+        tax.all_names = all_names;
+        tax.ncbi_nodes = ncbi_nodes;
+        tax.divisions = self.extras.divisions.clone();
+        tax.genetic_codes = self.extras.genetic_codes.clone();
+        tax.support_values = supports;
+        tax.synthesized_ids = self.extras.synthesized_ids.clone();
+        Ok(tax)
+    }
+}
+
+/// Same as the &str implementation of [GeneralTaxonomy], merged tax IDs are followed.
+impl<'t> Taxonomy<'t, &'t str> for BinaryTaxonomy {
+    fn root(&'t self) -> &'t str {
+        self.from_internal_index(0)
+            .expect("An empty taxonomy has no root")
+    }
+
+    fn children(&'t self, tax_id: &str) -> TaxonomyResult<Vec<&'t str>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::children(self, idx)?
+            .into_iter()
+            .map(|x| self.from_internal_index(x))
+            .collect()
+    }
+
+    fn descendants(&'t self, tax_id: &'t str) -> TaxonomyResult<Vec<&'t str>> {
+        let idx = self.to_internal_index(tax_id)?;
+        let mut descendants = Taxonomy::<InternalIndex>::descendants(self, idx)?
+            .into_iter()
+            .map(|x| self.from_internal_index(x))
+            .collect::<TaxonomyResult<Vec<&str>>>()?;
+        descendants.sort_unstable();
+        Ok(descendants)
+    }
+
+    fn parent(&'t self, tax_id: &str) -> TaxonomyResult<Option<(&'t str, f32)>> {
+        let idx = self.to_internal_index(tax_id)?;
+        match Taxonomy::<InternalIndex>::parent(self, idx)? {
+            Some((parent, distance)) => Ok(Some((self.from_internal_index(parent)?, distance))),
+            None => Ok(None),
+        }
+    }
+
+    fn lineage(&'t self, tax_id: &'t str) -> TaxonomyResult<Vec<&'t str>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::lineage(self, idx)?
+            .into_iter()
+            .map(|x| self.from_internal_index(x))
+            .collect()
+    }
+
+    fn lca(&'t self, id1: &'t str, id2: &'t str) -> TaxonomyResult<&'t str> {
+        let idx1 = self.to_internal_index(id1)?;
+        let idx2 = self.to_internal_index(id2)?;
+        self.from_internal_index(Taxonomy::<InternalIndex>::lca(self, idx1, idx2)?)
+    }
+
+    fn name(&'t self, tax_id: &str) -> TaxonomyResult<&'t str> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::name(self, idx)
+    }
+
+    fn data(&'t self, tax_id: &str) -> TaxonomyResult<Cow<'t, HashMap<String, Value>>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::data(self, idx)
+    }
+
+    fn all_names(&'t self, tax_id: &str) -> TaxonomyResult<Cow<'t, [TaxName]>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::all_names(self, idx)
+    }
+
+    fn support(&'t self, tax_id: &str) -> TaxonomyResult<Option<f32>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::support(self, idx)
+    }
+
+    fn node_info(&'t self, tax_id: &str) -> TaxonomyResult<Option<Cow<'t, NodeInfo>>> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::node_info(self, idx)
+    }
+
+    fn division_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, Division>>> {
+        Taxonomy::<InternalIndex>::division_by_id(self, id)
+    }
+
+    fn genetic_code_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, GeneticCode>>> {
+        Taxonomy::<InternalIndex>::genetic_code_by_id(self, id)
+    }
+
+    fn merged_tax_ids(&'t self) -> TaxonomyResult<Vec<(&'t str, &'t str)>> {
+        Taxonomy::<InternalIndex>::merged_tax_ids(self)
+    }
+
+    fn deleted_tax_ids(&'t self) -> TaxonomyResult<Vec<&'t str>> {
+        Taxonomy::<InternalIndex>::deleted_tax_ids(self)
+    }
+
+    fn rank(&'t self, tax_id: &str) -> TaxonomyResult<TaxRank> {
+        let idx = self.to_internal_index(tax_id)?;
+        Taxonomy::<InternalIndex>::rank(self, idx)
+    }
+
+    fn len(&'t self) -> usize
+    where
+        Self: Sized,
+    {
+        self.len
+    }
+}
+
+impl<'t> Taxonomy<'t, InternalIndex> for BinaryTaxonomy {
+    fn root(&'t self) -> InternalIndex {
+        0
+    }
+
+    fn children(&'t self, idx: InternalIndex) -> TaxonomyResult<Vec<InternalIndex>> {
+        self.check_index(idx)?;
+        let offsets = self.section(CHILD_OFFSETS);
+        let start = u32_at(offsets, idx).ok_or_else(corrupted)? as usize;
+        let end = u32_at(offsets, idx + 1).ok_or_else(corrupted)? as usize;
+        let children = self.section(CHILDREN);
+        (start..end)
+            .map(|ix| match u32_at(children, ix) {
+                Some(child) if (child as usize) < self.len => Ok(child as usize),
+                _ => Err(corrupted()),
+            })
+            .collect()
+    }
+
+    fn descendants(&'t self, idx: InternalIndex) -> TaxonomyResult<Vec<InternalIndex>> {
+        let children: HashSet<InternalIndex> = self
+            .traverse(idx)?
+            .map(|(n, _)| n)
+            .filter(|n| *n != idx)
+            .collect();
+        Ok(children.into_iter().collect())
+    }
+
+    fn parent(&'t self, idx: InternalIndex) -> TaxonomyResult<Option<(InternalIndex, f32)>> {
+        if idx == 0 {
+            return Ok(None);
+        }
+        Ok(Some((self.parent_index(idx)?, self.distance(idx)?)))
+    }
+
+    fn name(&'t self, idx: InternalIndex) -> TaxonomyResult<&'t str> {
+        self.check_index(idx)?;
+        self.column_str(NAMES, idx)
+    }
+
+    fn data(&'t self, idx: InternalIndex) -> TaxonomyResult<Cow<'t, HashMap<String, Value>>> {
+        self.check_index(idx)?;
+        let data: HashMap<String, Value> = self.column_json(DATA, idx)?.unwrap_or_default();
+        Ok(Cow::Owned(data))
+    }
+
+    fn all_names(&'t self, idx: InternalIndex) -> TaxonomyResult<Cow<'t, [TaxName]>> {
+        self.check_index(idx)?;
+        let names: Vec<TaxName> = self.column_json(ALL_NAMES, idx)?.unwrap_or_default();
+        Ok(Cow::Owned(names))
+    }
+
+    fn support(&'t self, idx: InternalIndex) -> TaxonomyResult<Option<f32>> {
+        self.support_value(idx)
+    }
+
+    fn node_info(&'t self, idx: InternalIndex) -> TaxonomyResult<Option<Cow<'t, NodeInfo>>> {
+        self.check_index(idx)?;
+        Ok(self.column_json(NODE_INFOS, idx)?.map(Cow::Owned))
+    }
+
+    fn division_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, Division>>> {
+        Ok(self.extras.divisions.get(&id).map(Cow::Borrowed))
+    }
+
+    fn genetic_code_by_id(&'t self, id: u32) -> TaxonomyResult<Option<Cow<'t, GeneticCode>>> {
+        Ok(self.extras.genetic_codes.get(&id).map(Cow::Borrowed))
+    }
+
+    fn merged_tax_ids(&'t self) -> TaxonomyResult<Vec<(&'t str, &'t str)>> {
+        (0..self.column_len(MERGED_OLD_IDS).unwrap_or_default())
+            .map(|ix| {
+                Ok((
+                    self.column_str(MERGED_OLD_IDS, ix)?,
+                    self.column_str(MERGED_NEW_IDS, ix)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn deleted_tax_ids(&'t self) -> TaxonomyResult<Vec<&'t str>> {
+        (0..self.column_len(DELETED_IDS).unwrap_or_default())
+            .map(|ix| self.column_str(DELETED_IDS, ix))
+            .collect()
+    }
+
+    fn rank(&'t self, idx: InternalIndex) -> TaxonomyResult<TaxRank> {
+        self.check_index(idx)?;
+        u16_at(self.section(RANKS), idx)
+            .and_then(|r| self.extras.ranks.get(r as usize))
+            .copied()
+            .ok_or_else(corrupted)
+    }
+
+    fn len(&'t self) -> usize
+    where
+        Self: Sized,
+    {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ncbi;
+
+    fn ncbi_binary() -> (GeneralTaxonomy, Vec<u8>) {
+        let mut tax = ncbi::load("tests/data/").unwrap();
+        tax.data[0].insert("readcount".to_string(), Value::from(1000));
+        let idx = tax.to_internal_index("561").unwrap();
+        tax.support_values[idx] = Some(0.95);
+        tax.synthesized_ids.insert("2".to_string());
+        let mut bytes = Vec::new();
+        save(&mut bytes, &tax).unwrap();
+        (tax, bytes)
+    }
+
+    #[test]
+    fn can_round_trip_binary() {
+        let (tax, bytes) = ncbi_binary();
+        let loaded = load(&mut &bytes[..]).unwrap();
+        assert_eq!(loaded, tax);
+    }
+
+    #[test]
+    fn can_use_binary_taxonomy() {
+        let (tax, bytes) = ncbi_binary();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("taxonomy.bin");
+        std::fs::write(&path, &bytes).unwrap();
+        let binary = BinaryTaxonomy::open(&path).unwrap();
+
+        assert_eq!(Taxonomy::<&str>::len(&binary), Taxonomy::<&str>::len(&tax));
+        assert_eq!(Taxonomy::<&str>::root(&binary), "1");
+        assert_eq!(binary.name("562").unwrap(), "Escherichia coli");
+        assert_eq!(binary.rank("562").unwrap(), TaxRank::Species);
+        assert_eq!(binary.parent("562").unwrap(), Some(("561", 1.)));
+        assert_eq!(binary.support("561").unwrap(), Some(0.95));
+        assert_eq!(binary.support("562").unwrap(), None);
+        assert_eq!(
+            binary.children("561").unwrap(),
+            tax.children("561").unwrap()
+        );
+        assert_eq!(
+            binary.descendants("2").unwrap(),
+            tax.descendants("2").unwrap()
+        );
+        assert_eq!(binary.lineage("562").unwrap(), tax.lineage("562").unwrap());
+        assert_eq!(binary.lca("562", "10239").unwrap(), "1");
+        for tax_id in ["1", "562", "10239"] {
+            assert_eq!(binary.data(tax_id).unwrap(), tax.data(tax_id).unwrap());
+            assert_eq!(
+                binary.node_info(tax_id).unwrap(),
+                tax.node_info(tax_id).unwrap()
+            );
+            assert_eq!(
+                binary.all_names(tax_id).unwrap(),
+                tax.all_names(tax_id).unwrap()
+            );
+        }
+
+        // merged and deleted tax IDs behave like in the GeneralTaxonomy
+        assert_eq!(binary.name("469598").unwrap(), "Escherichia coli");
+        assert_eq!(
+            binary.to_internal_index("2795424").unwrap_err().kind,
+            ErrorKind::DeletedTaxId("2795424".to_owned())
+        );
+        assert_eq!(
+            binary.to_internal_index("123456789").unwrap_err().kind,
+            ErrorKind::NoSuchTaxId("123456789".to_owned())
+        );
+        assert!(Taxonomy::<InternalIndex>::name(&binary, 100).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_binary_files() {
+        let (_, bytes) = ncbi_binary();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(BinaryTaxonomy::from_bytes(wrong_magic).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = FORMAT_VERSION as u8 + 1;
+        assert!(BinaryTaxonomy::from_bytes(wrong_version).is_err());
+
+        // only the header is checked when opening, the rest by the checksum
+        let mut corrupted = bytes.clone();
+        corrupted[CHECKSUM_END] ^= 0xff;
+        assert!(BinaryTaxonomy::from_bytes(corrupted).is_err());
+        for ix in [HEADER_LEN + 3, bytes.len() - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[ix] ^= 0xff;
+            assert!(load(&mut &corrupted[..]).is_err());
+            let binary = BinaryTaxonomy::from_bytes(corrupted).unwrap();
+            assert!(binary.verify().is_err());
+        }
+        assert!(BinaryTaxonomy::from_bytes(bytes.clone())
+            .unwrap()
+            .verify()
+            .is_ok());
+
+        // the root tax ID is checked when opening since `root` can't fail
+        let mut corrupted = bytes.clone();
+        let len = u64_at(&bytes, 2).unwrap() as usize;
+        let root_tax_id = u64_at(&bytes, 4 + 2 * TAX_IDS).unwrap() as usize + (len + 2) * 8;
+        corrupted[root_tax_id] = 0xff;
+        assert!(BinaryTaxonomy::from_bytes(corrupted).is_err());
+
+        // node counts that overflow the section sizes
+        for len in [u64::MAX, u64::MAX / 4 + 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[CHECKSUM_END..CHECKSUM_END + 8].copy_from_slice(&len.to_le_bytes());
+            assert!(BinaryTaxonomy::from_bytes(corrupted).is_err());
+        }
+
+        assert!(BinaryTaxonomy::from_bytes(bytes[..bytes.len() / 2].to_vec()).is_err());
+        assert!(BinaryTaxonomy::from_bytes(Vec::new()).is_err());
+    }
+}
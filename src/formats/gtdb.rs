@@ -1,39 +1,129 @@
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::fmt::{Debug, Display};
+use std::io::{BufRead, BufReader, Read, Write};
 
 use crate::base::{GeneralTaxonomy, InternalIndex};
 use crate::errors::{Error, ErrorKind, TaxonomyResult};
 use crate::rank::TaxRank;
+use crate::Taxonomy;
 
-/// Read GTDB format into a Taxonomy object out of a `reader`.
-///
-/// Still somewhat experimental and may not support all GTDB features.
-pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
-    let mut tax_names = Vec::new();
+use flate2::read::MultiGzDecoder;
+use serde_json::{Number, Value};
+
+/// The rank given to the genome nodes added with [LoadOptions::include_genomes]
+pub const GENOME_RANK: TaxRank = TaxRank::Strain;
+
+/// Options for loading GTDB taxonomies.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Add each genome as a leaf node below its species, with the [GENOME_RANK] rank.
+    /// Its tax ID is the accession without the `RS_`/`GB_` prefix (e.g. `GCF_000005845.2`)
+    /// and the accession as written in the file is kept in its `gtdb_accession` data.
+    pub include_genomes: bool,
+}
+
+/// The data key holding the name of a node without the GTDB suffixes (e.g. `g__Escherichia`
+/// for `g__Escherichia_A`), only set for the names that have suffixes.
+pub const BASE_NAME_KEY: &str = "gtdb_base_name";
+
+/// Whether that level of a lineage is empty or only a rank prefix, like `s__`.
+fn is_placeholder(level: &str) -> bool {
+    level.is_empty() || level.strip_suffix("__").is_some_and(|x| x.len() == 1)
+}
+
+/// Removes the suffixes GTDB adds to the names of polyphyletic groups, e.g.
+/// `g__Escherichia_A` or `s__Pseudomonas_E viridiflava_B`: one or two capital letters
+/// after an underscore at the end of a word.
+pub fn base_name(name: &str) -> String {
+    name.split(' ')
+        .map(|word| match word.rsplit_once('_') {
+            Some((base, suffix))
+                if !base.is_empty()
+                    && !base.ends_with('_')
+                    && (1..=2).contains(&suffix.len())
+                    && suffix.bytes().all(|b| b.is_ascii_uppercase()) =>
+            {
+                base
+            }
+            _ => word,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the tax IDs of all the nodes that only differ from `name` by their GTDB
+/// suffixes, e.g. `g__Escherichia`, `g__Escherichia_A` and `g__Escherichia_B` for
+/// `g__Escherichia` (or `g__Escherichia_A`).
+pub fn find_suffixed_variants<'t>(tax: &'t GeneralTaxonomy, name: &str) -> Vec<&'t str> {
+    let base = base_name(name.trim());
+    tax.tax_ids
+        .iter()
+        .zip(tax.data.iter())
+        .filter(|(tax_id, data)| match data.get(BASE_NAME_KEY) {
+            Some(node_base) => node_base == base.as_str(),
+            None => **tax_id == base,
+        })
+        .map(|(tax_id, _)| tax_id.as_str())
+        .collect()
+}
+
+/// Removes the `RS_` (RefSeq) or `GB_` (GenBank) prefix GTDB adds to accessions.
+fn strip_accession_source(accession: &str) -> &str {
+    accession
+        .strip_prefix("RS_")
+        .or_else(|| accession.strip_prefix("GB_"))
+        .unwrap_or(accession)
+}
+
+/// Builds a taxonomy out of GTDB lineages, shared by the taxonomy and metadata loaders.
+#[derive(Default)]
+struct Builder {
+    tax_ids: Vec<String>,
+    tax_names: Vec<String>,
     // Maps tax name to (index, lineage prefix)
-    let mut tax_names_idx: HashMap<String, (InternalIndex, String)> = HashMap::new();
-    let mut parent_ids = Vec::new();
-    let mut tax_ranks = Vec::new();
+    tax_names_idx: HashMap<String, (InternalIndex, String)>,
+    parent_ids: Vec<InternalIndex>,
+    tax_ranks: Vec<TaxRank>,
+    data: Vec<HashMap<String, Value>>,
+}
 
-    for (row_idx, row_result) in BufReader::new(reader).lines().enumerate() {
-        let line_num = row_idx + 1;
-        let row = row_result?;
-        // parts[0] -> accession number
-        // parts[1] -> lineage, `;` separated
-        let parts: Vec<_> = row.split('\t').collect();
-        if parts.len() != 2 {
+impl Builder {
+    fn push(
+        &mut self,
+        tax_id: String,
+        parent_id: InternalIndex,
+        rank: TaxRank,
+        data: HashMap<String, Value>,
+    ) -> InternalIndex {
+        self.tax_names.push(tax_id.clone());
+        self.tax_ids.push(tax_id);
+        self.parent_ids.push(parent_id);
+        self.tax_ranks.push(rank);
+        self.data.push(data);
+        self.tax_ids.len() - 1
+    }
+
+    /// Adds the missing nodes of a `;` separated lineage and returns the index of the
+    /// lowest one. Whitespace around the levels is ignored, as are the empty levels and
+    /// placeholders like a trailing `s__`.
+    fn add_lineage(&mut self, line_num: usize, lineage: &str) -> TaxonomyResult<InternalIndex> {
+        let lineage: Vec<_> = lineage
+            .split(';')
+            .map(|level| level.trim())
+            .filter(|level| !is_placeholder(level))
+            .collect();
+        if lineage.is_empty() {
             return Err(Error::new(ErrorKind::ImportError {
                 line: line_num,
-                msg: "Expected tab-delimited line with exactly two parts (accession and lineage)"
-                    .to_owned(),
+                msg: "Empty lineage".to_owned(),
             }));
         }
-        let lineage: Vec<_> = parts[1].split(';').collect();
+        let mut parent_id = 0;
 
         for (i, level) in lineage.iter().enumerate() {
             let curr_prefix = lineage[..i].join(";");
-            match tax_names_idx.get(*level) {
-                Some((_, prev_prefix)) => {
+            match self.tax_names_idx.get(*level) {
+                Some((idx, prev_prefix)) => {
                     if curr_prefix != *prev_prefix {
                         return Err(Error::new(ErrorKind::ImportError {
                             line: line_num,
@@ -43,44 +133,267 @@ pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
                             ),
                         }));
                     }
-                    continue;
+                    parent_id = *idx;
                 }
                 None => {
-                    let tax_rank = match &level[..3] {
-                        "d__" => TaxRank::Domain,
-                        "p__" => TaxRank::Phylum,
-                        "c__" => TaxRank::Class,
-                        "o__" => TaxRank::Order,
-                        "f__" => TaxRank::Family,
-                        "g__" => TaxRank::Genus,
-                        "s__" => TaxRank::Species,
-                        _ => TaxRank::Unspecified,
-                    };
-                    tax_names.push(level.to_string());
-                    tax_ranks.push(tax_rank);
-                    let idx = tax_names.len() - 1;
-                    tax_names_idx.insert(level.to_string(), (idx, curr_prefix));
-                    if i > 0 {
-                        let parent_id = tax_names_idx.get(lineage[i - 1]).unwrap().0;
-                        parent_ids.push(parent_id);
-                    } else {
-                        parent_ids.push(0);
+                    let tax_rank = GTDB_RANKS
+                        .iter()
+                        .find(|(_, prefix)| level.starts_with(prefix))
+                        .map(|(rank, _)| *rank)
+                        .unwrap_or(TaxRank::Unspecified);
+                    let mut data = HashMap::new();
+                    let base = base_name(level);
+                    if base != *level {
+                        data.insert(BASE_NAME_KEY.to_string(), base.into());
                     }
+                    let idx = self.push(level.to_string(), parent_id, tax_rank, data);
+                    self.tax_names_idx
+                        .insert(level.to_string(), (idx, curr_prefix));
+                    parent_id = idx;
                 }
             }
         }
+        Ok(parent_id)
+    }
+
+    /// Adds a genome node below `parent_id`, see [LoadOptions::include_genomes].
+    fn add_genome(
+        &mut self,
+        accession: &str,
+        parent_id: InternalIndex,
+        mut data: HashMap<String, Value>,
+    ) {
+        data.insert("gtdb_accession".to_string(), accession.into());
+        let tax_id = strip_accession_source(accession).to_string();
+        self.push(tax_id, parent_id, GENOME_RANK, data);
     }
 
-    let gt = GeneralTaxonomy::from_arrays(
-        tax_names.clone(),
-        parent_ids,
-        Some(tax_names),
-        Some(tax_ranks),
-        None,
-        None,
-    )?;
-    gt.validate_uniqueness()?;
-    Ok(gt)
+    fn build(self) -> TaxonomyResult<GeneralTaxonomy> {
+        let gt = GeneralTaxonomy::from_arrays(
+            self.tax_ids,
+            self.parent_ids,
+            Some(self.tax_names),
+            Some(self.tax_ranks),
+            None,
+            Some(self.data),
+        )?;
+        gt.validate_uniqueness()?;
+        Ok(gt)
+    }
+}
+
+/// Read GTDB format into a Taxonomy object out of a `reader`.
+///
+/// Still somewhat experimental and may not support all GTDB features.
+pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
+    load_with_options(reader, &LoadOptions::default())
+}
+
+/// Same as [load] but with the given [LoadOptions], e.g. to keep the genomes.
+pub fn load_with_options<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> TaxonomyResult<GeneralTaxonomy> {
+    let mut builder = Builder::default();
+
+    for (row_idx, row_result) in BufReader::new(reader).lines().enumerate() {
+        let line_num = row_idx + 1;
+        let row = row_result?;
+        if row.trim().is_empty() {
+            continue;
+        }
+        // parts[0] -> accession number
+        // parts[1] -> lineage, `;` separated
+        let parts: Vec<_> = row.trim().split('\t').map(|x| x.trim()).collect();
+        if parts.len() != 2 {
+            return Err(Error::new(ErrorKind::ImportError {
+                line: line_num,
+                msg: "Expected tab-delimited line with exactly two parts (accession and lineage)"
+                    .to_owned(),
+            }));
+        }
+        let species_idx = builder.add_lineage(line_num, parts[1])?;
+        if options.include_genomes {
+            builder.add_genome(parts[0], species_idx, HashMap::new());
+        }
+    }
+
+    builder.build()
+}
+
+/// The columns of the GTDB metadata files (e.g. `bac120_metadata.tsv`) kept by default by
+/// [load_metadata].
+pub const DEFAULT_METADATA_COLUMNS: [&str; 7] = [
+    "checkm_completeness",
+    "checkm_contamination",
+    "genome_size",
+    "gc_percentage",
+    "gtdb_representative",
+    "ncbi_taxid",
+    "ncbi_taxonomy",
+];
+
+/// Converts a value of the GTDB metadata files to JSON: `t`/`f` are booleans and
+/// numbers are numbers, except in the ID columns (e.g. `ncbi_taxid`) which are kept as
+/// strings. Returns [None] for empty and `none` values.
+fn metadata_value(column: &str, value: &str) -> Option<Value> {
+    if value.is_empty() || value == "none" {
+        return None;
+    }
+    if column.ends_with("taxid") || column.ends_with("accession") {
+        return Some(value.into());
+    }
+    let value = match value {
+        "t" => Value::Bool(true),
+        "f" => Value::Bool(false),
+        _ => {
+            if let Ok(n) = value.parse::<i64>() {
+                n.into()
+            } else if let Some(n) = value.parse::<f64>().ok().and_then(Number::from_f64) {
+                Value::Number(n)
+            } else {
+                value.into()
+            }
+        }
+    };
+    Some(value)
+}
+
+/// Read a GTDB metadata file (e.g. `bac120_metadata.tsv` or `ar53_metadata.tsv`), plain
+/// or gzipped, into a Taxonomy object out of a `reader`.
+///
+/// The tree is built from the `gtdb_taxonomy` column, with each genome as a leaf node like
+/// with [LoadOptions::include_genomes]. The given `columns` (e.g. [DEFAULT_METADATA_COLUMNS])
+/// are stored in the data of the genome nodes, without the `none` values.
+pub fn load_metadata<R: Read>(reader: &mut R, columns: &[&str]) -> TaxonomyResult<GeneralTaxonomy> {
+    let mut reader = BufReader::new(reader);
+    let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    let reader: Box<dyn BufRead> = if is_gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+    let mut lines = reader.lines();
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => {
+            return Err(Error::new(ErrorKind::ImportError {
+                line: 1,
+                msg: "Missing header in GTDB metadata file".to_owned(),
+            }))
+        }
+    };
+    let header: Vec<&str> = header.split('\t').collect();
+    let column_idx = |name: &str| {
+        header.iter().position(|x| *x == name).ok_or_else(|| {
+            Error::new(ErrorKind::ImportError {
+                line: 1,
+                msg: format!("Missing {} column in GTDB metadata file", name),
+            })
+        })
+    };
+    let accession_idx = column_idx("accession")?;
+    let lineage_idx = column_idx("gtdb_taxonomy")?;
+    let columns = columns
+        .iter()
+        .map(|name| Ok((*name, column_idx(name)?)))
+        .collect::<TaxonomyResult<Vec<_>>>()?;
+
+    let mut builder = Builder::default();
+    for (row_idx, row_result) in lines.enumerate() {
+        let line_num = row_idx + 2;
+        let row = row_result?;
+        if row.trim().is_empty() {
+            continue;
+        }
+        let parts: Vec<_> = row.trim_end_matches('\r').split('\t').collect();
+        if parts.len() != header.len() {
+            return Err(Error::new(ErrorKind::ImportError {
+                line: line_num,
+                msg: format!(
+                    "Expected {} tab-delimited columns, found {}",
+                    header.len(),
+                    parts.len()
+                ),
+            }));
+        }
+        let species_idx = builder.add_lineage(line_num, parts[lineage_idx])?;
+        let accession = parts[accession_idx].trim();
+        let data = columns
+            .iter()
+            .filter_map(|(name, idx)| Some((name.to_string(), metadata_value(name, parts[*idx])?)))
+            .collect();
+        builder.add_genome(accession, species_idx, data);
+    }
+
+    builder.build()
+}
+
+/// The ranks of a GTDB lineage, in order, with their prefix
+pub(crate) const GTDB_RANKS: [(TaxRank, &str); 7] = [
+    (TaxRank::Domain, "d__"),
+    (TaxRank::Phylum, "p__"),
+    (TaxRank::Class, "c__"),
+    (TaxRank::Order, "o__"),
+    (TaxRank::Family, "f__"),
+    (TaxRank::Genus, "g__"),
+    (TaxRank::Species, "s__"),
+];
+
+/// Write a Taxonomy in the GTDB format: one `accession<TAB>d__...;p__...;...;s__...` line
+/// for each leaf below `root_node` (or the root if not given).
+///
+/// The accession is the `gtdb_accession` data of the leaf if it has one (see
+/// [LoadOptions::include_genomes]) or its tax ID otherwise. Names get the prefix of their
+/// rank if they don't already have it, superkingdoms are written as domains, the nodes of
+/// other ranks are left out and the missing ranks are written as an empty prefix (e.g. `g__`).
+pub fn save<'t, T, W: Write, X: Taxonomy<'t, T>>(
+    writer: &mut W,
+    taxonomy: &'t X,
+    root_node: Option<T>,
+) -> TaxonomyResult<()>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    let root_node = root_node.unwrap_or_else(|| taxonomy.root());
+
+    for (node, pre) in taxonomy.traverse(root_node)? {
+        if !pre || !taxonomy.children(node.clone())?.is_empty() {
+            continue;
+        }
+
+        let mut names: [Option<&str>; 7] = [None; 7];
+        for ancestor in taxonomy.lineage(node.clone())? {
+            let rank = match taxonomy.rank(ancestor.clone())? {
+                TaxRank::Superkingdom => TaxRank::Domain,
+                rank => rank,
+            };
+            if let Some(pos) = GTDB_RANKS.iter().position(|(r, _)| *r == rank) {
+                // the lineage goes up so keep the lowest node of each rank
+                if names[pos].is_none() {
+                    names[pos] = Some(taxonomy.name(ancestor)?);
+                }
+            }
+        }
+        let lineage: Vec<String> = GTDB_RANKS
+            .iter()
+            .zip(names)
+            .map(|((_, prefix), name)| match name {
+                Some(name) if name.starts_with(prefix) => name.to_string(),
+                Some(name) => format!("{}{}", prefix, name),
+                None => prefix.to_string(),
+            })
+            .collect();
+
+        let data = taxonomy.data(node.clone())?;
+        let accession = match data.get("gtdb_accession").and_then(|v| v.as_str()) {
+            Some(accession) => accession.to_string(),
+            None => node.to_string(),
+        };
+        writeln!(writer, "{}\t{}", accession, lineage.join(";"))?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -122,6 +435,224 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_load_gtdb_genomes() {
+        let mut file = File::open("tests/data/gtdb_sample.tsv").unwrap();
+        let options = LoadOptions {
+            include_genomes: true,
+        };
+        let tax = load_with_options(&mut file, &options).unwrap();
+
+        let tax_id = "GCF_012278395.1";
+        assert_eq!(tax.rank(tax_id).unwrap(), GENOME_RANK);
+        assert_eq!(tax.name(tax_id).unwrap(), "GCF_012278395.1");
+        assert_eq!(
+            tax.data(tax_id).unwrap()["gtdb_accession"],
+            "RS_GCF_012278395.1"
+        );
+        assert_eq!(
+            tax.lineage(tax_id).unwrap(),
+            [
+                "GCF_012278395.1",
+                "s__Staphylococcus aureus",
+                "g__Staphylococcus",
+                "f__Staphylococcaceae",
+                "o__Staphylococcales",
+                "c__Bacilli",
+                "p__Firmicutes",
+                "d__Bacteria"
+            ]
+        );
+        let idx = tax.to_internal_index("GCA_001420305.1").unwrap();
+        assert_eq!(
+            tax.name(tax.parent(idx).unwrap().unwrap().0).unwrap(),
+            "s__Acinetobacter baumannii"
+        );
+
+        // genomes are not there by default
+        let mut file = File::open("tests/data/gtdb_sample.tsv").unwrap();
+        let tax = load(&mut file).unwrap();
+        assert!(tax.to_internal_index("GCF_012278395.1").is_err());
+    }
+
+    #[test]
+    fn can_save_gtdb_format() {
+        let content = std::fs::read_to_string("tests/data/gtdb_sample.tsv").unwrap();
+        let options = LoadOptions {
+            include_genomes: true,
+        };
+        let tax = load_with_options(&mut content.as_bytes(), &options).unwrap();
+        let mut out = Vec::new();
+        save::<&str, _, _>(&mut out, &tax, None).unwrap();
+
+        let mut expected: Vec<&str> = content.lines().collect();
+        let out = String::from_utf8(out).unwrap();
+        let mut saved: Vec<&str> = out.lines().collect();
+        expected.sort_unstable();
+        saved.sort_unstable();
+        assert_eq!(saved, expected);
+
+        // without the genomes the leaves are the species
+        let tax = load(&mut content.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        save(&mut out, &tax, Some("s__Escherichia coli")).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "s__Escherichia coli\td__Bacteria;p__Proteobacteria;c__Gammaproteobacteria;\
+             o__Enterobacterales;f__Enterobacteriaceae;g__Escherichia;s__Escherichia coli\n"
+        );
+    }
+
+    #[test]
+    fn can_save_other_taxonomies_to_gtdb() {
+        let tax = crate::ncbi::load("tests/data/").unwrap();
+        let mut out = Vec::new();
+        save(&mut out, &tax, Some("562")).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let line = out.lines().next().unwrap();
+        let (accession, lineage) = line.split_once('\t').unwrap();
+        let ranks: Vec<&str> = lineage.split(';').collect();
+        assert_eq!(ranks.len(), 7);
+        assert_eq!(ranks[0], "d__Bacteria");
+        assert_eq!(ranks[6], "s__Escherichia coli");
+        assert!(tax.lineage(accession).unwrap().contains(&"562"));
+    }
+
+    #[test]
+    fn fills_missing_gtdb_ranks() {
+        let tax = GeneralTaxonomy::from_arrays(
+            vec!["1".to_string(), "2".to_string(), "3".to_string()],
+            vec![0, 0, 1],
+            Some(vec![
+                "root".to_string(),
+                "Bacteria".to_string(),
+                "E. coli".to_string(),
+            ]),
+            Some(vec![
+                TaxRank::Unspecified,
+                TaxRank::Superkingdom,
+                TaxRank::Species,
+            ]),
+            None,
+            None,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        save::<&str, _, _>(&mut out, &tax, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "3\td__Bacteria;p__;c__;o__;f__;g__;s__E. coli\n"
+        );
+    }
+
+    #[test]
+    fn can_load_gtdb_metadata() {
+        let mut file = File::open("tests/data/gtdb_metadata_sample.tsv").unwrap();
+        let tax = load_metadata(&mut file, &DEFAULT_METADATA_COLUMNS).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax), 21);
+
+        let tax_id = "GCF_000005845.2";
+        assert_eq!(tax.rank(tax_id).unwrap(), GENOME_RANK);
+        assert_eq!(
+            tax.parent(tax_id).unwrap(),
+            Some(("s__Escherichia coli", 1.0))
+        );
+        let data = tax.data(tax_id).unwrap();
+        assert_eq!(data["gtdb_accession"], "RS_GCF_000005845.2");
+        assert_eq!(data["checkm_completeness"], 100.0);
+        assert_eq!(data["checkm_contamination"], 0.04);
+        assert_eq!(data["genome_size"], 4641652);
+        assert_eq!(data["gtdb_representative"], false);
+        assert_eq!(data["ncbi_taxid"], "562");
+        assert!(data["ncbi_taxonomy"]
+            .as_str()
+            .unwrap()
+            .ends_with(";s__Escherichia coli"));
+        assert!(!data.contains_key("ncbi_organism_name"));
+
+        let mut file = File::open("tests/data/gtdb_metadata_sample.tsv").unwrap();
+        let tax = load_metadata(&mut file, &["ncbi_strain_identifiers"]).unwrap();
+        assert_eq!(
+            tax.data("GCF_000195955.2").unwrap()["ncbi_strain_identifiers"],
+            "H37Rv"
+        );
+        // `none` values are left out
+        assert_eq!(tax.data("GCA_002459855.1").unwrap().len(), 1);
+
+        let mut file = File::open("tests/data/gtdb_metadata_sample.tsv").unwrap();
+        assert!(load_metadata(&mut file, &["unknown_column"]).is_err());
+        assert!(load_metadata(&mut "".as_bytes(), &[]).is_err());
+    }
+
+    #[test]
+    fn can_load_messy_gtdb_lineages() {
+        let content = "\
+            GB_GCA_1.1\t d__Bacteria ; p__Proteobacteria;c__Gammaproteobacteria;s__ \r\n\
+            \n\
+            GB_GCA_2.1\td__Bacteria;;p__Proteobacteria;c__;x__Weird;g__Escherichia_A;s__\n\
+            GB_GCA_3.1\td__Bacteria;p__Proteobacteria;x__Weird;g__Escherichia;s__Escherichia coli_B\n\
+            GB_GCA_4.1\td;p_\n";
+        let options = LoadOptions {
+            include_genomes: true,
+        };
+        let tax = load_with_options(&mut content.as_bytes(), &options).unwrap();
+
+        assert_eq!(
+            tax.lineage("GCA_1.1").unwrap(),
+            [
+                "GCA_1.1",
+                "c__Gammaproteobacteria",
+                "p__Proteobacteria",
+                "d__Bacteria"
+            ]
+        );
+        assert_eq!(tax.rank("x__Weird").unwrap(), TaxRank::Unspecified);
+        assert_eq!(tax.rank("d").unwrap(), TaxRank::Unspecified);
+        assert_eq!(
+            tax.parent("GCA_2.1").unwrap().unwrap().0,
+            "g__Escherichia_A"
+        );
+        assert!(tax.to_internal_index("s__").is_err());
+        assert!(tax.to_internal_index("c__").is_err());
+
+        assert_eq!(
+            tax.data("g__Escherichia_A").unwrap()[BASE_NAME_KEY],
+            "g__Escherichia"
+        );
+        assert_eq!(
+            tax.data("s__Escherichia coli_B").unwrap()[BASE_NAME_KEY],
+            "s__Escherichia coli"
+        );
+        assert!(!tax
+            .data("g__Escherichia")
+            .unwrap()
+            .contains_key(BASE_NAME_KEY));
+
+        let mut variants = find_suffixed_variants(&tax, "g__Escherichia_A");
+        variants.sort_unstable();
+        assert_eq!(variants, ["g__Escherichia", "g__Escherichia_A"]);
+        assert_eq!(
+            find_suffixed_variants(&tax, "s__Escherichia coli"),
+            ["s__Escherichia coli_B"]
+        );
+        assert!(find_suffixed_variants(&tax, "g__Salmonella").is_empty());
+
+        assert!(load(&mut "A\t ; s__\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn can_remove_gtdb_suffixes() {
+        assert_eq!(base_name("g__Escherichia_A"), "g__Escherichia");
+        assert_eq!(
+            base_name("s__Pseudomonas_E viridiflava_AB"),
+            "s__Pseudomonas viridiflava"
+        );
+        assert_eq!(base_name("f__WRONG_FAMILY"), "f__WRONG_FAMILY");
+        assert_eq!(base_name("g__UBA1234"), "g__UBA1234");
+        assert_eq!(base_name("g__CAG-1_a"), "g__CAG-1_a");
+        assert_eq!(base_name("s__"), "s__");
+    }
+
     #[test]
     fn invalid_gtdb_format() {
         let mut file = File::open("tests/data/gtdb_invalid.tsv").unwrap();
@@ -2,13 +2,14 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
-use std::io::{Read, Write};
+use std::io::{BufReader, Read, Write};
 use std::str::FromStr;
 
 use crate::base::GeneralTaxonomy;
 use crate::errors::{Error, ErrorKind, TaxonomyResult};
 use crate::rank::TaxRank;
 use crate::Taxonomy;
+use serde::de::DeserializeSeed;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, to_value, to_writer, Value};
 
@@ -28,7 +29,7 @@ use serde_json::{json, to_value, to_writer, Value};
 ///     { "id": 2, "name": "kingdom A", "rank": "kingdom" },
 ///     { "id": 3, "name": "phylum A", "rank": "phylum" },
 ///     { "id": 4, "name": "genus A", "rank": "genus" },
-///     { "id": 5, "name": "species A1", "rank": "species" }
+///     { "id": 5, "name": "species A1", "rank": "species", "distance": 0.25 }
 ///   ],
 ///   "links": [
 ///     { "source": 5, "target": 4 },
@@ -45,6 +46,8 @@ use serde_json::{json, to_value, to_writer, Value};
 ///   id – unique integer identifier
 ///   name – the scientific name or placeholder label
 ///   rank – the taxonomic rank (e.g., "species", "genus", "family")
+///   distance – optional, the branch length to the parent node. Defaults to 1 and is only
+///   written when it's something else
 ///
 /// links:
 /// A list of directed edges between nodes. Each link has:
@@ -71,6 +74,7 @@ use serde_json::{json, to_value, to_writer, Value};
 ///                     "id": "562",
 ///                     "name": "Escherichia coli",
 ///                     "rank": "species",
+///                     "distance": 0.25
 ///                 }
 ///             ]
 ///         }
@@ -78,6 +82,8 @@ use serde_json::{json, to_value, to_writer, Value};
 /// }
 /// ```
 //
+/// Tree nodes have the same `id`, `name`, `rank` and `distance` fields as the node link ones.
+///
 /// For both formats, you can add more data on each node object and these will be available after loading.
 /// If a `rank` propery is present, it will be parsed as a NCBI rank.
 #[derive(Eq, PartialEq)]
@@ -86,6 +92,7 @@ pub enum JsonFormat {
     /// 1. the nodes with their taxonomic info. Internal (integer) IDs are the node's position in
     ///    the nodes array
     /// 2. the links between each node: a `source` node has a `parent` node.
+    ///
     /// The nodes are represented by indices in the nodes array
     /// Only use that format if you have existing taxonomies in that format.
     NodeLink,
@@ -160,6 +167,9 @@ struct TaxNode {
     #[serde(serialize_with = "serialize_tax_rank")]
     #[serde(default = "default_tax_rank")]
     rank: TaxRank,
+    /// The distance to the parent node, left out when it's the default of 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    distance: Option<f32>,
 
     /// We want to keep any other fields that was in JSON, they all get put in this hashmap
     #[serde(flatten)]
@@ -172,6 +182,55 @@ struct Link {
     target: usize,
 }
 
+/// Node link IDs have to be integers
+fn check_node_link_id(id: &str) -> TaxonomyResult<()> {
+    if id.parse::<usize>().is_err() {
+        return Err(Error::new(ErrorKind::ImportError {
+            line: 0,
+            msg: format!("Tax ID {} cannot be converted to an integer", id),
+        }));
+    }
+    Ok(())
+}
+
+fn add_link(link: &Link, parent_ids: &mut [usize], num_nodes: usize) -> TaxonomyResult<()> {
+    if link.source >= num_nodes {
+        return Err(Error::new(ErrorKind::ImportError {
+            line: 0,
+            msg: format!(
+                "JSON source index {} specified, but there are only {} nodes",
+                link.source, num_nodes
+            ),
+        }));
+    }
+    if link.target >= num_nodes {
+        return Err(Error::new(ErrorKind::ImportError {
+            line: 0,
+            msg: format!(
+                "JSON target index {} specified, but there are only {} nodes",
+                link.target, num_nodes
+            ),
+        }));
+    }
+
+    parent_ids[link.source] = link.target;
+    Ok(())
+}
+
+fn links_not_found() -> Error {
+    Error::new(ErrorKind::ImportError {
+        line: 0,
+        msg: "'links' not in JSON".to_owned(),
+    })
+}
+
+fn pointer_not_found(pointer: &str) -> Error {
+    Error::new(ErrorKind::ImportError {
+        line: 0,
+        msg: format!("JSON path {} does not correspond to a value", pointer),
+    })
+}
+
 /// Requires `nodes` to be an array of object with at least {rank, name, id}
 fn load_node_link_json(tax_json: &Value) -> TaxonomyResult<GeneralTaxonomy> {
     let json_tax_nodes = tax_json["nodes"]
@@ -187,23 +246,13 @@ fn load_node_link_json(tax_json: &Value) -> TaxonomyResult<GeneralTaxonomy> {
     let mut tax_nodes = Vec::with_capacity(json_tax_nodes.len());
     for n in json_tax_nodes {
         let node: TaxNode = serde_json::from_value(n)?;
-        if node.id.parse::<usize>().is_err() {
-            return Err(Error::new(ErrorKind::ImportError {
-                line: 0,
-                msg: format!("Tax ID {} cannot be converted to an integer", node.id),
-            }));
-        }
+        check_node_link_id(&node.id)?;
         tax_nodes.push(node);
     }
 
     let tax_links = tax_json["links"]
         .as_array()
-        .ok_or_else(|| {
-            Error::new(ErrorKind::ImportError {
-                line: 0,
-                msg: "'links' not in JSON".to_owned(),
-            })
-        })?
+        .ok_or_else(links_not_found)?
         .clone();
 
     let num_nodes = tax_nodes.len();
@@ -211,37 +260,20 @@ fn load_node_link_json(tax_json: &Value) -> TaxonomyResult<GeneralTaxonomy> {
     let mut names: Vec<String> = Vec::with_capacity(tax_nodes.len());
     let mut ranks: Vec<TaxRank> = Vec::with_capacity(tax_nodes.len());
     let mut data: Vec<HashMap<String, Value>> = Vec::with_capacity(tax_nodes.len());
+    let mut distances: Vec<f32> = Vec::with_capacity(tax_nodes.len());
     let mut parent_ids = vec![0; tax_nodes.len()];
 
     for node in tax_nodes {
         tax_ids.push(node.id);
         names.push(node.name);
         ranks.push(node.rank);
+        distances.push(node.distance.unwrap_or(1.0));
         data.push(node.extra);
     }
 
     for l in tax_links {
         let link: Link = serde_json::from_value(l)?;
-        if link.source >= num_nodes {
-            return Err(Error::new(ErrorKind::ImportError {
-                line: 0,
-                msg: format!(
-                    "JSON source index {} specified, but there are only {} nodes",
-                    link.source, num_nodes
-                ),
-            }));
-        }
-        if link.target >= num_nodes {
-            return Err(Error::new(ErrorKind::ImportError {
-                line: 0,
-                msg: format!(
-                    "JSON target index {} specified, but there are only {} nodes",
-                    link.target, num_nodes
-                ),
-            }));
-        }
-
-        parent_ids[link.source] = link.target;
+        add_link(&link, &mut parent_ids, num_nodes)?;
     }
 
     GeneralTaxonomy::from_arrays(
@@ -249,7 +281,7 @@ fn load_node_link_json(tax_json: &Value) -> TaxonomyResult<GeneralTaxonomy> {
         parent_ids,
         Some(names),
         Some(ranks),
-        None,
+        Some(distances),
         Some(data),
     )
 }
@@ -263,6 +295,9 @@ struct TaxNodeTree {
     #[serde(serialize_with = "serialize_tax_rank")]
     #[serde(default = "default_tax_rank")]
     rank: TaxRank,
+    /// The distance to the parent node, left out when it's the default of 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    distance: Option<f32>,
     #[serde(default)]
     children: Vec<TaxNodeTree>,
 
@@ -273,65 +308,407 @@ struct TaxNodeTree {
 fn load_tree_json(tax_json: &Value) -> TaxonomyResult<GeneralTaxonomy> {
     let root_node: TaxNodeTree = serde_json::from_value(tax_json.clone())?;
 
-    fn add_node(
-        parent_loc: usize,
-        node: TaxNodeTree,
-        tax_ids: &mut Vec<String>,
-        parent_ids: &mut Vec<usize>,
-        names: &mut Vec<String>,
-        ranks: &mut Vec<TaxRank>,
-        data: &mut Vec<HashMap<String, Value>>,
-    ) -> TaxonomyResult<()> {
-        tax_ids.push(node.id);
-        parent_ids.push(parent_loc);
-        names.push(node.name);
-        ranks.push(node.rank);
-        data.push(node.extra);
-        let loc = tax_ids.len() - 1;
+    fn add_node(parent_loc: usize, node: TaxNodeTree, arrays: &mut TaxonomyArrays) {
+        arrays.tax_ids.push(node.id);
+        arrays.parent_ids.push(parent_loc);
+        arrays.names.push(node.name);
+        arrays.ranks.push(node.rank);
+        arrays.distances.push(node.distance.unwrap_or(1.0));
+        arrays.data.push(node.extra);
+        let loc = arrays.tax_ids.len() - 1;
         for child in node.children {
-            add_node(loc, child, tax_ids, parent_ids, names, ranks, data)?;
+            add_node(loc, child, arrays);
+        }
+    }
+
+    let mut arrays = TaxonomyArrays::default();
+    add_node(0, root_node, &mut arrays);
+    arrays.into_taxonomy()
+}
+
+/// The arrays of a [GeneralTaxonomy], filled in as the JSON is read.
+#[derive(Debug, Default)]
+struct TaxonomyArrays {
+    tax_ids: Vec<String>,
+    parent_ids: Vec<usize>,
+    names: Vec<String>,
+    ranks: Vec<TaxRank>,
+    distances: Vec<f32>,
+    data: Vec<HashMap<String, Value>>,
+}
+
+impl TaxonomyArrays {
+    /// Adds an empty node and returns its position, its fields are set once they are read.
+    fn push(&mut self, parent: usize) -> usize {
+        self.tax_ids.push(String::new());
+        self.parent_ids.push(parent);
+        self.names.push(String::new());
+        self.ranks.push(TaxRank::Unspecified);
+        self.distances.push(1.0);
+        self.data.push(HashMap::new());
+        self.tax_ids.len() - 1
+    }
+
+    fn into_taxonomy(self) -> TaxonomyResult<GeneralTaxonomy> {
+        GeneralTaxonomy::from_arrays(
+            self.tax_ids,
+            self.parent_ids,
+            Some(self.names),
+            Some(self.ranks),
+            Some(self.distances),
+            Some(self.data),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TaxId(#[serde(deserialize_with = "deserialize_u64_or_string")] String);
+
+#[derive(Deserialize)]
+struct Rank(#[serde(deserialize_with = "deserialize_tax_rank")] TaxRank);
+
+/// The `nodes` and `links` arrays of a node link document, if it is one.
+#[derive(Debug, Default)]
+struct NodeLinkParts {
+    nodes: Option<TaxonomyArrays>,
+    links: Option<Vec<Link>>,
+}
+
+/// Reads a tree format node and its children straight into `arrays`.
+/// The top-level object also looks for the node link `nodes` and `links` arrays since we
+/// only know which format we are reading once all its keys have been seen.
+struct TreeNodeVisitor<'a> {
+    arrays: &'a mut TaxonomyArrays,
+    parent: usize,
+    node_link: Option<&'a mut NodeLinkParts>,
+}
+
+impl<'de, 'a> de::DeserializeSeed<'de> for TreeNodeVisitor<'a> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> de::Visitor<'de> for TreeNodeVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a taxonomy node")
+    }
+
+    fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        // the node is added before its children so they end up in the same order as the JSON
+        let loc = self.arrays.push(self.parent);
+        let mut id = None;
+        let mut name = None;
+        let mut rank = None;
+        let mut distance = None;
+        let mut has_children = false;
+        let mut extra = HashMap::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match (key.as_str(), self.node_link.as_deref_mut()) {
+                ("nodes", Some(parts)) if parts.nodes.is_none() => {
+                    let mut nodes = TaxonomyArrays::default();
+                    map.next_value_seed(NodesVisitor(&mut nodes))?;
+                    parts.nodes = Some(nodes);
+                }
+                // before `nodes` it could also be a tree node field, see below
+                (
+                    "links",
+                    Some(NodeLinkParts {
+                        nodes: Some(_),
+                        links,
+                    }),
+                ) => *links = Some(map.next_value()?),
+                ("id", _) if id.is_none() => id = Some(map.next_value::<TaxId>()?.0),
+                ("name", _) if name.is_none() => name = Some(map.next_value()?),
+                ("rank", _) if rank.is_none() => rank = Some(map.next_value::<Rank>()?.0),
+                ("distance", _) if distance.is_none() => distance = Some(map.next_value()?),
+                ("children", _) if !has_children => {
+                    has_children = true;
+                    map.next_value_seed(ChildrenVisitor {
+                        arrays: self.arrays,
+                        parent: loc,
+                    })?;
+                }
+                ("id" | "name" | "rank" | "distance" | "children", _) | ("nodes", Some(_)) => {
+                    return Err(de::Error::custom(format!("duplicate field `{}`", key)));
+                }
+                _ => {
+                    extra.insert(key, map.next_value()?);
+                }
+            }
         }
 
+        if let Some(parts @ NodeLinkParts { nodes: Some(_), .. }) = self.node_link {
+            if let Some(links) = extra.remove("links") {
+                parts.links = Some(serde_json::from_value(links).map_err(de::Error::custom)?);
+            }
+            return Ok(());
+        }
+        self.arrays.tax_ids[loc] = id.ok_or_else(|| de::Error::missing_field("id"))?;
+        self.arrays.names[loc] = name.ok_or_else(|| de::Error::missing_field("name"))?;
+        self.arrays.ranks[loc] = rank.unwrap_or(TaxRank::Unspecified);
+        self.arrays.distances[loc] = distance.flatten().unwrap_or(1.0);
+        self.arrays.data[loc] = extra;
         Ok(())
     }
+}
 
-    let mut tax_ids = Vec::new();
-    let mut parent_ids = Vec::new();
-    let mut names = Vec::new();
-    let mut ranks = Vec::new();
-    let mut data = Vec::new();
+struct ChildrenVisitor<'a> {
+    arrays: &'a mut TaxonomyArrays,
+    parent: usize,
+}
 
-    add_node(
-        0,
-        root_node,
-        &mut tax_ids,
-        &mut parent_ids,
-        &mut names,
-        &mut ranks,
-        &mut data,
-    )?;
+impl<'de, 'a> de::DeserializeSeed<'de> for ChildrenVisitor<'a> {
+    type Value = ();
 
-    GeneralTaxonomy::from_arrays(
-        tax_ids,
-        parent_ids,
-        Some(names),
-        Some(ranks),
-        None,
-        Some(data),
-    )
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> de::Visitor<'de> for ChildrenVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of taxonomy nodes")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        while seq
+            .next_element_seed(TreeNodeVisitor {
+                arrays: self.arrays,
+                parent: self.parent,
+                node_link: None,
+            })?
+            .is_some()
+        {}
+        Ok(())
+    }
+}
+
+/// Reads the node link `nodes` array one node at a time.
+struct NodesVisitor<'a>(&'a mut TaxonomyArrays);
+
+impl<'de, 'a> de::DeserializeSeed<'de> for NodesVisitor<'a> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> de::Visitor<'de> for NodesVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of taxonomy nodes")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        while let Some(node) = seq.next_element::<TaxNode>()? {
+            self.0.tax_ids.push(node.id);
+            self.0.parent_ids.push(0);
+            self.0.names.push(node.name);
+            self.0.ranks.push(node.rank);
+            self.0.distances.push(node.distance.unwrap_or(1.0));
+            self.0.data.push(node.extra);
+        }
+        Ok(())
+    }
+}
+
+enum JsonDocument {
+    Tree(TaxonomyArrays),
+    NodeLink(TaxonomyArrays, Option<Vec<Link>>),
 }
 
+impl JsonDocument {
+    fn into_taxonomy(self) -> TaxonomyResult<GeneralTaxonomy> {
+        match self {
+            JsonDocument::Tree(arrays) => arrays.into_taxonomy(),
+            JsonDocument::NodeLink(mut arrays, links) => {
+                for id in &arrays.tax_ids {
+                    check_node_link_id(id)?;
+                }
+                let num_nodes = arrays.tax_ids.len();
+                for link in links.ok_or_else(links_not_found)? {
+                    add_link(&link, &mut arrays.parent_ids, num_nodes)?;
+                }
+                arrays.into_taxonomy()
+            }
+        }
+    }
+}
+
+/// Follows the remaining JSON pointer `tokens` and reads the taxonomy found there, skipping
+/// everything else. Returns `None` if the pointer doesn't lead anywhere.
+struct PointerVisitor<'p> {
+    tokens: &'p [String],
+}
+
+impl<'de, 'p> de::DeserializeSeed<'de> for PointerVisitor<'p> {
+    type Value = Option<JsonDocument>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if !self.tokens.is_empty() {
+            return deserializer.deserialize_any(self);
+        }
+
+        let mut tree = TaxonomyArrays::default();
+        let mut parts = NodeLinkParts::default();
+        TreeNodeVisitor {
+            arrays: &mut tree,
+            parent: 0,
+            node_link: Some(&mut parts),
+        }
+        .deserialize(deserializer)?;
+        Ok(Some(match parts.nodes {
+            Some(nodes) => JsonDocument::NodeLink(nodes, parts.links),
+            None => JsonDocument::Tree(tree),
+        }))
+    }
+}
+
+impl<'de, 'p> de::Visitor<'de> for PointerVisitor<'p> {
+    type Value = Option<JsonDocument>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        let mut document = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == self.tokens[0] && document.is_none() {
+                document = map.next_value_seed(PointerVisitor {
+                    tokens: &self.tokens[1..],
+                })?;
+            } else {
+                map.next_value::<de::IgnoredAny>()?;
+            }
+        }
+        Ok(document)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        // same rules as `Value::pointer`: no leading `+` or `0`
+        let token = &self.tokens[0];
+        let index = if token.starts_with('+') || (token.starts_with('0') && token.len() > 1) {
+            None
+        } else {
+            token.parse::<usize>().ok()
+        };
+
+        let mut document = None;
+        let mut ix = 0;
+        loop {
+            if Some(ix) == index {
+                let seed = PointerVisitor {
+                    tokens: &self.tokens[1..],
+                };
+                match seq.next_element_seed(seed)? {
+                    Some(found) => document = found,
+                    None => break,
+                }
+            } else if seq.next_element::<de::IgnoredAny>()?.is_none() {
+                break;
+            }
+            ix += 1;
+        }
+        Ok(document)
+    }
+
+    fn visit_bool<E: de::Error>(self, _v: bool) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_i64<E: de::Error>(self, _v: i64) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_u64<E: de::Error>(self, _v: u64) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_f64<E: de::Error>(self, _v: f64) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_str<E: de::Error>(self, _v: &str) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+}
+
+/// Loads a taxonomy in either of the [JsonFormat] out of a `reader`.
+///
+/// The JSON is streamed: nodes are added to the taxonomy as they are read instead of
+/// parsing the whole document first, which keeps the memory usage close to the size of the
+/// taxonomy itself. If `json_pointer` is given, the taxonomy is read from that sub-object and
+/// the rest of the document is skipped.
 pub fn load<R: Read>(reader: R, json_pointer: Option<&str>) -> TaxonomyResult<GeneralTaxonomy> {
-    let tax_json: Value = serde_json::from_reader(reader)?;
+    let tokens: Vec<String> = match json_pointer {
+        Some("") | None => Vec::new(),
+        Some(p) if p.starts_with('/') => p
+            .split('/')
+            .skip(1)
+            .map(|t| t.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+        Some(p) => return Err(pointer_not_found(p)),
+    };
+
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let document = PointerVisitor { tokens: &tokens }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    let gt = document
+        .ok_or_else(|| pointer_not_found(json_pointer.unwrap_or_default()))?
+        .into_taxonomy()?;
+    gt.validate_uniqueness()?;
+    Ok(gt)
+}
+
+/// Loads a taxonomy in either of the [JsonFormat] out of an already parsed JSON `Value`.
+/// Prefer [load] when reading from a file as it doesn't need the whole document in memory.
+pub fn load_value(tax_json: &Value, json_pointer: Option<&str>) -> TaxonomyResult<GeneralTaxonomy> {
     let actual_tax_json = if let Some(p) = json_pointer {
-        tax_json.pointer(p).ok_or_else(|| {
-            Error::new(ErrorKind::ImportError {
-                line: 0,
-                msg: format!("JSON path {} does not correspond to a value", p),
-            })
-        })?
+        tax_json.pointer(p).ok_or_else(|| pointer_not_found(p))?
     } else {
-        &tax_json
+        tax_json
     };
 
     // determine the JSON type
@@ -344,14 +721,14 @@ pub fn load<R: Read>(reader: R, json_pointer: Option<&str>) -> TaxonomyResult<Ge
     Ok(gt)
 }
 
-pub fn save<'t, W: Write, T: 't, X: Taxonomy<'t, T>>(
+pub fn save<'t, W: Write, T, X: Taxonomy<'t, T>>(
     writer: W,
     taxonomy: &'t X,
     format: JsonFormat,
     root_node: Option<T>,
 ) -> TaxonomyResult<()>
 where
-    T: Clone + Debug + Display + Eq + Hash + PartialEq,
+    T: 't + Clone + Debug + Display + Eq + Hash + PartialEq,
 {
     // Defaults to root but root exists only if taxonomy is not empty.
     let root_node = root_node.or(if taxonomy.is_empty() {
@@ -369,20 +746,31 @@ where
     Ok(())
 }
 
-fn serialize_as_tree<'t, T: 't>(
+/// The distance to the parent of the node, if it has one and it isn't the default of 1.
+fn parent_distance<'t, T>(tax: &'t impl Taxonomy<'t, T>, tax_id: T) -> TaxonomyResult<Option<f32>>
+where
+    T: 't + Clone + Debug + Display + Eq + Hash + PartialEq,
+{
+    Ok(tax
+        .parent(tax_id)?
+        .map(|(_, distance)| distance)
+        .filter(|distance| *distance != 1.0))
+}
+
+fn serialize_as_tree<'t, T>(
     taxonomy: &'t impl Taxonomy<'t, T>,
     root_node: Option<T>,
 ) -> TaxonomyResult<Value>
 where
-    T: Clone + Debug + Display + Eq + Hash + PartialEq,
+    T: 't + Clone + Debug + Display + Eq + Hash + PartialEq,
 {
     let tax_id = root_node.ok_or(Error::new(ErrorKind::InvalidTaxonomy(
         "Taxonomy must have a root node.".to_string(),
     )))?;
 
-    fn inner<'t, T: 't>(tax: &'t impl Taxonomy<'t, T>, tax_id: T) -> TaxonomyResult<TaxNodeTree>
+    fn inner<'t, T>(tax: &'t impl Taxonomy<'t, T>, tax_id: T) -> TaxonomyResult<TaxNodeTree>
     where
-        T: Clone + Debug + Display + Eq + Hash + PartialEq,
+        T: 't + Clone + Debug + Display + Eq + Hash + PartialEq,
     {
         let mut children = Vec::new();
         for child in tax.children(tax_id.clone())? {
@@ -392,6 +780,7 @@ where
             id: tax_id.to_string(),
             name: tax.name(tax_id.clone())?.to_string(),
             rank: tax.rank(tax_id.clone())?,
+            distance: parent_distance(tax, tax_id.clone())?,
             children,
             extra: (*tax.data(tax_id)?).clone(),
         };
@@ -401,12 +790,12 @@ where
     Ok(to_value(inner(taxonomy, tax_id)?)?)
 }
 
-fn serialize_as_node_links<'t, T: 't>(
+fn serialize_as_node_links<'t, T>(
     tax: &'t impl Taxonomy<'t, T>,
     root_node: Option<T>,
 ) -> TaxonomyResult<Value>
 where
-    T: Clone + Debug + Display + Eq + Hash + PartialEq,
+    T: 't + Clone + Debug + Display + Eq + Hash + PartialEq,
 {
     let mut nodes = Vec::new();
     let mut links = Vec::new();
@@ -418,6 +807,7 @@ where
                 id: tid.to_string(),
                 name: tax.name(tid.clone())?.to_string(),
                 rank: tax.rank(tid.clone())?,
+                distance: parent_distance(tax, tid.clone())?,
                 extra: (*tax.data(tid.clone())?).clone(),
             };
             nodes.push(to_value(&node).unwrap());
@@ -466,12 +856,6 @@ mod tests {
         )
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    struct NodeLinkFormat {
-        nodes: Vec<TaxNode>,
-        links: Vec<Link>,
-    }
-
     #[test]
     fn can_load_small_node_link_format() {
         let example = r#"{
@@ -559,7 +943,7 @@ mod tests {
         );
         // Converting to Value should give us the same data
         let serialized = serialize_as_tree(&tax, Some(Taxonomy::<&str>::root(&tax))).unwrap();
-        let input_val: TaxNodeTree = from_str(&example).unwrap();
+        let input_val: TaxNodeTree = from_str(example).unwrap();
         assert_eq!(serialized, to_value(&input_val).unwrap());
     }
 
@@ -720,4 +1104,156 @@ mod tests {
         let example = r#"{"id": "1", "rank": null, "name": ""}"#;
         assert!(load(Cursor::new(example), None).is_ok());
     }
+
+    fn assert_same_taxonomy(tax: &GeneralTaxonomy, expected: &GeneralTaxonomy) {
+        assert_eq!(tax.tax_ids, expected.tax_ids);
+        assert_eq!(tax.parent_ids, expected.parent_ids);
+        assert_eq!(tax.names, expected.names);
+        assert_eq!(tax.ranks, expected.ranks);
+        assert_eq!(tax.data, expected.data);
+    }
+
+    #[test]
+    fn streaming_load_matches_value_load() {
+        let node_link = std::fs::read_to_string("tests/data/ncbi_subset_tax.json").unwrap();
+        let tax = load(Cursor::new(&node_link), None).unwrap();
+        let expected = load_value(&from_str(&node_link).unwrap(), None).unwrap();
+        assert_same_taxonomy(&tax, &expected);
+
+        let mut tree = Vec::new();
+        save::<_, &str, _>(&mut tree, &tax, JsonFormat::Tree, None).unwrap();
+        let tax = load(Cursor::new(&tree), None).unwrap();
+        let expected = load_value(&serde_json::from_slice(&tree).unwrap(), None).unwrap();
+        assert_same_taxonomy(&tax, &expected);
+
+        // keys in any order, children before the node fields
+        let example = r#"{
+            "children": [{"rank": "species", "children": [], "name": "E. coli", "id": 562}],
+            "extra": {"nodes": [1]},
+            "name": "root",
+            "id": "1"
+        }"#;
+        let tax = load(Cursor::new(example), None).unwrap();
+        let expected = load_value(&from_str(example).unwrap(), None).unwrap();
+        assert_same_taxonomy(&tax, &expected);
+        assert_eq!(tax.tax_ids, vec!["1", "562"]);
+
+        // `links` is only the node link array if there are `nodes`, before or after it
+        let example = r#"{"id": "1", "name": "root", "links": ["https://example.com"]}"#;
+        let tax = load(Cursor::new(example), None).unwrap();
+        let expected = load_value(&from_str(example).unwrap(), None).unwrap();
+        assert_same_taxonomy(&tax, &expected);
+        assert_eq!(tax.data[0]["links"], json!(["https://example.com"]));
+        let example = r#"{
+            "links": [{"source": 1, "target": 0}],
+            "nodes": [{"id": 0, "name": "root"}, {"id": 1, "name": "A"}]
+        }"#;
+        let tax = load(Cursor::new(example), None).unwrap();
+        let expected = load_value(&from_str(example).unwrap(), None).unwrap();
+        assert_same_taxonomy(&tax, &expected);
+        assert_eq!(tax.parent("1").unwrap(), Some(("0", 1.)));
+    }
+
+    #[test]
+    fn can_stream_from_json_pointer() {
+        let example = r#"{
+            "before": {"id": "0", "name": "skipped", "children": [{"a": [1, {"b": null}]}]},
+            "a/b": [
+                "skipped",
+                {"tree": {"id": "1", "name": "root", "children": [{"id": "2", "name": "B"}]}},
+                {"nodes": [{"id": 1, "name": "root"}], "links": []}
+            ],
+            "after": [true, 1.5, -1]
+        }"#;
+        let tax = load(Cursor::new(example), Some("/a~1b/1/tree")).unwrap();
+        assert_eq!(tax.tax_ids, vec!["1", "2"]);
+        let tax = load(Cursor::new(example), Some("/a~1b/2")).unwrap();
+        assert_eq!(tax.tax_ids, vec!["1"]);
+
+        for pointer in ["/a~1b/3", "/a~1b/01", "/a~1b/0/tree", "/missing", "a~1b"] {
+            let err = load(Cursor::new(example), Some(pointer)).unwrap_err();
+            assert!(err.to_string().contains("does not correspond to a value"));
+            assert!(load_value(&from_str(example).unwrap(), Some(pointer)).is_err());
+        }
+
+        // the rest of the document still has to be valid JSON
+        assert!(load(
+            Cursor::new(r#"{"a": {"nodes": [], "links": []}, "b": }"#),
+            Some("/a")
+        )
+        .is_err());
+        assert!(load(Cursor::new(r#"{"nodes": [], "links": []} 1"#), None).is_err());
+    }
+
+    #[test]
+    fn can_round_trip_distances() {
+        let tax = crate::newick::load(&mut Cursor::new("((1:0.25,2:2)3:0.5,4)5;")).unwrap();
+        for format in [JsonFormat::Tree, JsonFormat::NodeLink] {
+            let mut out = Vec::new();
+            save::<_, &str, _>(&mut out, &tax, format, None).unwrap();
+            let value: Value = serde_json::from_slice(&out).unwrap();
+            // the default distance of 1 isn't written
+            assert_eq!(out.windows(10).filter(|w| w == b"\"distance\"").count(), 3);
+
+            for tax2 in [
+                load(Cursor::new(&out), None).unwrap(),
+                load_value(&value, None).unwrap(),
+            ] {
+                assert_eq!(
+                    Taxonomy::<&str>::parent(&tax2, "1").unwrap(),
+                    Some(("3", 0.25))
+                );
+                assert_eq!(
+                    Taxonomy::<&str>::parent(&tax2, "2").unwrap(),
+                    Some(("3", 2.0))
+                );
+                assert_eq!(
+                    Taxonomy::<&str>::parent(&tax2, "3").unwrap(),
+                    Some(("5", 0.5))
+                );
+                assert_eq!(
+                    Taxonomy::<&str>::parent(&tax2, "4").unwrap(),
+                    Some(("5", 1.0))
+                );
+                assert!(Taxonomy::<&str>::data(&tax2, "1").unwrap().is_empty());
+            }
+        }
+
+        // a missing or null distance is the default one
+        let example =
+            r#"{"id": "1", "name": "", "children": [{"id": "2", "name": "", "distance": null}]}"#;
+        let tax = load(Cursor::new(example), None).unwrap();
+        assert_eq!(
+            Taxonomy::<&str>::parent(&tax, "2").unwrap(),
+            Some(("1", 1.0))
+        );
+        let example = r#"{"id": "1", "name": "", "distance": "far"}"#;
+        assert!(load(Cursor::new(example), None).is_err());
+    }
+
+    #[test]
+    fn errors_on_invalid_streamed_node_link() {
+        let example = r#"{"nodes": [{"id": "a", "name": "root"}], "links": []}"#;
+        let err = load(Cursor::new(example), None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("cannot be converted to an integer"));
+
+        let example = r#"{"nodes": [{"id": 1, "name": "root"}]}"#;
+        let err = load(Cursor::new(example), None).unwrap_err();
+        assert!(err.to_string().contains("'links' not in JSON"));
+
+        let example =
+            r#"{"nodes": [{"id": 1, "name": "root"}], "links": [{"source": 1, "target": 0}]}"#;
+        let err = load(Cursor::new(example), None).unwrap_err();
+        assert!(err.to_string().contains("source index 1 specified"));
+
+        let example = r#"{"id": 1, "name": "root", "id": 2}"#;
+        let err = load(Cursor::new(example), None).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `id`"));
+
+        let example = r#"{"nodes": [{"id": 0, "name": "root"}], "nodes": [], "links": []}"#;
+        let err = load(Cursor::new(example), None).unwrap_err();
+        assert!(err.to_string().contains("duplicate field `nodes`"));
+    }
 }
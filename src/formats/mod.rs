@@ -1,3 +1,5 @@
+pub mod accession2taxid;
+pub mod binary;
 pub mod gtdb;
 pub mod json;
 pub mod ncbi;
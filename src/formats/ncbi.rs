@@ -1,94 +1,838 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::Path;
 use std::str::FromStr;
 
-use crate::base::GeneralTaxonomy;
+use flate2::read::MultiGzDecoder;
+use serde_json::Value;
+
+pub use crate::base::{Division, GeneticCode, NodeInfo};
+
+use crate::base::{GeneralTaxonomy, TaxName, SCIENTIFIC_NAME};
 use crate::errors::{Error, ErrorKind, TaxonomyResult};
 use crate::rank::TaxRank;
 use crate::taxonomy::Taxonomy;
 
 const NODES_FILENAME: &str = "nodes.dmp";
 const NAMES_FILENAME: &str = "names.dmp";
+const MERGED_FILENAME: &str = "merged.dmp";
+const DELNODES_FILENAME: &str = "delnodes.dmp";
+const DIVISION_FILENAME: &str = "division.dmp";
+const GENCODE_FILENAME: &str = "gencode.dmp";
+const HOST_FILENAME: &str = "host.dmp";
+const TYPEMATERIAL_FILENAME: &str = "typematerial.dmp";
+const RANKEDLINEAGE_FILENAME: &str = "rankedlineage.dmp";
+const FULLNAMELINEAGE_FILENAME: &str = "fullnamelineage.dmp";
+const TAXIDLINEAGE_FILENAME: &str = "taxidlineage.dmp";
+/// The optional files that are loaded alongside `nodes.dmp` and `names.dmp` if present
+const EXTRA_FILENAMES: [&str; 6] = [
+    MERGED_FILENAME,
+    DELNODES_FILENAME,
+    DIVISION_FILENAME,
+    GENCODE_FILENAME,
+    HOST_FILENAME,
+    TYPEMATERIAL_FILENAME,
+];
+/// The ranks of the `rankedlineage.dmp` columns following the tax ID and name
+const RANKEDLINEAGE_RANKS: [TaxRank; 8] = [
+    TaxRank::Species,
+    TaxRank::Genus,
+    TaxRank::Family,
+    TaxRank::Order,
+    TaxRank::Class,
+    TaxRank::Phylum,
+    TaxRank::Kingdom,
+    TaxRank::Superkingdom,
+];
+
+fn parse_id_field(field: &str) -> Result<Option<u32>, String> {
+    let field = field.trim();
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("Could not parse {} as an integer in nodes.dmp", field))
+}
+
+fn parse_flag_field(field: &str) -> Result<Option<bool>, String> {
+    match field.trim() {
+        "" => Ok(None),
+        "0" => Ok(Some(false)),
+        "1" => Ok(Some(true)),
+        f => Err(format!("Could not parse {} as a 0/1 flag in nodes.dmp", f)),
+    }
+}
+
+impl NodeInfo {
+    /// Parses the `nodes.dmp` fields that come after the rank.
+    /// Returns `None` if all of them are empty, e.g. for files written before this was supported.
+    fn from_fields(fields: &[&str]) -> Result<Option<Self>, String> {
+        if fields.iter().all(|f| f.trim().is_empty()) {
+            return Ok(None);
+        }
+        let field = |i: usize| fields.get(i).map_or("", |f| f.trim());
+
+        Ok(Some(NodeInfo {
+            embl_code: field(0).to_string(),
+            division_id: parse_id_field(field(1))?,
+            inherited_division: parse_flag_field(field(2))?.unwrap_or_default(),
+            genetic_code_id: parse_id_field(field(3))?,
+            inherited_genetic_code: parse_flag_field(field(4))?.unwrap_or_default(),
+            mitochondrial_genetic_code_id: parse_id_field(field(5))?,
+            inherited_mitochondrial_genetic_code: parse_flag_field(field(6))?.unwrap_or_default(),
+            genbank_hidden: parse_flag_field(field(7))?.unwrap_or_default(),
+            hidden_subtree_root: parse_flag_field(field(8))?.unwrap_or_default(),
+            comments: field(9).to_string(),
+            plastid_genetic_code_id: parse_id_field(field(10))?,
+            inherited_plastid_genetic_code: parse_flag_field(field(11))?,
+            specified_species: parse_flag_field(field(12))?,
+            hydrogenosome_genetic_code_id: parse_id_field(field(13))?,
+            inherited_hydrogenosome_genetic_code: parse_flag_field(field(14))?,
+        }))
+    }
+
+    /// Formats the `nodes.dmp` fields that come after the rank, separators included.
+    fn to_fields(&self) -> String {
+        let id = |x: Option<u32>| x.map(|x| x.to_string()).unwrap_or_default();
+        let flag = |x: bool| if x { "1" } else { "0" };
+        let mut out = format!(
+            "{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|",
+            self.embl_code,
+            id(self.division_id),
+            flag(self.inherited_division),
+            id(self.genetic_code_id),
+            flag(self.inherited_genetic_code),
+            id(self.mitochondrial_genetic_code_id),
+            flag(self.inherited_mitochondrial_genetic_code),
+            flag(self.genbank_hidden),
+            flag(self.hidden_subtree_root),
+            self.comments,
+        );
+        if self.plastid_genetic_code_id.is_some()
+            || self.inherited_plastid_genetic_code.is_some()
+            || self.specified_species.is_some()
+            || self.hydrogenosome_genetic_code_id.is_some()
+            || self.inherited_hydrogenosome_genetic_code.is_some()
+        {
+            let opt_flag = |x: Option<bool>| x.map(flag).unwrap_or_default();
+            out.push_str(&format!(
+                "\t{}\t|\t{}\t|\t{}\t|\t{}\t|\t{}\t|",
+                id(self.plastid_genetic_code_id),
+                opt_flag(self.inherited_plastid_genetic_code),
+                opt_flag(self.specified_species),
+                id(self.hydrogenosome_genetic_code_id),
+                opt_flag(self.inherited_hydrogenosome_genetic_code),
+            ));
+        }
+        out
+    }
+}
+
+/// What to do with the nodes whose parent is not in `nodes.dmp`, e.g. because the dump
+/// was filtered or truncated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// Fail to load the taxonomy
+    #[default]
+    Error,
+    /// Attach the orphans directly to the root node
+    AttachToRoot,
+    /// Attach the orphans to a synthetic [UNPLACED_TAX_ID] node, itself a child of the root
+    AttachToUnplaced,
+}
+
+/// The tax ID of the synthetic node orphans are attached to with [OrphanPolicy::AttachToUnplaced]
+pub const UNPLACED_TAX_ID: &str = "unplaced";
+
+/// Options for loading NCBI taxonomies. The defaults are strict: loading fails on the
+/// first problem found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    pub orphans: OrphanPolicy,
+    /// Skip the lines that cannot be parsed, or that refer to unknown nodes, instead of failing
+    pub skip_malformed_lines: bool,
+}
+
+impl LoadOptions {
+    /// Options that load as much as possible of a filtered or truncated dump
+    pub fn lenient() -> Self {
+        LoadOptions {
+            orphans: OrphanPolicy::AttachToUnplaced,
+            skip_malformed_lines: true,
+        }
+    }
+}
+
+/// A repair made while loading a NCBI taxonomy with non-default [LoadOptions], or one of the
+/// `merged.dmp` lines that are always skipped because they merge into an unknown tax ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// The parent of that node could not be found so it was attached to `new_parent_id` instead
+    ReattachedOrphan {
+        tax_id: String,
+        missing_parent_id: String,
+        new_parent_id: String,
+    },
+    /// That line (starting at 1) of that file could not be used and was skipped
+    SkippedLine {
+        filename: String,
+        line: usize,
+        msg: String,
+    },
+}
+
+/// Keeps track of the options and of the repairs made while loading.
+struct Loader<'a> {
+    options: &'a LoadOptions,
+    repairs: Vec<Repair>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(options: &'a LoadOptions) -> Self {
+        Loader {
+            options,
+            repairs: Vec::new(),
+        }
+    }
+
+    /// Records that a line was skipped if the options allow it, returns the error otherwise.
+    fn skip_line(&mut self, filename: &str, line: usize, error: Error) -> TaxonomyResult<()> {
+        if !self.options.skip_malformed_lines {
+            return Err(error);
+        }
+        let msg = match error.kind {
+            ErrorKind::ImportError { msg, .. } => msg,
+            _ => error.to_string(),
+        };
+        self.repairs.push(Repair::SkippedLine {
+            filename: filename.to_string(),
+            line,
+            msg,
+        });
+        Ok(())
+    }
+
+    /// Reads the trimmed fields of one of the simpler `.dmp` files, checking that each line
+    /// has at least `min_fields` fields and a non-empty first field.
+    /// Returns the line number alongside the fields.
+    fn read_dmp<R: Read>(
+        &mut self,
+        reader: R,
+        filename: &str,
+        min_fields: usize,
+    ) -> TaxonomyResult<Vec<(usize, Vec<String>)>> {
+        let mut rows = Vec::new();
+        for (ix, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let fields: Vec<String> = line
+                .strip_suffix("\t|")
+                .unwrap_or(&line)
+                .split("\t|")
+                .map(|x| x.trim().to_string())
+                .collect();
+            if fields.len() < min_fields || fields[0].is_empty() {
+                let msg = format!("Not enough fields in {}; bad line?", filename);
+                self.skip_line(filename, ix + 1, import_error(ix + 1, msg))?;
+                continue;
+            }
+            rows.push((ix + 1, fields));
+        }
+        Ok(rows)
+    }
+
+    /// Adds the content of one of the optional `.dmp` files to the taxonomy.
+    fn load_extra_dmp<R: Read>(
+        &mut self,
+        gt: &mut GeneralTaxonomy,
+        filename: &str,
+        reader: R,
+    ) -> TaxonomyResult<()> {
+        match filename {
+            MERGED_FILENAME => {
+                for (line, fields) in self.read_dmp(reader, filename, 2)? {
+                    match gt.add_merged_id(&fields[0], &fields[1]) {
+                        Ok(()) => {}
+                        // filtered dumps usually come with the full merged.dmp so the
+                        // rows merging into nodes that were filtered out are always skipped
+                        Err(Error {
+                            kind: ErrorKind::NoSuchTaxId(_) | ErrorKind::DeletedTaxId(_),
+                            ..
+                        }) => self.repairs.push(Repair::SkippedLine {
+                            filename: filename.to_string(),
+                            line,
+                            msg: format!(
+                                "Tax ID {} was merged into {} which is not in the taxonomy",
+                                fields[0], fields[1]
+                            ),
+                        }),
+                        Err(e) => {
+                            let msg = match e.kind {
+                                ErrorKind::OperationNotAllowed(msg) => msg,
+                                _ => e.to_string(),
+                            };
+                            self.skip_line(filename, line, import_error(line, msg))?;
+                        }
+                    }
+                }
+            }
+            DELNODES_FILENAME => {
+                for (_, mut fields) in self.read_dmp(reader, filename, 1)? {
+                    gt.deleted_ids.insert(fields.swap_remove(0));
+                }
+            }
+            DIVISION_FILENAME => {
+                for (line, mut fields) in self.read_dmp(reader, filename, 4)? {
+                    let id = match parse_division_or_code_id(&fields[0], line, filename) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            self.skip_line(filename, line, e)?;
+                            continue;
+                        }
+                    };
+                    gt.divisions.insert(
+                        id,
+                        Division {
+                            comments: fields.swap_remove(3),
+                            name: fields.swap_remove(2),
+                            code: fields.swap_remove(1),
+                        },
+                    );
+                }
+            }
+            GENCODE_FILENAME => {
+                for (line, mut fields) in self.read_dmp(reader, filename, 5)? {
+                    let id = match parse_division_or_code_id(&fields[0], line, filename) {
+                        Ok(id) => id,
+                        Err(e) => {
+                            self.skip_line(filename, line, e)?;
+                            continue;
+                        }
+                    };
+                    gt.genetic_codes.insert(
+                        id,
+                        GeneticCode {
+                            start_codons: fields.swap_remove(4),
+                            translation_table: fields.swap_remove(3),
+                            name: fields.swap_remove(2),
+                            abbreviation: fields.swap_remove(1),
+                        },
+                    );
+                }
+            }
+            HOST_FILENAME => {
+                for (_, fields) in self.read_dmp(reader, filename, 2)? {
+                    if let Some(idx) = gt.tax_id_lookup.get(&fields[0]) {
+                        let hosts: Vec<Value> = fields[1]
+                            .split(',')
+                            .map(|x| x.trim())
+                            .filter(|x| !x.is_empty())
+                            .map(Value::from)
+                            .collect();
+                        gt.data[*idx].insert("hosts".to_string(), Value::from(hosts));
+                    }
+                }
+            }
+            TYPEMATERIAL_FILENAME => {
+                for (_, mut fields) in self.read_dmp(reader, filename, 4)? {
+                    if let Some(idx) = gt.tax_id_lookup.get(&fields[0]) {
+                        let mut material = serde_json::Map::new();
+                        material.insert("identifier".to_string(), fields.swap_remove(3).into());
+                        material.insert("type".to_string(), fields.swap_remove(2).into());
+                        material.insert("name".to_string(), fields.swap_remove(1).into());
+                        let entry = gt.data[*idx]
+                            .entry("type_material".to_string())
+                            .or_insert_with(|| Value::Array(Vec::new()));
+                        if let Value::Array(materials) = entry {
+                            materials.push(Value::Object(material));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_nodes_and_names<R1: Read, R2: Read>(
+        &mut self,
+        nodes_reader: R1,
+        names_reader: R2,
+    ) -> TaxonomyResult<GeneralTaxonomy> {
+        let nodes_content = read_dmp_content(nodes_reader, NODES_FILENAME)?;
+        let names_content = read_dmp_content(names_reader, NAMES_FILENAME)?;
+
+        // First we go through the nodes
+        let parsed_nodes = map_lines(&nodes_content, parse_node_line);
+        let mut tax_ids: Vec<String> = Vec::with_capacity(parsed_nodes.len());
+        let mut parents: Vec<&str> = Vec::with_capacity(parsed_nodes.len());
+        let mut ranks: Vec<TaxRank> = Vec::with_capacity(parsed_nodes.len());
+        // the line of each node, since malformed lines might have been skipped
+        let mut lines: Vec<usize> = Vec::with_capacity(parsed_nodes.len());
+        // handed over to the taxonomy as is so it doesn't have to be built twice
+        let mut tax_id_lookup: HashMap<String, usize> = HashMap::with_capacity(parsed_nodes.len());
+        let mut node_infos: Vec<Option<NodeInfo>> = Vec::with_capacity(parsed_nodes.len());
+        let mut duplicates: Vec<&str> = Vec::new();
+
+        for (ix, node) in parsed_nodes.into_iter().enumerate() {
+            let node = match node {
+                Ok(node) => node,
+                Err(e) => {
+                    self.skip_line(NODES_FILENAME, ix + 1, at_line(e, ix + 1))?;
+                    continue;
+                }
+            };
+            if tax_id_lookup
+                .insert(node.tax_id.to_string(), tax_ids.len())
+                .is_some()
+            {
+                duplicates.push(node.tax_id);
+            }
+            tax_ids.push(node.tax_id.to_string());
+            parents.push(node.parent_id);
+            ranks.push(node.rank);
+            lines.push(ix + 1);
+            node_infos.push(node.info);
+        }
+
+        if !duplicates.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidTaxonomy(format!(
+                "Some tax ids are duplicated: {:?}.",
+                duplicates
+            ))));
+        }
+
+        // The root is the node that is its own parent, it has to come first
+        if !tax_ids.is_empty() {
+            let root_idx = (0..tax_ids.len())
+                .find(|i| tax_ids[*i] == parents[*i])
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidTaxonomy(
+                        "No node in nodes.dmp is its own parent to act as the root.".to_owned(),
+                    ))
+                })?;
+            if root_idx != 0 {
+                tax_ids.swap(0, root_idx);
+                parents.swap(0, root_idx);
+                ranks.swap(0, root_idx);
+                lines.swap(0, root_idx);
+                node_infos.swap(0, root_idx);
+                for idx in [0, root_idx] {
+                    if let Some(lookup_idx) = tax_id_lookup.get_mut(&tax_ids[idx]) {
+                        *lookup_idx = idx;
+                    }
+                }
+            }
+        }
+
+        // Nodes whose parent is missing are either an error or get re-attached somewhere
+        // depending on the options
+        let n_nodes = tax_ids.len();
+        let mut parent_ids = Vec::with_capacity(n_nodes + 1);
+        let mut unplaced_idx = tax_id_lookup.get(UNPLACED_TAX_ID).copied();
+        for (i, parent) in parents.into_iter().enumerate() {
+            match tax_id_lookup.get(parent) {
+                // only the root can be its own parent
+                Some(idx) if *idx != i || i == 0 => {
+                    parent_ids.push(*idx);
+                    continue;
+                }
+                _ => {}
+            }
+            let new_parent_idx = match self.options.orphans {
+                OrphanPolicy::Error if parent == tax_ids[i] => {
+                    return Err(import_error(
+                        lines[i],
+                        format!("Tax ID {} is its own parent but isn't the root", parent),
+                    ));
+                }
+                OrphanPolicy::Error => {
+                    return Err(import_error(
+                        lines[i],
+                        format!("Parent ID {} could not be found in nodes.dmp", parent),
+                    ));
+                }
+                OrphanPolicy::AttachToRoot => 0,
+                OrphanPolicy::AttachToUnplaced => *unplaced_idx.get_or_insert(n_nodes),
+            };
+            let new_parent_id = if new_parent_idx == n_nodes {
+                UNPLACED_TAX_ID.to_string()
+            } else {
+                tax_ids[new_parent_idx].clone()
+            };
+            self.repairs.push(Repair::ReattachedOrphan {
+                tax_id: tax_ids[i].clone(),
+                missing_parent_id: parent.to_string(),
+                new_parent_id,
+            });
+            parent_ids.push(new_parent_idx);
+        }
+        if unplaced_idx == Some(n_nodes) {
+            tax_id_lookup.insert(UNPLACED_TAX_ID.to_string(), n_nodes);
+            tax_ids.push(UNPLACED_TAX_ID.to_string());
+            parent_ids.push(0);
+            ranks.push(TaxRank::Unspecified);
+            node_infos.push(None);
+        }
+
+        // And then grab their names by their idx, keeping all of the name classes around
+        let mut names: Vec<String> = vec![String::new(); tax_ids.len()];
+        if unplaced_idx == Some(n_nodes) {
+            names[n_nodes] = UNPLACED_TAX_ID.to_string();
+        }
+        let mut all_names: HashMap<String, Vec<TaxName>> = HashMap::with_capacity(n_nodes);
+        for (ix, name) in map_lines(&names_content, parse_name_line)
+            .into_iter()
+            .enumerate()
+        {
+            let (tax_id, name) = match name {
+                Ok(name) => name,
+                Err(e) => {
+                    self.skip_line(NAMES_FILENAME, ix + 1, at_line(e, ix + 1))?;
+                    continue;
+                }
+            };
+            let idx = match tax_id_lookup.get(tax_id) {
+                Some(idx) => *idx,
+                None => {
+                    let msg = format!("Tax ID {} could not be found in nodes.dmp", tax_id);
+                    self.skip_line(NAMES_FILENAME, ix + 1, import_error(ix + 1, msg))?;
+                    continue;
+                }
+            };
+            if name.class == SCIENTIFIC_NAME {
+                names[idx] = name.name.clone();
+            }
+            match all_names.get_mut(tax_id) {
+                Some(node_names) => node_names.push(name),
+                None => {
+                    all_names.insert(tax_ids[idx].clone(), vec![name]);
+                }
+            }
+        }
+
+        let mut gt = GeneralTaxonomy::from_arrays_with_lookup(
+            tax_ids,
+            parent_ids,
+            Some(names),
+            Some(ranks),
+            None,
+            None,
+            Some(tax_id_lookup),
+        )?;
+        gt.all_names = all_names;
+        gt.ncbi_nodes = node_infos;
+
+        Ok(gt)
+    }
+}
+
+/// A line of `nodes.dmp`, borrowing from the file content.
+struct NodeLine<'a> {
+    tax_id: &'a str,
+    parent_id: &'a str,
+    rank: TaxRank,
+    info: Option<NodeInfo>,
+}
+
+/// The maximum number of fields in a `nodes.dmp` line, for the `new_taxdump` format
+const MAX_NODE_FIELDS: usize = 18;
+
+/// Reads a whole `.dmp` file in memory so its lines can be parsed without copying them.
+fn read_dmp_content<R: Read>(mut reader: R, filename: &str) -> TaxonomyResult<String> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    String::from_utf8(content).map_err(|e| {
+        let line =
+            memchr::memchr_iter(b'\n', &e.as_bytes()[..e.utf8_error().valid_up_to()]).count() + 1;
+        import_error(line, format!("{} is not valid UTF-8", filename))
+    })
+}
+
+/// Iterates over the lines of `content`, without their line endings.
+fn dmp_lines(content: &str) -> impl Iterator<Item = &str> {
+    let mut rest = content;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (line, next) = match memchr::memchr(b'\n', rest.as_bytes()) {
+            Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+            None => (rest, ""),
+        };
+        rest = next;
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    })
+}
+
+/// Parses all the lines of `content` with `parse`, keeping them in order.
+#[cfg(not(feature = "parallel"))]
+fn map_lines<'a, T, F>(content: &'a str, parse: F) -> Vec<T>
+where
+    F: Fn(&'a str) -> T,
+{
+    dmp_lines(content).map(parse).collect()
+}
+
+/// Parses all the lines of `content` with `parse`, keeping them in order.
+/// The content is split in chunks of lines that are parsed in parallel.
+#[cfg(feature = "parallel")]
+fn map_lines<'a, T, F>(content: &'a str, parse: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&'a str) -> T + Sync,
+{
+    use rayon::prelude::*;
+
+    const CHUNK_SIZE: usize = 1 << 20;
+    let mut chunks = Vec::with_capacity(content.len() / CHUNK_SIZE + 1);
+    let mut rest = content;
+    while rest.len() > CHUNK_SIZE {
+        // chunks always end at the end of a line
+        match memchr::memchr(b'\n', &rest.as_bytes()[CHUNK_SIZE..]) {
+            Some(pos) => {
+                let (chunk, next) = rest.split_at(CHUNK_SIZE + pos + 1);
+                chunks.push(chunk);
+                rest = next;
+            }
+            None => break,
+        }
+    }
+    chunks.push(rest);
+
+    let parsed: Vec<Vec<T>> = chunks
+        .into_par_iter()
+        .map(|chunk| dmp_lines(chunk).map(&parse).collect())
+        .collect();
+    parsed.into_iter().flatten().collect()
+}
+
+/// Splits a line on the `\t|\t` separators, ignoring the final `\t|`.
+/// Only the first `N` fields are kept but all of them are counted.
+fn split_dmp_line<const N: usize>(line: &str) -> ([&str; N], usize) {
+    let line = line.strip_suffix("\t|").unwrap_or(line);
+    let bytes = line.as_bytes();
+    let mut fields = [""; N];
+    let mut count = 0;
+    let mut start = 0;
+    for pos in memchr::memchr_iter(b'|', bytes) {
+        if pos > start && bytes[pos - 1] == b'\t' && bytes.get(pos + 1) == Some(&b'\t') {
+            if count < N {
+                fields[count] = &line[start..pos - 1];
+            }
+            count += 1;
+            start = pos + 2;
+        }
+    }
+    if count < N {
+        fields[count] = &line[start..];
+    }
+    (fields, count + 1)
+}
+
+fn parse_node_line(line: &str) -> TaxonomyResult<NodeLine<'_>> {
+    let (fields, count) = split_dmp_line::<MAX_NODE_FIELDS>(line);
+    if count < 10 {
+        // should be at least 13
+        return Err(import_error(
+            0,
+            "Not enough fields in nodes.dmp; bad line?".to_owned(),
+        ));
+    }
+    let info = NodeInfo::from_fields(&fields[3..count.min(MAX_NODE_FIELDS)])
+        .map_err(|msg| import_error(0, msg))?;
+    Ok(NodeLine {
+        tax_id: fields[0].trim(),
+        parent_id: fields[1].trim(),
+        rank: TaxRank::from_str(fields[2])?,
+        info,
+    })
+}
+
+fn parse_name_line(line: &str) -> TaxonomyResult<(&str, TaxName)> {
+    let (fields, count) = split_dmp_line::<4>(line);
+    if count > 10 {
+        // should only be 5
+        return Err(import_error(0, "Too many fields in names.dmp".to_owned()));
+    }
+    if count < 4 {
+        return Err(import_error(
+            0,
+            "Not enough fields in names.dmp; bad line?".to_owned(),
+        ));
+    }
+    Ok((
+        fields[0].trim(),
+        TaxName {
+            name: fields[1].trim().to_string(),
+            unique_name: fields[2].trim().to_string(),
+            class: fields[3].trim().to_string(),
+        },
+    ))
+}
+
+/// Sets the line of an import error, for errors coming from the line parsers.
+fn at_line(mut error: Error, line: usize) -> Error {
+    if let ErrorKind::ImportError { line: l, .. } = &mut error.kind {
+        *l = line;
+    }
+    error
+}
+
+fn import_error(line: usize, msg: String) -> Error {
+    Error::new(ErrorKind::ImportError { line, msg })
+}
+
+fn parse_division_or_code_id(field: &str, line: usize, filename: &str) -> TaxonomyResult<u32> {
+    field.parse().map_err(|_| {
+        import_error(
+            line,
+            format!("Could not parse {} as an integer in {}", field, filename),
+        )
+    })
+}
 
 /// Loads a NCBI taxonomy from the given directory.
 /// The directory should contain at least two files: `nodes.dmp` and `names.dmp`.
+/// If a `merged.dmp` file is present, the merged tax IDs it lists will resolve
+/// to the nodes they were merged into. The lines merging into a tax ID that is not in
+/// `nodes.dmp`, e.g. in a filtered dump, are skipped and reported as a [Repair].
+/// If a `delnodes.dmp` file is present, looking up the deleted tax IDs it lists
+/// will return an [ErrorKind::DeletedTaxId] error.
+/// The other columns of `nodes.dmp` (genetic codes, division, ...) are kept as a [NodeInfo]
+/// and the `division.dmp` and `gencode.dmp` files are loaded if present.
+/// The `new_taxdump` `host.dmp` and `typematerial.dmp` files are loaded into the node
+/// data if present, respectively as a `hosts` list of strings and as a `type_material` list
+/// of objects with `name`, `type` and `identifier` keys.
 pub fn load<P: AsRef<Path>>(ncbi_directory: P) -> TaxonomyResult<GeneralTaxonomy> {
+    load_with_options(ncbi_directory, &LoadOptions::default()).map(|(gt, _)| gt)
+}
+
+/// Same as [load] but with the given [LoadOptions], e.g. to load a filtered or truncated
+/// dump. Returns all the repairs that had to be made alongside the taxonomy.
+pub fn load_with_options<P: AsRef<Path>>(
+    ncbi_directory: P,
+    options: &LoadOptions,
+) -> TaxonomyResult<(GeneralTaxonomy, Vec<Repair>)> {
     let dir = ncbi_directory.as_ref();
     let nodes_file = std::fs::File::open(dir.join(NODES_FILENAME))?;
     let names_file = std::fs::File::open(dir.join(NAMES_FILENAME))?;
 
-    // First we go through the nodes
-    let mut tax_ids: Vec<String> = Vec::new();
-    let mut parents: Vec<String> = Vec::new();
-    let mut ranks: Vec<TaxRank> = Vec::new();
-    let mut tax_to_idx: HashMap<String, usize> = HashMap::new();
-
-    for (ix, line) in BufReader::new(nodes_file).lines().enumerate() {
-        let mut fields: Vec<String> = line?.split("\t|\t").map(|x| x.to_string()).collect();
-        if fields.len() < 10 {
-            // should be at least 14
-            return Err(Error::new(ErrorKind::ImportError {
-                line: ix,
-                msg: "Not enough fields in nodes.dmp; bad line?".to_owned(),
-            }));
+    let mut loader = Loader::new(options);
+    let mut gt = loader.load_nodes_and_names(nodes_file, names_file)?;
+    for filename in EXTRA_FILENAMES {
+        let path = dir.join(filename);
+        if path.exists() {
+            loader.load_extra_dmp(&mut gt, filename, std::fs::File::open(path)?)?;
         }
-        let tax_id = fields.remove(0).trim().to_string();
-        let parent_tax_id = fields.remove(0).trim().to_string();
-        let rank = fields.remove(0);
-
-        tax_ids.push(tax_id.clone());
-        parents.push(parent_tax_id.to_string());
-        ranks.push(TaxRank::from_str(&rank)?);
-        tax_to_idx.insert(tax_id, ix);
     }
+    Ok((gt, loader.repairs))
+}
 
-    // TODO: fixme? this fails if we have unmapped parent nodes (i.e. file is truncated?)
-    let mut parent_ids = Vec::with_capacity(parents.len());
-    for (i, parent) in parents.into_iter().enumerate() {
-        if let Some(idx) = tax_to_idx.get(&parent) {
-            parent_ids.push(*idx);
-        } else {
-            return Err(Error::new(ErrorKind::ImportError {
-                line: i + 1,
-                msg: format!("Parent ID {} could not be found in nodes.dmp", parent),
-            }));
+/// Loads a NCBI taxonomy from a `taxdump.tar.gz` (or `new_taxdump.tar.gz`) archive,
+/// without extracting it to disk. Uncompressed tar archives are also accepted.
+/// The archive should contain at least `nodes.dmp` and `names.dmp`; the other files
+/// are handled as in [load].
+pub fn load_archive<R: Read>(reader: R) -> TaxonomyResult<GeneralTaxonomy> {
+    load_archive_with_options(reader, &LoadOptions::default()).map(|(gt, _)| gt)
+}
+
+/// Same as [load_archive] but with the given [LoadOptions].
+/// Returns all the repairs that had to be made alongside the taxonomy.
+pub fn load_archive_with_options<R: Read>(
+    reader: R,
+    options: &LoadOptions,
+) -> TaxonomyResult<(GeneralTaxonomy, Vec<Repair>)> {
+    let mut reader = BufReader::new(reader);
+    let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    let reader: Box<dyn Read> = if is_gzip {
+        Box::new(MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    // The members can be in any order so we keep the ones we need in memory
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let filename = match entry.path()?.file_name() {
+            Some(f) => f.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if filename == NODES_FILENAME
+            || filename == NAMES_FILENAME
+            || EXTRA_FILENAMES.contains(&filename.as_str())
+        {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            files.insert(filename, content);
         }
     }
 
-    // And then grab their names by their idx
-    let mut names: Vec<String> = vec![String::new(); tax_ids.len()];
-    for (ix, line) in BufReader::new(names_file).lines().enumerate() {
-        let mut fields: Vec<String> = line?.split("\t|\t").map(|x| x.to_string()).collect();
-        if fields.len() > 10 {
-            // should only be 5
-            return Err(Error::new(ErrorKind::ImportError {
-                line: ix,
-                msg: "Too many fields in names.dmp".to_owned(),
-            }));
-        }
-        let tax_id = fields.remove(0).trim().to_string();
-        let name = fields.remove(0).trim().to_string();
-        let name_class = fields.remove(1);
-        if name_class.starts_with("scientific name") {
-            let name = name.to_string();
-            names[tax_to_idx[&*tax_id]] = name;
+    let missing = |filename: &str| import_error(0, format!("{} not found in archive", filename));
+    let nodes = files
+        .remove(NODES_FILENAME)
+        .ok_or_else(|| missing(NODES_FILENAME))?;
+    let names = files
+        .remove(NAMES_FILENAME)
+        .ok_or_else(|| missing(NAMES_FILENAME))?;
+
+    let mut loader = Loader::new(options);
+    let mut gt = loader.load_nodes_and_names(&nodes[..], &names[..])?;
+    for filename in EXTRA_FILENAMES {
+        if let Some(content) = files.get(filename) {
+            loader.load_extra_dmp(&mut gt, filename, &content[..])?;
         }
     }
+    Ok((gt, loader.repairs))
+}
 
-    let gt =
-        GeneralTaxonomy::from_arrays(tax_ids, parent_ids, Some(names), Some(ranks), None, None)?;
-    gt.validate_uniqueness()?;
-    Ok(gt)
+/// Loads a NCBI taxonomy from the content of `nodes.dmp` and `names.dmp`, eg from memory or stdin.
+pub fn load_from_readers<R1: Read, R2: Read>(
+    nodes_reader: R1,
+    names_reader: R2,
+) -> TaxonomyResult<GeneralTaxonomy> {
+    load_from_readers_with_options(nodes_reader, names_reader, &LoadOptions::default())
+        .map(|(gt, _)| gt)
 }
 
-pub fn save<'t, T: 't, P: AsRef<Path>, X: Taxonomy<'t, T>>(
-    tax: &'t X,
-    out_dir: P,
-) -> TaxonomyResult<()>
+/// Same as [load_from_readers] but with the given [LoadOptions].
+/// Returns all the repairs that had to be made alongside the taxonomy.
+pub fn load_from_readers_with_options<R1: Read, R2: Read>(
+    nodes_reader: R1,
+    names_reader: R2,
+    options: &LoadOptions,
+) -> TaxonomyResult<(GeneralTaxonomy, Vec<Repair>)> {
+    let mut loader = Loader::new(options);
+    let gt = loader.load_nodes_and_names(nodes_reader, names_reader)?;
+    Ok((gt, loader.repairs))
+}
+
+fn write_name<W: Write, T: Display>(
+    writer: &mut W,
+    tax_id: &T,
+    name: &str,
+    unique_name: &str,
+    class: &str,
+) -> TaxonomyResult<()> {
+    writer.write_all(
+        format!(
+            "{}\t|\t{}\t|\t{}\t|\t{}\t|\n",
+            tax_id, name, unique_name, class
+        )
+        .as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Saves a taxonomy as NCBI `nodes.dmp` and `names.dmp` files in the given directory.
+/// All the names of a node from [Taxonomy::all_names] are written, not only its main name,
+/// and the other `nodes.dmp` columns are written from [Taxonomy::node_info].
+pub fn save<'t, T, P: AsRef<Path>, X: Taxonomy<'t, T>>(tax: &'t X, out_dir: P) -> TaxonomyResult<()>
 where
-    T: Clone + Debug + Display + PartialEq,
+    T: 't + Clone + Debug + Display + PartialEq,
 {
     let dir = out_dir.as_ref();
     std::fs::create_dir_all(dir)?;
@@ -106,14 +850,36 @@ where
                 .map(|(x, _)| format!("{}", x))
                 .unwrap_or_default()
         };
-        name_writer
-            .write_all(format!("{}\t|\t{}\t|\t\t|\tscientific name\t|\n", &key, name).as_bytes())?;
+        // The main name might have been edited since loading so it takes precedence
+        // over the scientific name that was stored alongside the other names
+        let all_names = tax.all_names(key.clone())?;
+        let mut wrote_name = false;
+        for n in all_names.iter() {
+            if n.class == SCIENTIFIC_NAME {
+                if wrote_name {
+                    continue;
+                }
+                let unique_name = if n.name == name { &n.unique_name } else { "" };
+                write_name(&mut name_writer, &key, name, unique_name, SCIENTIFIC_NAME)?;
+                wrote_name = true;
+            } else {
+                write_name(&mut name_writer, &key, &n.name, &n.unique_name, &n.class)?;
+            }
+        }
+        if !wrote_name {
+            write_name(&mut name_writer, &key, name, "", SCIENTIFIC_NAME)?;
+        }
+        let other_fields = tax
+            .node_info(key.clone())?
+            .map(|info| info.to_fields())
+            .unwrap_or_else(|| "\t|\t".repeat(9) + "\t|");
         node_writer.write_all(
             format!(
-                "{}\t|\t{}\t|\t{}\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\n",
+                "{}\t|\t{}\t|\t{}\t|\t{}\n",
                 &key,
                 parent,
                 rank.to_ncbi_rank(),
+                other_fields,
             )
             .as_bytes(),
         )?;
@@ -122,10 +888,76 @@ where
     Ok(())
 }
 
+/// Writes a dmp line made of the given fields.
+fn write_dmp_line<W: Write>(writer: &mut W, fields: &[&str]) -> TaxonomyResult<()> {
+    writer.write_all(format!("{}\t|\n", fields.join("\t|\t")).as_bytes())?;
+    Ok(())
+}
+
+/// Saves the lineage files of the NCBI `new_taxdump` into the given directory,
+/// computed from the lineage of each node:
+/// - `rankedlineage.dmp`: the names of the species, genus, family, order, class, phylum, kingdom
+///   and superkingdom ancestors of each node
+/// - `fullnamelineage.dmp`: the names of all the ancestors of each node
+/// - `taxidlineage.dmp`: the tax IDs of all the ancestors of each node
+///
+/// As in the NCBI files, the node itself and the root are not part of its lineage.
+pub fn save_lineages<'t, T, P: AsRef<Path>, X: Taxonomy<'t, T>>(
+    tax: &'t X,
+    out_dir: P,
+) -> TaxonomyResult<()>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    let dir = out_dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let mut ranked_writer =
+        BufWriter::new(std::fs::File::create(dir.join(RANKEDLINEAGE_FILENAME))?);
+    let mut fullname_writer =
+        BufWriter::new(std::fs::File::create(dir.join(FULLNAMELINEAGE_FILENAME))?);
+    let mut taxid_writer = BufWriter::new(std::fs::File::create(dir.join(TAXIDLINEAGE_FILENAME))?);
+
+    for key in tax.traverse(tax.root())?.filter(|x| x.1).map(|x| x.0) {
+        let tax_id = key.to_string();
+        let name = tax.name(key.clone())?;
+        let mut lineage = tax.lineage(key)?;
+        // drop the node itself and the root, and start from the top of the tree
+        lineage.pop();
+        let ancestors = lineage.get(1..).unwrap_or_default();
+
+        let mut ranked_names = [""; RANKEDLINEAGE_RANKS.len()];
+        let mut full_names = String::new();
+        let mut tax_ids = String::new();
+        for ancestor in ancestors.iter().rev() {
+            let ancestor_name = tax.name(ancestor.clone())?;
+            let rank = match tax.rank(ancestor.clone())? {
+                TaxRank::Domain => TaxRank::Superkingdom,
+                r => r,
+            };
+            if let Some(ix) = RANKEDLINEAGE_RANKS.iter().position(|r| *r == rank) {
+                ranked_names[ix] = ancestor_name;
+            }
+            full_names.push_str(ancestor_name);
+            full_names.push_str("; ");
+            tax_ids.push_str(&ancestor.to_string());
+            tax_ids.push(' ');
+        }
+
+        let mut fields = vec![tax_id.as_str(), name];
+        fields.extend(ranked_names);
+        write_dmp_line(&mut ranked_writer, &fields)?;
+        write_dmp_line(&mut fullname_writer, &[&tax_id, name, &full_names])?;
+        write_dmp_line(&mut taxid_writer, &[&tax_id, &tax_ids])?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::taxonomy::Taxonomy;
+    use std::borrow::Cow;
     use std::io::Write;
     use tempfile::tempdir;
 
@@ -258,5 +1090,512 @@ mod tests {
             Taxonomy::<&str>::children(&tax2, "561").unwrap(),
             vec!["562"]
         );
+
+        // Check all the other names are preserved
+        assert_eq!(tax2.all_names, tax.all_names);
+        assert_eq!(
+            std::fs::read_to_string(out.join(NAMES_FILENAME))
+                .unwrap()
+                .lines()
+                .find(|l| l.starts_with("2\t"))
+                .unwrap(),
+            "2\t|\tBacteria\t|\tBacteria <prokaryotes>\t|\tscientific name\t|"
+        );
+    }
+
+    #[test]
+    fn can_use_ncbi_name_classes() {
+        let mut tax = load("tests/data/").unwrap();
+        assert_eq!(
+            tax.names_of("562", "synonym").unwrap(),
+            vec![
+                "Bacillus coli",
+                "Bacterium coli",
+                "Bacterium coli commune",
+                "Enterococcus coli"
+            ]
+        );
+        assert_eq!(tax.names_of("562", "common name").unwrap(), vec!["E. coli"]);
+        assert_eq!(
+            tax.names_of("562", SCIENTIFIC_NAME).unwrap(),
+            vec!["Escherichia coli"]
+        );
+        assert!(tax.names_of("543", "synonym").unwrap().is_empty());
+
+        assert_eq!(
+            tax.find_all_by_name_class("Bacterium coli", &["synonym"]),
+            vec!["562"]
+        );
+        assert!(tax
+            .find_all_by_name_class("Bacterium coli", &[SCIENTIFIC_NAME])
+            .is_empty());
+        assert_eq!(
+            tax.find_all_by_name_class("Escherichia coli", &[SCIENTIFIC_NAME, "synonym"]),
+            vec!["562"]
+        );
+
+        // The names are kept when pruning
+        let pruned = crate::prune_to(&tax, &["562"], false).unwrap();
+        assert_eq!(
+            pruned.names_of("562", "common name").unwrap(),
+            vec!["E. coli"]
+        );
+        assert_eq!(
+            pruned.find_all_by_name_class("Bacterium coli", &["synonym"]),
+            vec!["562"]
+        );
+        let pruned = crate::prune_away(&tax, &["10239"]).unwrap();
+        assert_eq!(
+            pruned.names_of("562", "synonym").unwrap(),
+            tax.names_of("562", "synonym").unwrap()
+        );
+
+        // Renaming a node changes the scientific name that gets saved
+        let idx = tax.to_internal_index("562").unwrap();
+        tax.names[idx] = "Escherichia coli renamed".to_string();
+        let dir = tempdir().unwrap();
+        save::<&str, _, _>(&tax, dir.path()).unwrap();
+        let tax2 = load(dir.path()).unwrap();
+        assert_eq!(
+            tax2.names_of("562", SCIENTIFIC_NAME).unwrap(),
+            vec!["Escherichia coli renamed"]
+        );
+        assert_eq!(
+            tax2.names_of("562", "common name").unwrap(),
+            vec!["E. coli"]
+        );
+    }
+
+    #[test]
+    fn can_import_ncbi_merged() {
+        let tax = load("tests/data/").unwrap();
+        assert_eq!(tax.merged_into("469598"), Some("562"));
+        assert_eq!(tax.merged_into("562"), None);
+        assert_eq!(
+            tax.to_internal_index("469598").unwrap(),
+            tax.to_internal_index("562").unwrap()
+        );
+        assert_eq!(
+            Taxonomy::<&str>::name(&tax, "469598").unwrap(),
+            "Escherichia coli"
+        );
+        assert_eq!(
+            Taxonomy::<&str>::lineage(&tax, "469598").unwrap()[..2],
+            ["562", "561"]
+        );
+
+        // the merged tax IDs are kept with the node they were merged into
+        let pruned = crate::prune_to(&tax, &["562"], false).unwrap();
+        assert_eq!(pruned.name("469598").unwrap(), "Escherichia coli");
+        let pruned = crate::prune_away(&tax, &["561"]).unwrap();
+        assert_eq!(pruned.merged_into("469598"), None);
+        assert!(pruned.to_internal_index("469598").is_err());
+    }
+
+    #[test]
+    fn skips_merged_ids_not_in_taxonomy() {
+        let dir = tempdir().unwrap();
+        for filename in [NODES_FILENAME, NAMES_FILENAME] {
+            std::fs::copy(
+                Path::new("tests/data/").join(filename),
+                dir.path().join(filename),
+            )
+            .unwrap();
+        }
+        // merging into a tax ID that isn't there is fine, e.g. in filtered dumps
+        std::fs::write(
+            dir.path().join(MERGED_FILENAME),
+            "469598\t|\t562\t|\n3\t|\t123456789\t|\n",
+        )
+        .unwrap();
+        let (tax, repairs) = load_with_options(dir.path(), &LoadOptions::default()).unwrap();
+        assert_eq!(tax.merged_into("469598"), Some("562"));
+        assert_eq!(tax.merged_into("3"), None);
+        assert_eq!(repairs.len(), 1);
+
+        // but merging a tax ID that is still a node isn't
+        std::fs::write(
+            dir.path().join(MERGED_FILENAME),
+            "469598\t|\t562\t|\n3\t|\t123456789\t|\n2\t|\t562\t|\n",
+        )
+        .unwrap();
+        assert!(load(dir.path()).is_err());
+
+        let (tax, repairs) = load_with_options(dir.path(), &LoadOptions::lenient()).unwrap();
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::SkippedLine {
+                    filename: MERGED_FILENAME.to_string(),
+                    line: 2,
+                    msg: "Tax ID 3 was merged into 123456789 which is not in the taxonomy"
+                        .to_string(),
+                },
+                Repair::SkippedLine {
+                    filename: MERGED_FILENAME.to_string(),
+                    line: 3,
+                    msg: "Tax ID 2 is still present in the taxonomy".to_string(),
+                },
+            ]
+        );
+        assert_eq!(tax.merged_into("469598"), Some("562"));
+        assert_eq!(tax.merged_into("3"), None);
+        assert!(tax.to_internal_index("3").is_err());
+        assert_eq!(tax.merged_into("2"), None);
+    }
+
+    #[test]
+    fn can_import_ncbi_node_info() {
+        let tax = load("tests/data/").unwrap();
+        let idx = tax.to_internal_index("562").unwrap();
+        let info = tax.ncbi_nodes[idx].as_ref().unwrap();
+        assert_eq!(info.embl_code, "EC");
+        assert_eq!(info.division_id, Some(0));
+        assert_eq!(info.genetic_code_id, Some(11));
+        assert_eq!(info.mitochondrial_genetic_code_id, Some(0));
+        assert!(info.genbank_hidden);
+        assert!(!info.hidden_subtree_root);
+
+        assert_eq!(tax.division("562").unwrap().unwrap().name, "Bacteria");
+        let code = tax.genetic_code("562").unwrap().unwrap();
+        assert_eq!(code.name, "Bacterial, Archaeal and Plant Plastid");
+        assert_eq!(code.translation_table.len(), 64);
+        assert!(tax.mitochondrial_genetic_code("562").unwrap().is_none());
+
+        // the columns have their own accessor and don't end up in the node data
+        let info = Taxonomy::<&str>::node_info(&tax, "562").unwrap().unwrap();
+        assert!(matches!(info, Cow::Borrowed(_)));
+        assert_eq!(info.genetic_code_id, Some(11));
+        let data = Taxonomy::<&str>::data(&tax, "562").unwrap();
+        assert!(matches!(data, Cow::Borrowed(_)));
+        assert!(data.get("genetic_code_id").is_none());
+        let pruned = crate::prune_to(&tax, &["562"], false).unwrap();
+        let pruned_idx = pruned.to_internal_index("562").unwrap();
+        assert_eq!(pruned.ncbi_nodes[pruned_idx], tax.ncbi_nodes[idx]);
+        // with the divisions and genetic codes they refer to
+        assert_eq!(pruned.division("562").unwrap().unwrap().name, "Bacteria");
+        assert_eq!(
+            pruned.genetic_code("562").unwrap(),
+            tax.genetic_code("562").unwrap()
+        );
+
+        // All the columns are written back as they were
+        let dir = tempdir().unwrap();
+        save::<&str, _, _>(&tax, dir.path()).unwrap();
+        let mut expected: Vec<String> = std::fs::read_to_string("tests/data/nodes.dmp")
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let mut saved: Vec<String> = std::fs::read_to_string(dir.path().join(NODES_FILENAME))
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        expected.sort();
+        saved.sort();
+        assert_eq!(saved, expected);
+    }
+
+    #[test]
+    fn can_import_ncbi_deleted() {
+        let tax = load("tests/data/").unwrap();
+        assert!(tax.is_deleted("2795424"));
+        assert!(!tax.is_deleted("562"));
+        assert_eq!(
+            tax.to_internal_index("2795424").unwrap_err().kind,
+            ErrorKind::DeletedTaxId("2795424".to_owned())
+        );
+        assert_eq!(
+            tax.to_internal_index("123456789").unwrap_err().kind,
+            ErrorKind::NoSuchTaxId("123456789".to_owned())
+        );
+
+        // the deleted tax IDs are kept when pruning
+        let pruned = crate::prune_to(&tax, &["562"], false).unwrap();
+        assert_eq!(
+            pruned.to_internal_index("2795424").unwrap_err().kind,
+            ErrorKind::DeletedTaxId("2795424".to_owned())
+        );
+    }
+
+    fn build_archive(filenames: &[&str], prefix: &str) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for filename in filenames {
+            let content = std::fs::read(Path::new("tests/data/").join(filename)).unwrap();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("{}{}", prefix, filename), &content[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn can_import_ncbi_archive() {
+        let from_dir = load("tests/data/").unwrap();
+        let tar = build_archive(
+            &[
+                "gtdb_sample.tsv",
+                NAMES_FILENAME,
+                NODES_FILENAME,
+                MERGED_FILENAME,
+                DELNODES_FILENAME,
+            ],
+            "",
+        );
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&tar).unwrap();
+        let tar_gz = encoder.finish().unwrap();
+
+        for archive in [&tar, &tar_gz] {
+            let tax = load_archive(&archive[..]).unwrap();
+            assert_eq!(
+                Taxonomy::<&str>::len(&tax),
+                Taxonomy::<&str>::len(&from_dir)
+            );
+            assert_eq!(tax.name("562").unwrap(), "Escherichia coli");
+            assert_eq!(tax.merged_into("469598"), Some("562"));
+            assert!(tax.is_deleted("2795424"));
+        }
+
+        // files in a sub directory are found too
+        let tar = build_archive(&[NAMES_FILENAME, NODES_FILENAME], "taxdump/");
+        let tax = load_archive(&tar[..]).unwrap();
+        assert_eq!(tax.name("562").unwrap(), "Escherichia coli");
+
+        let tar = build_archive(&[NODES_FILENAME], "");
+        assert!(load_archive(&tar[..]).is_err());
+    }
+
+    #[test]
+    fn can_import_ncbi_from_readers() {
+        let nodes = std::fs::read("tests/data/nodes.dmp").unwrap();
+        let names = std::fs::read("tests/data/names.dmp").unwrap();
+        let tax = load_from_readers(&nodes[..], &names[..]).unwrap();
+        assert_eq!(tax.name("562").unwrap(), "Escherichia coli");
+        assert_eq!(tax.rank("562").unwrap(), TaxRank::Species);
+    }
+
+    #[test]
+    fn can_import_ncbi_host_and_type_material() {
+        let tax = load("tests/data/").unwrap();
+        let data = tax.data("10239").unwrap();
+        assert_eq!(
+            data["hosts"],
+            serde_json::json!(["bacteria", "vertebrates"])
+        );
+        let data = tax.data("562").unwrap();
+        assert!(data.get("hosts").is_none());
+        assert_eq!(
+            data["type_material"],
+            serde_json::json!([
+                {"name": "Escherichia coli", "type": "neotype", "identifier": "ATCC 11775"},
+                {"name": "Escherichia coli", "type": "neotype", "identifier": "DSM 30083"},
+            ])
+        );
+    }
+
+    #[test]
+    fn can_export_ncbi_lineages() {
+        let tax = load("tests/data/").unwrap();
+        let out = tempdir().unwrap();
+        save_lineages::<&str, _, _>(&tax, out.path()).unwrap();
+
+        let ranked = std::fs::read_to_string(out.path().join(RANKEDLINEAGE_FILENAME)).unwrap();
+        assert_eq!(ranked.lines().count(), 10);
+        assert!(ranked.contains(
+            "562\t|\tEscherichia coli\t|\t\t|\tEscherichia\t|\tEnterobacteriaceae\t|\t\
+            Enterobacterales\t|\tGammaproteobacteria\t|\tProteobacteria\t|\t\t|\tBacteria\t|\n"
+        ));
+        assert!(ranked.contains("1\t|\troot\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\t\t|\n"));
+
+        let full_names =
+            std::fs::read_to_string(out.path().join(FULLNAMELINEAGE_FILENAME)).unwrap();
+        assert!(full_names.contains(
+            "561\t|\tEscherichia\t|\tcellular organisms; Bacteria; Proteobacteria; \
+            Gammaproteobacteria; Enterobacterales; Enterobacteriaceae; \t|\n"
+        ));
+        assert!(full_names.contains("131567\t|\tcellular organisms\t|\t\t|\n"));
+
+        let tax_ids = std::fs::read_to_string(out.path().join(TAXIDLINEAGE_FILENAME)).unwrap();
+        assert!(tax_ids.contains("562\t|\t131567 2 1224 1236 91347 543 561 \t|\n"));
+        assert!(tax_ids.contains("1\t|\t\t|\n"));
+    }
+
+    #[test]
+    fn can_import_filtered_ncbi() {
+        // 1236 is missing, and the last line of nodes.dmp is truncated
+        let nodes =
+            "1\t|\t1\t|\tno rank\t|\t\t|\t8\t|\t0\t|\t1\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+2\t|\t1\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+1224\t|\t2\t|\tphylum\t|\t\t|\t0\t|\t1\t|\t11\t|\t1\t|\t0\t|\t1\t|\t0\t|\t0\t|\t\t|
+91347\t|\t1236\t|\torder\t|\t\t|\t0\t|\t1\t|\t11\t|\t1\t|\t0\t|\t1\t|\t0\t|\t0\t|\t\t|
+543\t|\t91347\t|\tfamily\t|\t\t|\t0\t|\t1\t|\t11\t|\t1\t|\t0\t|\t1\t|\t0\t|\t0\t|\t\t|
+561\t|\t543\t|\tgenus\t|\t\t|\t0\t|\t1\t|\t11\t|\t1\t|\t0\t|\t1\t|\t0\t|\t0\t|\t\t|
+562\t|\t561\t|\tspecies
+";
+        let names = "1\t|\troot\t|\t\t|\tscientific name\t|
+2\t|\tBacteria\t|\tBacteria <prokaryotes>\t|\tscientific name\t|
+1224\t|\tProteobacteria\t|\t\t|\tscientific name\t|
+1236\t|\tGammaproteobacteria\t|\t\t|\tscientific name\t|
+91347\t|\tEnterobacterales\t|\t\t|\tscientific name\t|
+543\t|\tEnterobacteriaceae\t|\t\t|\tscientific name\t|
+561\t|\tEscherichia\t|\t\t|\tscientific name\t|
+562\t|\tEscherichia coli\t|\t\t|\tscientific name\t|
+";
+        assert!(load_from_readers(nodes.as_bytes(), names.as_bytes()).is_err());
+        let options = LoadOptions {
+            skip_malformed_lines: true,
+            ..Default::default()
+        };
+        assert!(
+            load_from_readers_with_options(nodes.as_bytes(), names.as_bytes(), &options).is_err()
+        );
+
+        let (tax, repairs) = load_from_readers_with_options(
+            nodes.as_bytes(),
+            names.as_bytes(),
+            &LoadOptions::lenient(),
+        )
+        .unwrap();
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::SkippedLine {
+                    filename: NODES_FILENAME.to_string(),
+                    line: 7,
+                    msg: "Not enough fields in nodes.dmp; bad line?".to_string(),
+                },
+                Repair::ReattachedOrphan {
+                    tax_id: "91347".to_string(),
+                    missing_parent_id: "1236".to_string(),
+                    new_parent_id: UNPLACED_TAX_ID.to_string(),
+                },
+                Repair::SkippedLine {
+                    filename: NAMES_FILENAME.to_string(),
+                    line: 4,
+                    msg: "Tax ID 1236 could not be found in nodes.dmp".to_string(),
+                },
+                Repair::SkippedLine {
+                    filename: NAMES_FILENAME.to_string(),
+                    line: 8,
+                    msg: "Tax ID 562 could not be found in nodes.dmp".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            tax.lineage("561").unwrap(),
+            vec!["561", "543", "91347", UNPLACED_TAX_ID, "1"]
+        );
+        assert_eq!(tax.name(UNPLACED_TAX_ID).unwrap(), UNPLACED_TAX_ID);
+
+        let options = LoadOptions {
+            orphans: OrphanPolicy::AttachToRoot,
+            skip_malformed_lines: true,
+        };
+        let (tax, _) =
+            load_from_readers_with_options(nodes.as_bytes(), names.as_bytes(), &options).unwrap();
+        assert_eq!(tax.parent("91347").unwrap(), Some(("1", 1.)));
+        assert_eq!(Taxonomy::<&str>::len(&tax), 6);
+    }
+
+    #[test]
+    fn can_import_ncbi_root_anywhere() {
+        // the root isn't on the first line and a malformed line comes before the orphan
+        let nodes =
+            "2\t|\t1\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+bad line
+1\t|\t1\t|\tno rank\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+91347\t|\t1236\t|\torder\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+";
+        let options = LoadOptions {
+            skip_malformed_lines: true,
+            ..Default::default()
+        };
+        let err =
+            load_from_readers_with_options(nodes.as_bytes(), "".as_bytes(), &options).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::ImportError {
+                line: 4,
+                msg: "Parent ID 1236 could not be found in nodes.dmp".to_owned(),
+            }
+        );
+
+        let (tax, _) = load_from_readers_with_options(
+            nodes.as_bytes(),
+            "".as_bytes(),
+            &LoadOptions::lenient(),
+        )
+        .unwrap();
+        assert_eq!(Taxonomy::<&str>::root(&tax), "1");
+        assert_eq!(tax.lineage("2").unwrap(), vec!["2", "1"]);
+        assert_eq!(
+            tax.lineage("91347").unwrap(),
+            vec!["91347", UNPLACED_TAX_ID, "1"]
+        );
+
+        // only the root can be its own parent
+        let nodes =
+            "1\t|\t1\t|\tno rank\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+2\t|\t2\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+";
+        let err = load_from_readers(nodes.as_bytes(), "".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::ImportError {
+                line: 2,
+                msg: "Tax ID 2 is its own parent but isn't the root".to_owned(),
+            }
+        );
+
+        // without a root there's nothing to attach the other nodes to
+        let nodes =
+            "2\t|\t1\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+";
+        let err = load_from_readers_with_options(
+            nodes.as_bytes(),
+            "".as_bytes(),
+            &LoadOptions::lenient(),
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::InvalidTaxonomy(_)));
+    }
+
+    #[test]
+    fn can_split_dmp_lines() {
+        assert_eq!(
+            split_dmp_line::<4>("1\t|\troot\t|\t\t|\tscientific name\t|"),
+            (["1", "root", "", "scientific name"], 4)
+        );
+        // pipes in names are not separators
+        assert_eq!(
+            split_dmp_line::<2>("12\t|\tA|B\t|\tsynonym\t|"),
+            (["12", "A|B"], 3)
+        );
+        assert_eq!(split_dmp_line::<3>("562\t|\t561"), (["562", "561", ""], 2));
+        assert_eq!(split_dmp_line::<2>(""), (["", ""], 1));
+
+        let lines: Vec<&str> = dmp_lines("a\r\nb\n\nc").collect();
+        assert_eq!(lines, vec!["a", "b", "", "c"]);
+    }
+
+    #[test]
+    fn cannot_import_duplicated_ncbi_nodes() {
+        let nodes =
+            "1\t|\t1\t|\tno rank\t|\t\t|\t8\t|\t0\t|\t1\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+2\t|\t1\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+2\t|\t1\t|\tsuperkingdom\t|\t\t|\t0\t|\t0\t|\t11\t|\t0\t|\t0\t|\t0\t|\t0\t|\t0\t|\t\t|
+";
+        let err = load_from_readers(nodes.as_bytes(), "".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::InvalidTaxonomy("Some tax ids are duplicated: [\"2\"].".to_owned())
+        );
     }
 }
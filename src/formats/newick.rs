@@ -1,13 +1,26 @@
 use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::base::GeneralTaxonomy;
 use crate::errors::{Error, ErrorKind, TaxonomyResult};
+use crate::rank::TaxRank;
 use crate::Taxonomy;
 
-use memchr::memchr2;
-use std::collections::VecDeque;
+use serde_json::{Number, Value};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Display};
 
+/// The prefix of the comments holding NHX (New Hampshire eXtended) attributes
+const NHX_PREFIX: &str = "&&NHX";
+/// The NHX keys mapped to the tax ID, name, rank and support value of the nodes; all the
+/// other keys are stored in the node data
+const NHX_TAX_ID: &str = "T";
+const NHX_NAME: &str = "S";
+const NHX_RANK: &str = "rank";
+const NHX_SUPPORT: &str = "B";
+
 /// NewickToken is used as an intermediate during the tokenization of a Newick string.
 #[derive(PartialEq)]
 enum NewickToken {
@@ -28,13 +41,147 @@ impl NewickToken {
     }
 }
 
-pub fn save<'t, T: 't, W: Write>(
+/// A function returning the label of a node out of its tax ID and name, see [NodeLabel::Custom].
+pub type LabelFn = Arc<dyn Fn(&str, &str) -> TaxonomyResult<String>>;
+
+/// What to label the nodes with when writing Newick files.
+#[derive(Clone, Default)]
+pub enum NodeLabel {
+    /// The tax ID
+    #[default]
+    TaxId,
+    /// The name, or the tax ID if it doesn't have one
+    Name,
+    /// The tax ID and name, separated by `|` (e.g. `562|Escherichia coli`)
+    TaxIdAndName,
+    /// The result of the function
+    Custom(LabelFn),
+}
+
+impl Debug for NodeLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeLabel::TaxId => write!(f, "TaxId"),
+            NodeLabel::Name => write!(f, "Name"),
+            NodeLabel::TaxIdAndName => write!(f, "TaxIdAndName"),
+            NodeLabel::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl NodeLabel {
+    fn label(&self, tax_id: &str, name: &str) -> TaxonomyResult<String> {
+        Ok(match self {
+            NodeLabel::TaxId => tax_id.to_string(),
+            NodeLabel::Name if name.is_empty() => tax_id.to_string(),
+            NodeLabel::Name => name.to_string(),
+            NodeLabel::TaxIdAndName => format!("{}|{}", tax_id, name),
+            NodeLabel::Custom(f) => f(tax_id, name)?,
+        })
+    }
+}
+
+/// Options for writing Newick files.
+#[derive(Clone, Debug, Default)]
+pub struct SaveOptions {
+    pub label: NodeLabel,
+    /// Only label the leaves
+    pub omit_internal_labels: bool,
+    /// Don't write any branch length. Otherwise only the lengths of 0 are left out.
+    pub omit_branch_lengths: bool,
+    /// The number of decimals of the branch lengths, as short as possible if not set
+    pub precision: Option<usize>,
+    /// Label the internal nodes with their support value instead, like most phylogenetic
+    /// tools do. The internal nodes without support value are not labelled.
+    pub support_labels: bool,
+    /// Write the name, rank, support value and data of the nodes as NHX attributes after their
+    /// branch length, e.g. `562:1[&&NHX:S=Escherichia coli:rank=species]`. The data that cannot be written
+    /// (lists, objects and values containing NHX or Newick punctuation) is left out.
+    /// The tax ID is written as the `T` attribute if the nodes are not labelled by it.
+    pub nhx: bool,
+}
+
+/// Quotes a label if it has whitespace, Newick punctuation or quotes, doubling its quotes.
+fn quote_label(label: &str) -> Cow<'_, str> {
+    if label.contains(|c: char| c.is_whitespace() || "()[]',:;".contains(c)) {
+        Cow::Owned(format!("'{}'", label.replace('\'', "''")))
+    } else {
+        Cow::Borrowed(label)
+    }
+}
+
+/// Whether that key or value can be written in a NHX comment.
+fn is_nhx_safe(value: &str) -> bool {
+    !value.is_empty() && !value.contains(|c| "[]:=,;()".contains(c))
+}
+
+/// The `[&&NHX:...]` comment for that node, or [None] if it doesn't have any attribute.
+fn nhx_comment<'t, T>(
+    taxonomy: &'t impl Taxonomy<'t, T>,
+    node: T,
+    with_tax_id: bool,
+) -> TaxonomyResult<Option<String>>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    let mut attributes = Vec::new();
+    let tax_id = node.to_string();
+    if with_tax_id && is_nhx_safe(&tax_id) {
+        attributes.push(format!("{}={}", NHX_TAX_ID, tax_id));
+    }
+    let name = taxonomy.name(node.clone())?;
+    if is_nhx_safe(name) {
+        attributes.push(format!("{}={}", NHX_NAME, name));
+    }
+    let rank = taxonomy.rank(node.clone())?;
+    if rank != TaxRank::Unspecified {
+        attributes.push(format!("{}={}", NHX_RANK, rank.to_ncbi_rank()));
+    }
+    if let Some(support) = taxonomy.support(node.clone())? {
+        attributes.push(format!("{}={}", NHX_SUPPORT, support));
+    }
+    let data = taxonomy.data(node)?;
+    let mut keys: Vec<&String> = data.keys().collect();
+    keys.sort_unstable();
+    for key in keys {
+        let value = match &data[key] {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            _ => continue,
+        };
+        if is_nhx_safe(key) && is_nhx_safe(&value) {
+            attributes.push(format!("{}={}", key, value));
+        }
+    }
+
+    if attributes.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format!("[{}:{}]", NHX_PREFIX, attributes.join(":"))))
+}
+
+/// Write a Taxonomy in the Newick format, labelling the nodes by their tax ID.
+pub fn save<'t, T, W: Write>(
     writer: &mut W,
     taxonomy: &'t impl Taxonomy<'t, T>,
     root_node: Option<T>,
 ) -> TaxonomyResult<()>
 where
-    T: Clone + Debug + Display + PartialEq,
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    save_with_options(writer, taxonomy, root_node, &SaveOptions::default())
+}
+
+/// Same as [save] but with the given [SaveOptions], e.g. to write NHX attributes.
+pub fn save_with_options<'t, T, W: Write>(
+    writer: &mut W,
+    taxonomy: &'t impl Taxonomy<'t, T>,
+    root_node: Option<T>,
+    options: &SaveOptions,
+) -> TaxonomyResult<()>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
 {
     let root_node = root_node.unwrap_or_else(|| taxonomy.root());
     let mut out_buf = VecDeque::new();
@@ -44,11 +191,32 @@ where
             out_buf.push_back(NewickToken::Start);
         } else {
             out_buf.push_back(NewickToken::End);
-            let mut name: String = node.to_string().split_whitespace().collect();
-            if let Some((_, dist)) = taxonomy.parent(node)? {
-                if dist > 0.0 {
+            let mut name = String::new();
+            let is_leaf = taxonomy.children(node.clone())?.is_empty();
+            if options.support_labels && !is_leaf {
+                if let Some(support) = taxonomy.support(node.clone())? {
+                    name.push_str(&support.to_string());
+                }
+            } else if !options.omit_internal_labels || is_leaf {
+                let label = options
+                    .label
+                    .label(&node.to_string(), taxonomy.name(node.clone())?)?;
+                name.push_str(&quote_label(&label));
+            }
+            if let Some((_, dist)) = taxonomy.parent(node.clone())? {
+                if dist > 0.0 && !options.omit_branch_lengths {
                     name.push(':');
-                    name.push_str(&dist.to_string());
+                    match options.precision {
+                        Some(precision) => name.push_str(&format!("{:.*}", precision, dist)),
+                        None => name.push_str(&dist.to_string()),
+                    }
+                }
+            }
+            if options.nhx {
+                let with_tax_id = !matches!(options.label, NodeLabel::TaxId)
+                    || (options.support_labels && !is_leaf);
+                if let Some(comment) = nhx_comment(taxonomy, node, with_tax_id)? {
+                    name.push_str(&comment);
                 }
             }
             out_buf.push_back(NewickToken::NameDist(name));
@@ -80,85 +248,366 @@ where
     Ok(())
 }
 
-/// Read Newick format into a Taxonomy object out of a `reader`.
+/// Options for reading Newick files.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Read the numeric labels of the internal nodes (e.g. `(A,B)95`) as their support value
+    /// rather than as their tax ID, like most phylogenetic tools write them.
+    pub internal_support: bool,
+    /// Give the nodes without label a tax ID made of this prefix and a counter (e.g. `node1`)
+    /// instead of an empty one, see [GeneralTaxonomy::synthesize_ids].
+    pub id_prefix: Option<String>,
+    /// With `id_prefix`, use the name of the nodes without label (from their NHX `S`
+    /// attribute) as their tax ID when possible.
+    pub names_as_ids: bool,
+}
+
+/// A token of a Newick string, see [Tokenizer].
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Colon,
+    Semicolon,
+    /// A label, with its quotes removed and its escaped quotes unescaped
+    Label(String),
+    /// The content of a `[...]` comment
+    Comment(String),
+}
+
+/// Splits a Newick string into [Token]s, along with the byte offset they start at.
 ///
-/// Still somewhat experimental and may not support all Newick features.
-pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
-    // TODO: handle empty tax ids?
-    // TODO: handle quoted labels
-    // TODO: handle ending `;`
-    let mut buffer: Vec<u8> = Vec::new();
-    reader.read_to_end(&mut buffer)?;
+/// Whitespace (including newlines) is skipped outside of labels. Labels can be quoted with
+/// `'`, with `''` standing for a quote; quoted labels can contain any character, including
+/// the Newick punctuation. Unquoted labels end at the first punctuation character and are
+/// trimmed, but are otherwise kept as they are (underscores are not turned into spaces).
+struct Tokenizer<'a> {
+    buffer: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        Tokenizer { buffer, pos: 0 }
+    }
+
+    /// Builds an import error for the given byte offset, with its line and column.
+    fn error_at(&self, offset: usize, msg: &str) -> Error {
+        let before = &self.buffer[..offset.min(self.buffer.len())];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        Error::new(ErrorKind::ImportError {
+            line,
+            msg: format!(
+                "{} (byte offset {}, column {})",
+                msg,
+                offset,
+                offset - line_start + 1
+            ),
+        })
+    }
+
+    fn to_str(&self, start: usize, end: usize) -> TaxonomyResult<&'a str> {
+        std::str::from_utf8(&self.buffer[start..end])
+            .map_err(|_| self.error_at(start, "Could not parse label as unicode"))
+    }
+
+    fn next_token(&mut self) -> TaxonomyResult<Option<(Token, usize)>> {
+        while self.pos < self.buffer.len() && self.buffer[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        let byte = match self.buffer.get(start) {
+            Some(b) => *b,
+            None => return Ok(None),
+        };
+        self.pos += 1;
+        let token = match byte {
+            b'(' => Token::Open,
+            b')' => Token::Close,
+            b',' => Token::Comma,
+            b':' => Token::Colon,
+            b';' => Token::Semicolon,
+            b'[' => {
+                let end = memchr::memchr(b']', &self.buffer[self.pos..])
+                    .map(|x| x + self.pos)
+                    .ok_or_else(|| self.error_at(start, "Unterminated comment"))?;
+                let comment = self.to_str(self.pos, end)?;
+                self.pos = end + 1;
+                Token::Comment(comment.to_string())
+            }
+            b']' => return Err(self.error_at(start, "Unexpected ']' outside of a comment")),
+            b'\'' => {
+                let mut label = String::new();
+                loop {
+                    let end = memchr::memchr(b'\'', &self.buffer[self.pos..])
+                        .map(|x| x + self.pos)
+                        .ok_or_else(|| self.error_at(start, "Unterminated quoted label"))?;
+                    label.push_str(self.to_str(self.pos, end)?);
+                    self.pos = end + 1;
+                    // `''` is an escaped quote, anything else ends the label
+                    if self.buffer.get(self.pos) == Some(&b'\'') {
+                        label.push('\'');
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Token::Label(label)
+            }
+            _ => {
+                while self.pos < self.buffer.len() && !b"()[]',:;".contains(&self.buffer[self.pos])
+                {
+                    self.pos += 1;
+                }
+                Token::Label(self.to_str(start, self.pos)?.trim_end().to_string())
+            }
+        };
+        Ok(Some((token, start)))
+    }
+}
 
-    // make the root node explicitly first
+/// Where the parser is in the description of the current node.
+#[derive(Clone, Copy, PartialEq)]
+enum NodeState {
+    /// Nothing seen yet
+    Start,
+    /// After the `)` closing its children
+    Closed,
+    /// After its label
+    Labeled,
+    /// After its branch length
+    WithLength,
+}
+
+/// Parses the next tree of the `tokenizer`, returns [None] if there is nothing left.
+fn parse_tree(
+    tokenizer: &mut Tokenizer,
+    options: &LoadOptions,
+) -> TaxonomyResult<Option<GeneralTaxonomy>> {
+    // the root node is explicitly first
     let mut tax_ids: Vec<String> = vec!["".to_string()];
     let mut parent_ids: Vec<usize> = vec![0];
     let mut dists: Vec<f32> = vec![1.];
-    let mut cur_lineage: Vec<usize> = Vec::new();
+    let mut names: Vec<String> = vec!["".to_string()];
+    let mut ranks: Vec<TaxRank> = vec![TaxRank::Unspecified];
+    let mut data: Vec<HashMap<String, Value>> = vec![HashMap::new()];
+    let mut supports: Vec<Option<f32>> = vec![None];
+    // the ancestors of the current node
+    let mut stack: Vec<usize> = Vec::new();
+    let mut cur_node = 0;
+    let mut state = NodeState::Start;
+    let mut seen_token = false;
 
-    let mut cur_pos = 0;
-    while cur_pos < buffer.len() {
-        match buffer[cur_pos] {
-            b'(' => {
-                // make a new node
+    loop {
+        let (token, offset) = match tokenizer.next_token()? {
+            Some(t) => t,
+            None if !seen_token => return Ok(None),
+            None if stack.is_empty() => break,
+            None => return Err(tokenizer.error_at(tokenizer.buffer.len(), "Missing closing ')'")),
+        };
+        match token {
+            // empty trees and comments between trees don't start a new tree
+            Token::Semicolon if !seen_token => continue,
+            Token::Comment(_) => {}
+            _ => seen_token = true,
+        }
+        match token {
+            Token::Open => {
+                if state != NodeState::Start {
+                    return Err(tokenizer.error_at(offset, "Unexpected '('"));
+                }
                 tax_ids.push("".to_string());
-                parent_ids.push(*cur_lineage.last().unwrap_or(&0));
+                parent_ids.push(cur_node);
                 dists.push(1.);
-                cur_lineage.push(tax_ids.len() - 1);
-                cur_pos += 1;
+                names.push("".to_string());
+                ranks.push(TaxRank::Unspecified);
+                data.push(HashMap::new());
+                supports.push(None);
+                stack.push(cur_node);
+                cur_node = tax_ids.len() - 1;
             }
-            b',' => {
-                // finish up with the current node
-                cur_lineage.pop();
-                // make a new node
+            Token::Comma => {
+                let parent = *stack
+                    .last()
+                    .ok_or_else(|| tokenizer.error_at(offset, "Unexpected ',' outside of '()'"))?;
                 tax_ids.push("".to_string());
-                parent_ids.push(*cur_lineage.last().unwrap_or(&0));
+                parent_ids.push(parent);
                 dists.push(1.);
-                cur_lineage.push(tax_ids.len() - 1);
-                cur_pos += 1;
+                names.push("".to_string());
+                ranks.push(TaxRank::Unspecified);
+                data.push(HashMap::new());
+                supports.push(None);
+                cur_node = tax_ids.len() - 1;
+                state = NodeState::Start;
             }
-            b')' => {
-                // just go back to the parent
-                cur_lineage.pop();
-                cur_pos += 1;
+            Token::Close => {
+                cur_node = stack
+                    .pop()
+                    .ok_or_else(|| tokenizer.error_at(offset, "Unexpected ')'"))?;
+                state = NodeState::Closed;
             }
-            _ => {
-                // set the name and dist on the cur_node
-                let cur_node = *cur_lineage.last().unwrap_or(&0);
-                let pos = memchr2(b',', b')', &buffer[cur_pos..])
-                    .map(|x| x + cur_pos)
-                    .unwrap_or_else(|| buffer.len());
-                let name_dist = &buffer[cur_pos..pos];
-                let mut chunk_iter = std::str::from_utf8(name_dist)
-                    .map_err(|_| {
-                        Error::new(ErrorKind::ImportError {
-                            line: 0,
-                            msg: format!(
-                                "Could not parse name/dist \"{}\" as unicode",
-                                String::from_utf8_lossy(name_dist)
-                            ),
-                        })
-                    })?
-                    .trim_end_matches(';')
-                    .splitn(2, |x| x == ':');
-                tax_ids[cur_node] = chunk_iter.next().unwrap_or("").to_string();
-                dists[cur_node] = chunk_iter.next().unwrap_or("1").parse().map_err(|e| {
-                    Error::new(ErrorKind::ImportError {
-                        line: 0,
-                        msg: format!("Could not parse distance as a number: {}", e),
-                    })
+            Token::Label(label) => {
+                if state != NodeState::Start && state != NodeState::Closed {
+                    return Err(tokenizer.error_at(offset, "Unexpected label"));
+                }
+                match label.parse() {
+                    Ok(support) if options.internal_support && state == NodeState::Closed => {
+                        supports[cur_node] = Some(support)
+                    }
+                    _ => tax_ids[cur_node] = label,
+                }
+                state = NodeState::Labeled;
+            }
+            Token::Colon => {
+                if state == NodeState::WithLength {
+                    return Err(tokenizer.error_at(offset, "Unexpected ':'"));
+                }
+                // comments are allowed before the length
+                let mut next = tokenizer.next_token()?;
+                while let Some((Token::Comment(_), _)) = next {
+                    next = tokenizer.next_token()?;
+                }
+                let (length, length_offset) = match next {
+                    Some((Token::Label(length), length_offset)) => (length, length_offset),
+                    _ => return Err(tokenizer.error_at(offset, "Missing branch length after ':'")),
+                };
+                dists[cur_node] = length.parse().map_err(|e| {
+                    tokenizer.error_at(
+                        length_offset,
+                        &format!("Could not parse distance \"{}\" as a number: {}", length, e),
+                    )
                 })?;
-                cur_pos = pos;
+                state = NodeState::WithLength;
+            }
+            Token::Comment(comment) => {
+                let attributes = match comment.strip_prefix(NHX_PREFIX) {
+                    Some(attributes) => attributes,
+                    None => continue,
+                };
+                for attribute in attributes.split(':').filter(|x| !x.is_empty()) {
+                    let (key, value) = attribute.split_once('=').ok_or_else(|| {
+                        tokenizer
+                            .error_at(offset, &format!("Invalid NHX attribute \"{}\"", attribute))
+                    })?;
+                    match key {
+                        NHX_TAX_ID => {
+                            // the label is kept as the name
+                            if names[cur_node].is_empty() {
+                                names[cur_node] = tax_ids[cur_node].clone();
+                            }
+                            tax_ids[cur_node] = value.to_string();
+                        }
+                        NHX_NAME => names[cur_node] = value.to_string(),
+                        NHX_RANK => match TaxRank::from_str(value) {
+                            Ok(rank) => ranks[cur_node] = rank,
+                            // not an NCBI rank, keep it as is
+                            Err(_) => {
+                                data[cur_node].insert(key.to_string(), nhx_value(value));
+                            }
+                        },
+                        NHX_SUPPORT => {
+                            supports[cur_node] = Some(value.parse().map_err(|_| {
+                                tokenizer.error_at(
+                                    offset,
+                                    &format!("Could not parse support \"{}\" as a number", value),
+                                )
+                            })?)
+                        }
+                        _ => {
+                            data[cur_node].insert(key.to_string(), nhx_value(value));
+                        }
+                    }
+                }
+            }
+            Token::Semicolon => {
+                if !stack.is_empty() {
+                    return Err(tokenizer.error_at(offset, "Missing closing ')' before ';'"));
+                }
+                break;
             }
         }
     }
 
-    GeneralTaxonomy::from_arrays(tax_ids, parent_ids, None, None, Some(dists), None)
+    let mut tax = GeneralTaxonomy::from_arrays(
+        tax_ids,
+        parent_ids,
+        Some(names),
+        Some(ranks),
+        Some(dists),
+        Some(data),
+    )?;
+    if let Some(prefix) = &options.id_prefix {
+        tax.synthesize_ids(prefix, options.names_as_ids);
+    }
+    tax.support_values = supports;
+    Ok(Some(tax))
+}
+
+/// Converts a NHX value to JSON: numbers are numbers, anything else is a string.
+fn nhx_value(value: &str) -> Value {
+    if let Ok(n) = value.parse::<i64>() {
+        return n.into();
+    }
+    match value.parse::<f64>().ok().and_then(Number::from_f64) {
+        Some(n) => Value::Number(n),
+        None => value.into(),
+    }
+}
+
+/// Read Newick format into a Taxonomy object out of a `reader`.
+///
+/// Only the first tree is read if there are several, see [load_all] to read all of them.
+/// The final `;` is optional and the nodes without label get an empty tax ID, see
+/// [LoadOptions::id_prefix] to give them one.
+///
+/// Comments are ignored, except for the NHX ones (`[&&NHX:key=value:...]`): `T` sets the
+/// tax ID of the node (the label then becomes its name), `S` its name, `rank` its rank and
+/// `B` its support value. The other attributes are stored in the node data.
+pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
+    load_with_options(reader, &LoadOptions::default())
+}
+
+/// Same as [load] but with the given [LoadOptions], e.g. to read the support values.
+pub fn load_with_options<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> TaxonomyResult<GeneralTaxonomy> {
+    let mut buffer: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let mut tokenizer = Tokenizer::new(&buffer);
+    parse_tree(&mut tokenizer, options)?.ok_or_else(|| tokenizer.error_at(0, "No tree found"))
+}
+
+/// Read all the `;` separated trees of a Newick file out of a `reader`.
+pub fn load_all<R: Read>(reader: &mut R) -> TaxonomyResult<Vec<GeneralTaxonomy>> {
+    load_all_with_options(reader, &LoadOptions::default())
+}
+
+/// Same as [load_all] but with the given [LoadOptions].
+pub fn load_all_with_options<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> TaxonomyResult<Vec<GeneralTaxonomy>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let mut tokenizer = Tokenizer::new(&buffer);
+    let mut trees = Vec::new();
+    while let Some(tree) = parse_tree(&mut tokenizer, options)? {
+        trees.push(tree);
+    }
+    Ok(trees)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::base::InternalIndex;
     use crate::taxonomy::Taxonomy;
 
     #[test]
@@ -193,4 +642,324 @@ mod tests {
         assert_eq!(tax.parent("D").unwrap(), Some(("E", 0.4)));
         assert_eq!(tax.parent("E").unwrap(), Some(("F", 0.5)));
     }
+
+    #[test]
+    fn test_load_newick_labels_and_comments() {
+        let newick_str = b"[a tree]\n(\n  'E. coli K-12':0.1[&comment],\n  'it''s:(weird)' : 0.2 ,\n  (C , D [x]:1e-2)Inner node:0.5\n)'root';";
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax), 6);
+        assert_eq!(Taxonomy::<&str>::root(&tax), "root");
+        assert_eq!(tax.parent("E. coli K-12").unwrap(), Some(("root", 0.1)));
+        assert_eq!(tax.parent("it's:(weird)").unwrap(), Some(("root", 0.2)));
+        assert_eq!(tax.parent("D").unwrap(), Some(("Inner node", 0.01)));
+        assert_eq!(tax.parent("C").unwrap(), Some(("Inner node", 1.)));
+        assert_eq!(tax.parent("Inner node").unwrap(), Some(("root", 0.5)));
+    }
+
+    #[test]
+    fn test_load_newick_nhx() {
+        let newick_str = b"((A:0.1[&&NHX:S=Escherichia coli:T=562:B=95],B[&&NHX:rank=genus:D=N:E=1.5]:0.2)C[&&NHX:B=100])D;";
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        assert_eq!(tax.name("562").unwrap(), "Escherichia coli");
+        assert_eq!(tax.parent("562").unwrap(), Some(("C", 0.1)));
+        assert_eq!(tax.support("562").unwrap(), Some(95.));
+        assert!(tax.to_internal_index("A").is_err());
+        assert_eq!(tax.rank("B").unwrap(), TaxRank::Genus);
+        assert_eq!(tax.data("B").unwrap()["D"], "N");
+        assert_eq!(tax.data("B").unwrap()["E"], 1.5);
+        assert_eq!(tax.support("C").unwrap(), Some(100.));
+        assert!(tax.data("C").unwrap().is_empty());
+
+        // the label is the name if there is no S
+        let tax = load(&mut b"(A[&&NHX:T=562])B;".as_ref()).unwrap();
+        assert_eq!(tax.name("562").unwrap(), "A");
+
+        assert!(load(&mut b"(A[&&NHX:B])C;".as_ref()).is_err());
+
+        // ranks that aren't NCBI ranks are kept in the data
+        let tax = load(&mut b"(A[&&NHX:rank=unknown-rank])C;".as_ref()).unwrap();
+        assert_eq!(tax.rank("A").unwrap(), TaxRank::Unspecified);
+        assert_eq!(tax.data("A").unwrap()["rank"], "unknown-rank");
+
+        assert!(load(&mut b"(A[&&NHX:B=high])C;".as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_load_newick_support() {
+        let newick_str = b"((A:0.1,B:0.2)95:0.3,(C,D)Inner:0.4,(E,12)0.5[&&NHX:T=F])G;";
+        let options = LoadOptions {
+            internal_support: true,
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert_eq!(tax.support("F").unwrap(), Some(0.5));
+        assert_eq!(tax.support("Inner").unwrap(), None);
+        // leaves keep their numeric labels
+        assert_eq!(tax.parent("12").unwrap(), Some(("F", 1.)));
+        // the node without tax ID keeps its support value
+        assert_eq!(tax.parent("A").unwrap(), Some(("", 0.1)));
+        assert_eq!(
+            Taxonomy::<InternalIndex>::support(&tax, 1).unwrap(),
+            Some(95.)
+        );
+
+        // and so does it if it gets a tax ID
+        let options = LoadOptions {
+            internal_support: true,
+            id_prefix: Some("node".to_string()),
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert_eq!(tax.parent("A").unwrap(), Some(("node1", 0.1)));
+        assert_eq!(tax.support("node1").unwrap(), Some(95.));
+
+        // numeric labels are tax IDs by default
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        assert_eq!(tax.parent("A").unwrap(), Some(("95", 0.1)));
+        assert_eq!(tax.support("95").unwrap(), None);
+
+        // the supports of unlabelled nodes are written back
+        let newick_str = b"((A,B)95,(C,D)80);";
+        let options = LoadOptions {
+            internal_support: true,
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert_eq!(
+            tax.support_values,
+            vec![None, Some(95.), None, None, Some(80.), None, None]
+        );
+        let mut bytes = Vec::new();
+        let save_options = SaveOptions {
+            omit_branch_lengths: true,
+            support_labels: true,
+            ..Default::default()
+        };
+        save_with_options::<InternalIndex, _>(&mut bytes, &tax, None, &save_options).unwrap();
+        let tax2 = load_with_options(&mut bytes.as_slice(), &options).unwrap();
+        // the leaves are labelled by their internal index, e.g. A is 2 and D is 6
+        for (leaf, support) in [("2", 95.), ("6", 80.)] {
+            let idx = tax2.to_internal_index(leaf).unwrap();
+            let parent = Taxonomy::<InternalIndex>::parent(&tax2, idx)
+                .unwrap()
+                .unwrap()
+                .0;
+            assert_eq!(tax2.support_values[parent], Some(support));
+        }
+    }
+
+    #[test]
+    fn test_write_newick_support() {
+        let newick_str = b"((A:0.1,B:0.2)C:0.3[&&NHX:B=95],(D,E)F)G;";
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        let write = |options: SaveOptions| {
+            let mut bytes = Vec::new();
+            save_with_options::<&str, _>(&mut bytes, &tax, None, &options).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        let support_labels = SaveOptions {
+            support_labels: true,
+            ..Default::default()
+        };
+        let newick = write(support_labels.clone());
+        assert_eq!(newick, "((E:1,D:1):1,(B:0.2,A:0.1)95:0.3);");
+        let options = LoadOptions {
+            internal_support: true,
+            ..Default::default()
+        };
+        let tax2 = load_with_options(&mut newick.as_bytes(), &options).unwrap();
+        assert_eq!(tax2.parent("A").unwrap(), Some(("", 0.1)));
+
+        assert_eq!(
+            write(SaveOptions {
+                nhx: true,
+                ..support_labels
+            }),
+            "((E:1,D:1):1[&&NHX:T=F],(B:0.2,A:0.1)95:0.3[&&NHX:T=C:B=95])[&&NHX:T=G];"
+        );
+        let newick = write(SaveOptions {
+            nhx: true,
+            ..Default::default()
+        });
+        assert_eq!(newick, "((E:1,D:1)F:1,(B:0.2,A:0.1)C:0.3[&&NHX:B=95])G;");
+        let tax2 = load(&mut newick.as_bytes()).unwrap();
+        assert_eq!(tax2.support("C").unwrap(), Some(95.));
+    }
+
+    #[test]
+    fn test_write_newick_nhx() {
+        let newick_str =
+            b"((A:0.1[&&NHX:S=Escherichia coli:rank=species:B=95:Q=x y],B:0.2[&&NHX:S=B:D=N])C)D;";
+        let mut tax = load(&mut newick_str.as_ref()).unwrap();
+        let idx = tax.to_internal_index("B").unwrap();
+        tax.data[idx].insert("list".to_string(), serde_json::json!([1, 2]));
+        tax.data[idx].insert("bad".to_string(), "a:b".into());
+
+        let options = SaveOptions {
+            nhx: true,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        save_with_options(&mut bytes, &tax, Some("C"), &options).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&bytes).unwrap(),
+            "(B:0.2[&&NHX:S=B:D=N],A:0.1[&&NHX:S=Escherichia coli:rank=species:B=95:Q=x y])C:1;"
+        );
+
+        // the annotations survive a round trip
+        let mut bytes = Vec::new();
+        save_with_options::<&str, _>(&mut bytes, &tax, None, &options).unwrap();
+        let tax2 = load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(tax2.name("A").unwrap(), "Escherichia coli");
+        assert_eq!(tax2.rank("A").unwrap(), TaxRank::Species);
+        assert_eq!(tax2.data("A").unwrap(), tax.data("A").unwrap());
+    }
+
+    #[test]
+    fn test_write_newick_options() {
+        let newick_str =
+            b"((A:0.123456[&&NHX:S=Escherichia coli],'B b':0.2[&&NHX:S=it's])C:1.5[&&NHX:S=C])D;";
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        let write = |options: SaveOptions| {
+            let mut bytes = Vec::new();
+            save_with_options::<&str, _>(&mut bytes, &tax, None, &options).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        assert_eq!(
+            write(SaveOptions::default()),
+            "(('B b':0.2,A:0.123456)C:1.5)D;"
+        );
+        assert_eq!(
+            write(SaveOptions {
+                label: NodeLabel::Name,
+                ..Default::default()
+            }),
+            "(('it''s':0.2,'Escherichia coli':0.123456)C:1.5)D;"
+        );
+        assert_eq!(
+            write(SaveOptions {
+                label: NodeLabel::TaxIdAndName,
+                omit_internal_labels: true,
+                precision: Some(2),
+                ..Default::default()
+            }),
+            "(('B b|it''s':0.20,'A|Escherichia coli':0.12):1.50);"
+        );
+        assert_eq!(
+            write(SaveOptions {
+                label: NodeLabel::Custom(Arc::new(|tax_id, _| Ok(tax_id.to_lowercase()))),
+                omit_branch_lengths: true,
+                ..Default::default()
+            }),
+            "(('b b',a)c)d;"
+        );
+        assert_eq!(
+            write(SaveOptions {
+                label: NodeLabel::Name,
+                omit_branch_lengths: true,
+                nhx: true,
+                ..Default::default()
+            }),
+            "(('it''s'[&&NHX:T=B b:S=it's],'Escherichia coli'[&&NHX:T=A:S=Escherichia coli])C[&&NHX:T=C:S=C])D[&&NHX:T=D];"
+        );
+
+        // the quoted labels are read back as they were
+        let tax2 = load(&mut write(SaveOptions::default()).as_bytes()).unwrap();
+        assert_eq!(tax2.parent("B b").unwrap(), Some(("C", 0.2)));
+    }
+
+    #[test]
+    fn test_load_newick_synthesized_ids() {
+        let newick_str = b"((A,B),(C,D)[&&NHX:S=Inner],(E,F)[&&NHX:S=A]);";
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        assert!(tax.validate_uniqueness().is_err());
+        assert!(tax.synthesized_ids.is_empty());
+
+        let options = LoadOptions {
+            id_prefix: Some("n".to_string()),
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert!(tax.validate_uniqueness().is_ok());
+        assert_eq!(Taxonomy::<&str>::root(&tax), "n1");
+        assert_eq!(tax.parent("A").unwrap(), Some(("n2", 1.)));
+        assert_eq!(tax.parent("C").unwrap(), Some(("n3", 1.)));
+        assert_eq!(tax.parent("E").unwrap(), Some(("n4", 1.)));
+        assert_eq!(tax.synthesized_ids.len(), 4);
+        // the tax IDs only depend on the tree
+        let tax2 = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert_eq!(tax2.tax_ids, tax.tax_ids);
+
+        let options = LoadOptions {
+            id_prefix: Some("n".to_string()),
+            names_as_ids: true,
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut newick_str.as_ref(), &options).unwrap();
+        assert_eq!(tax.parent("C").unwrap(), Some(("Inner", 1.)));
+        // A is already a tax ID
+        assert_eq!(tax.parent("E").unwrap(), Some(("n3", 1.)));
+        assert!(tax.synthesized_ids.contains("Inner"));
+        assert!(!tax.synthesized_ids.contains("A"));
+    }
+
+    #[test]
+    fn test_load_all_newick() {
+        let newick_str = b"(A,B)C;\n(D:1,(E)F)G;\n\nH;\n";
+        let trees = load_all(&mut newick_str.as_ref()).unwrap();
+        assert_eq!(trees.len(), 3);
+        assert_eq!(Taxonomy::<&str>::root(&trees[0]), "C");
+        assert_eq!(trees[1].parent("E").unwrap(), Some(("F", 1.)));
+        assert_eq!(Taxonomy::<&str>::len(&trees[2]), 1);
+
+        // load only reads the first one
+        let tax = load(&mut newick_str.as_ref()).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax), 3);
+
+        assert!(load_all(&mut b" \n".as_ref()).unwrap().is_empty());
+
+        // trailing comments and empty trees are not trees
+        for newick_str in ["(A,B)C;\n[comment]\n", "(A,B)C;;"] {
+            let trees = load_all(&mut newick_str.as_bytes()).unwrap();
+            assert_eq!(trees.len(), 1);
+            assert_eq!(Taxonomy::<&str>::root(&trees[0]), "C");
+        }
+    }
+
+    #[test]
+    fn test_load_invalid_newick() {
+        let error_at = |newick_str: &str| match load(&mut newick_str.as_bytes()) {
+            Err(e) => match e.kind {
+                ErrorKind::ImportError { line, msg } => (line, msg),
+                _ => panic!("Unexpected error {:?}", e),
+            },
+            Ok(_) => panic!("{} should not load", newick_str),
+        };
+
+        let (line, msg) = error_at("(A,\n B:x)C;");
+        assert_eq!(line, 2);
+        assert!(msg.contains("\"x\""));
+        assert!(msg.contains("(byte offset 7, column 4)"));
+
+        let (line, msg) = error_at("(A,B));");
+        assert_eq!(line, 1);
+        assert!(msg.starts_with("Unexpected ')' (byte offset 5, column 6)"));
+
+        assert!(error_at("(A,B;")
+            .1
+            .starts_with("Missing closing ')' before ';'"));
+        assert!(error_at("((A,B)").1.starts_with("Missing closing ')'"));
+        assert!(error_at("('A,B);")
+            .1
+            .starts_with("Unterminated quoted label"));
+        assert!(error_at("(A[,B);").1.starts_with("Unterminated comment"));
+        assert!(error_at("A,B;").1.starts_with("Unexpected ','"));
+        assert!(error_at("(A:1 'B');").1.starts_with("Unexpected label"));
+        assert!(error_at("(A:1:2);").1.starts_with("Unexpected ':'"));
+        assert!(error_at("(A:);").1.starts_with("Missing branch length"));
+        assert!(error_at("A(B);").1.starts_with("Unexpected '('"));
+        assert!(error_at("").1.starts_with("No tree found"));
+    }
 }
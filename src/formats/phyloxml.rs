@@ -1,159 +1,605 @@
-use std::io::{BufReader, Read};
+use std::io::{Read, Write};
 
-use quick_xml::events::Event;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde_json::{Map, Number, Value};
 
-use crate::base::GeneralTaxonomy;
+use crate::base::{GeneralTaxonomy, TaxName, SCIENTIFIC_NAME};
 use crate::errors::{Error, ErrorKind, TaxonomyResult};
 use crate::rank::TaxRank;
+use crate::Taxonomy;
 use std::collections::HashMap;
+use std::fmt::{Debug, Display};
 use std::str::FromStr;
 
-/// Read PhyloXML format into a Taxonomy object out of a `reader`.
-///
-/// Still somewhat experimental and may not support all PhyloXML features.
-pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
-    let mut xml_reader = quick_xml::Reader::from_reader(BufReader::new(reader));
-    xml_reader.trim_text(true);
+/// The namespace of the PhyloXML documents
+const PHYLOXML_NAMESPACE: &str = "http://www.phyloxml.org";
+/// The `ref` prefix of the `<property>` elements holding the node data
+const PROPERTY_PREFIX: &str = "taxonomy:";
+/// The type of the `<confidence>` elements holding the support values
+const CONFIDENCE_TYPE: &str = "bootstrap";
 
-    // find the <phylogeny> inside the XML
-    let mut buf = Vec::new();
-    loop {
-        let evt = xml_reader.read_event_into(&mut buf)?;
-        match evt {
-            Event::Start(ref e) => {
-                if e.name().as_ref() == b"phylogeny" {
-                    break;
-                }
-            }
-            Event::Eof => {
-                return Err(Error::new(ErrorKind::ImportError {
-                    line: 0,
-                    msg: "No valid phyloxml taxonomy found".to_owned(),
-                }));
+/// The name classes of the `<common_name>`s and `<synonym>`s of the taxonomies
+const COMMON_NAME: &str = "common name";
+const SYNONYM: &str = "synonym";
+/// The data keys of the `<sequence>`s and `<events>` of the clades
+const SEQUENCES_KEY: &str = "sequences";
+const EVENTS_KEY: &str = "events";
+
+/// Options for reading PhyloXML files.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Give the clades without `<id>` a tax ID made of this prefix and a counter (e.g.
+    /// `node1`) instead of an empty one, see [GeneralTaxonomy::synthesize_ids].
+    pub id_prefix: Option<String>,
+    /// With `id_prefix`, use the name of the clades without `<id>` as their tax ID when
+    /// possible.
+    pub names_as_ids: bool,
+}
+
+/// What has been read of a `<clade>` so far.
+struct Clade {
+    parent: usize,
+    tax_id: String,
+    name: Option<String>,
+    scientific_name: Option<String>,
+    /// The common names and synonyms of its `<taxonomy>`
+    other_names: Vec<TaxName>,
+    rank: TaxRank,
+    branch_length: f32,
+    support: Option<f32>,
+    data: HashMap<String, Value>,
+}
+
+impl Clade {
+    fn new(parent: usize, branch_length: f32) -> Self {
+        Clade {
+            parent,
+            tax_id: String::new(),
+            name: None,
+            scientific_name: None,
+            other_names: Vec::new(),
+            rank: TaxRank::Unspecified,
+            branch_length,
+            support: None,
+            data: HashMap::new(),
+        }
+    }
+}
+
+/// Reads the events of an in-memory PhyloXML document, keeping track of where they start to
+/// report the line and position of the errors.
+struct PhyloXmlReader<'a> {
+    buffer: &'a [u8],
+    reader: Reader<&'a [u8]>,
+    /// The byte offset of the last event read
+    event_start: usize,
+}
+
+impl<'a> PhyloXmlReader<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        let mut reader = Reader::from_reader(buffer);
+        reader.trim_text(true);
+        PhyloXmlReader {
+            buffer,
+            reader,
+            event_start: 0,
+        }
+    }
+
+    /// Builds an import error for the given byte offset, with its line and column.
+    fn error_at(&self, offset: usize, msg: &str) -> Error {
+        let before = &self.buffer[..offset.min(self.buffer.len())];
+        let line = before.iter().filter(|b| **b == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        Error::new(ErrorKind::ImportError {
+            line,
+            msg: format!(
+                "{} (byte offset {}, column {})",
+                msg,
+                offset,
+                offset - line_start + 1
+            ),
+        })
+    }
+
+    /// Builds an import error for the last event read.
+    fn error(&self, msg: &str) -> Error {
+        self.error_at(self.event_start, msg)
+    }
+
+    fn xml_error(&self, error: quick_xml::Error) -> Error {
+        self.error(&format!("Invalid XML: {}", error))
+    }
+
+    fn next_event(&mut self) -> TaxonomyResult<Event<'a>> {
+        // the leading whitespace is skipped by the reader
+        let position = self.reader.buffer_position();
+        self.event_start = position
+            + self.buffer[position..]
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+        self.reader.read_event().map_err(|e| self.xml_error(e))
+    }
+
+    /// Returns the unescaped text of a text event.
+    fn text(&self, text: &BytesText) -> TaxonomyResult<String> {
+        match text.unescape() {
+            Ok(text) => Ok(text.into_owned()),
+            Err(e) => Err(self.xml_error(e)),
+        }
+    }
+
+    /// Returns the unescaped value of that attribute of the element, if it has it.
+    fn attribute(&self, element: &BytesStart, name: &[u8]) -> TaxonomyResult<Option<String>> {
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(|e| self.xml_error(e.into()))?;
+            if attribute.key.as_ref() == name {
+                let value = attribute.unescape_value().map_err(|e| self.xml_error(e))?;
+                return Ok(Some(value.into_owned()));
             }
-            _ => continue,
         }
-        buf.clear();
+        Ok(None)
+    }
+
+    fn parse_number<N: FromStr>(&self, text: &str, what: &str) -> TaxonomyResult<N> {
+        text.trim().parse().map_err(|_| {
+            self.error(&format!(
+                "Could not interpret {} \"{}\" as a number",
+                what, text
+            ))
+        })
     }
+}
+
+/// Converts the text of a `<property>` to JSON according to its `datatype`, keeping it as a
+/// string if it doesn't match.
+fn property_to_value(datatype: &str, text: &str) -> Value {
+    let value = match datatype.trim_start_matches("xsd:") {
+        "boolean" => match text {
+            "true" | "1" => Some(Value::Bool(true)),
+            "false" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        "integer" | "long" | "int" | "short" | "byte" | "nonNegativeInteger"
+        | "nonPositiveInteger" | "negativeInteger" | "positiveInteger" | "unsignedLong"
+        | "unsignedInt" | "unsignedShort" | "unsignedByte" => {
+            text.parse::<i64>().ok().map(Value::from)
+        }
+        "double" | "float" | "decimal" => text
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        _ => None,
+    };
+    value.unwrap_or_else(|| text.into())
+}
 
-    let mut tax_ids: Vec<String> = vec![];
-    let mut names: Vec<Option<String>> = vec![];
-    let mut parent_ids: Vec<usize> = vec![];
-    let mut ranks: Vec<TaxRank> = vec![];
-    let mut dists: Vec<f32> = vec![];
+/// Reads a `<phylogeny>` whose start tag was just read and adds it to `trees`, followed by
+/// the phylogenies nested in it which are read as separate trees.
+fn read_phylogeny(
+    xml: &mut PhyloXmlReader,
+    trees: &mut Vec<GeneralTaxonomy>,
+    options: &LoadOptions,
+) -> TaxonomyResult<()> {
+    // keep the place of this phylogeny before the nested ones
+    let position = trees.len();
+    trees.push(GeneralTaxonomy::default());
 
+    let mut clades: Vec<Clade> = Vec::new();
+    // the clades and the elements that are currently open
     let mut cur_lineage: Vec<usize> = Vec::new();
+    let mut elements: Vec<Vec<u8>> = Vec::new();
+    // the key and datatype of the current `<property>`
+    let mut property: Option<(String, String)> = None;
+    let mut sequence: Option<Map<String, Value>> = None;
+    let mut events: Option<Map<String, Value>> = None;
 
-    let mut current_tag: Vec<u8> = b"".to_vec();
     loop {
-        match xml_reader.read_event_into(&mut buf)? {
-            Event::Start(ref e) => {
+        let event = xml.next_event()?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let is_empty = matches!(event, Event::Empty(_));
                 match e.name().as_ref() {
-                    b"phylogeny" => {
-                        return Err(Error::new(ErrorKind::ImportError {
-                            line: 0,
-                            msg: "Nested phylogeny not permitted".to_owned(),
-                        }));
+                    b"phylogeny" if !is_empty => {
+                        read_phylogeny(xml, trees, options)?;
+                        continue;
                     }
                     b"clade" => {
-                        let attributes: HashMap<&[u8], String> = e
-                            .attributes()
-                            .map(|a| {
-                                // TODO: sometimes this will blow up on an invalid attribute
-                                // (e.g. <test a=">) but there's no way to pass the error
-                                // out easily; we should probably figure out how to do that though
-                                let att = a.unwrap();
-                                let value = att.unescape_value().unwrap().to_string();
-                                (att.key.0, value)
-                            })
-                            .collect();
-                        cur_lineage.push(if tax_ids.is_empty() {
-                            0 // the very first node is the root node
-                        } else {
-                            tax_ids.len() - 1
-                        });
-                        tax_ids.push("".to_string());
-                        names.push(None);
-                        parent_ids.push(*cur_lineage.last().unwrap_or(&0));
-                        dists.push(
-                            attributes
-                                .get(&&b"branch_length"[..])
-                                .unwrap_or(&"1".to_string())
-                                .parse()
-                                .map_err(|_| {
-                                    Error::new(ErrorKind::ImportError {
-                                        line: 0,
-                                        msg: "Could not interpret branch length as a number"
-                                            .to_owned(),
-                                    })
-                                })?,
-                        );
-                        ranks.push(TaxRank::Unspecified);
-                    }
-                    t => current_tag = t.to_vec(),
+                        let branch_length = match xml.attribute(e, b"branch_length")? {
+                            Some(length) => xml.parse_number(&length, "branch length")?,
+                            None => 1.,
+                        };
+                        // the very first node is the root node
+                        let parent = *cur_lineage.last().unwrap_or(&0);
+                        clades.push(Clade::new(parent, branch_length));
+                        if !is_empty {
+                            cur_lineage.push(clades.len() - 1);
+                        }
+                    }
+                    b"property" => {
+                        let key = xml.attribute(e, b"ref")?.unwrap_or_default();
+                        // only keep the name of `prefix:name` references
+                        let key = match key.split_once(':') {
+                            Some((_, name)) => name.to_string(),
+                            None => key,
+                        };
+                        let datatype = xml.attribute(e, b"datatype")?.unwrap_or_default();
+                        property = Some((key, datatype));
+                    }
+                    b"sequence" => {
+                        let mut fields = Map::new();
+                        if let Some(sequence_type) = xml.attribute(e, b"type")? {
+                            fields.insert("type".to_string(), sequence_type.into());
+                        }
+                        sequence = Some(fields);
+                    }
+                    b"accession" => {
+                        if let (Some(fields), Some(source)) =
+                            (sequence.as_mut(), xml.attribute(e, b"source")?)
+                        {
+                            fields.insert("accession_source".to_string(), source.into());
+                        }
+                    }
+                    b"events" => events = Some(Map::new()),
+                    _ => {}
+                }
+                if !is_empty {
+                    elements.push(e.name().as_ref().to_vec());
                 }
             }
             Event::End(ref e) => {
-                match e.name().as_ref() {
-                    b"phylogeny" => break,
-                    b"clade" => {
+                elements.pop();
+                let clade = cur_lineage.last().map(|ix| &mut clades[*ix]);
+                match (e.name().as_ref(), clade) {
+                    (b"phylogeny", _) => break,
+                    (b"clade", _) => {
                         cur_lineage.pop();
                     }
-                    _ => current_tag = b"".to_vec(),
-                };
+                    (b"property", _) => property = None,
+                    (b"sequence", Some(clade)) => {
+                        if let Some(fields) = sequence.take() {
+                            let sequences = clade
+                                .data
+                                .entry(SEQUENCES_KEY.to_string())
+                                .or_insert_with(|| Value::Array(Vec::new()));
+                            if let Value::Array(sequences) = sequences {
+                                sequences.push(Value::Object(fields));
+                            }
+                        }
+                    }
+                    (b"events", Some(clade)) => {
+                        if let Some(fields) = events.take() {
+                            clade
+                                .data
+                                .insert(EVENTS_KEY.to_string(), Value::Object(fields));
+                        }
+                    }
+                    _ => {}
+                }
             }
             Event::Text(e) => {
-                if names.is_empty() {
-                    // sometimes the phylogeny itself has a <name>
-                    continue;
-                }
-                let text = e.unescape()?.to_string();
-                match &current_tag[..] {
-                    b"name" => *names.last_mut().unwrap() = Some(text),
-                    b"id" => *tax_ids.last_mut().unwrap() = text,
-                    b"branch_length" => {
-                        *dists.last_mut().unwrap() = text.parse().map_err(|_| {
-                            Error::new(ErrorKind::ImportError {
-                                line: 0,
-                                msg: "Could not interpret branch length as a number".to_string(),
-                            })
-                        })?;
-                    }
-                    b"rank" => *ranks.last_mut().unwrap() = TaxRank::from_str(&text)?,
-                    // TODO: do something with confidence scores?
-                    // b"confidence" => {},
-                    // TODO: build up a dict of additional metadata we can scrape out?
-                    // b"domain" | b"code" | b"id" | b"common_name" | b"scientific_name"
-                    // | b"accession" | b"description" | b"desc" | b"symbol" | b"mol_seq" | b"property" => {},
-                    _ => {} // bail!("Text outside in unexpected location"),
+                let clade = match cur_lineage.last() {
+                    Some(ix) => &mut clades[*ix],
+                    // e.g. the <name> of the phylogeny itself
+                    None => continue,
+                };
+                let text = xml.text(&e)?;
+                let tag = elements.last().map(|t| t.as_slice()).unwrap_or_default();
+                let parent = match elements.len() {
+                    n if n >= 2 => elements[n - 2].as_slice(),
+                    _ => b"",
+                };
+                match (parent, tag) {
+                    (b"clade", b"name") => clade.name = Some(text),
+                    (b"clade", b"branch_length") => {
+                        clade.branch_length = xml.parse_number(&text, "branch length")?
+                    }
+                    // only the first one is kept if there are several (e.g. bootstrap and
+                    // probability)
+                    (b"clade", b"confidence") if clade.support.is_none() => {
+                        clade.support = Some(xml.parse_number(&text, "confidence")?)
+                    }
+                    (b"clade", b"property") => {
+                        if let Some((key, datatype)) = &property {
+                            clade
+                                .data
+                                .insert(key.clone(), property_to_value(datatype, &text));
+                        }
+                    }
+                    (b"clade" | b"taxonomy", b"id") => clade.tax_id = text,
+                    (b"clade" | b"taxonomy", b"rank") => {
+                        clade.rank = TaxRank::from_str(&text)
+                            .map_err(|_| xml.error(&format!("Unknown rank \"{}\"", text)))?
+                    }
+                    (b"taxonomy", b"scientific_name") => clade.scientific_name = Some(text),
+                    (b"taxonomy", b"common_name") | (b"taxonomy", b"synonym") => {
+                        let class = if tag == b"synonym" {
+                            SYNONYM
+                        } else {
+                            COMMON_NAME
+                        };
+                        clade.other_names.push(TaxName {
+                            name: text,
+                            unique_name: String::new(),
+                            class: class.to_string(),
+                        });
+                    }
+                    (b"taxonomy", b"code" | b"authority" | b"uri") => {
+                        let key = String::from_utf8_lossy(tag).into_owned();
+                        clade.data.insert(key, text.into());
+                    }
+                    (b"sequence", _) => {
+                        if let Some(fields) = sequence.as_mut() {
+                            let key = String::from_utf8_lossy(tag).into_owned();
+                            fields.insert(key, text.into());
+                        }
+                    }
+                    (b"events", _) => {
+                        if let Some(fields) = events.as_mut() {
+                            let value = match tag {
+                                b"duplications" | b"speciations" | b"losses" => {
+                                    Value::from(xml.parse_number::<u64>(&text, "event count")?)
+                                }
+                                b"confidence" => {
+                                    Value::from(xml.parse_number::<f64>(&text, "confidence")?)
+                                }
+                                _ => text.into(),
+                            };
+                            let key = String::from_utf8_lossy(tag).into_owned();
+                            fields.insert(key, value);
+                        }
+                    }
+                    _ => {}
                 }
             }
-            Event::Eof => break,
+            Event::Eof => return Err(xml.error("Missing closing </phylogeny>")),
             _ => (),
         }
-        buf.clear();
+    }
+    if clades.is_empty() {
+        return Err(xml.error("Phylogeny without any clade"));
     }
 
-    let has_any_names = names.iter().all(|n| n.is_none());
-
-    let cleaned_names: Option<Vec<String>> = if has_any_names {
-        None
-    } else {
-        Some(names.into_iter().map(|n| n.unwrap_or_default()).collect())
-    };
-
-    // if not everything has a tax id, should we try to use the names instead?
-    // or should we should have a fallback that makes up an ID for this (and
-    // for Newick trees) for anything that doesn't have any kind of identifier?
+    let mut tax_ids = Vec::with_capacity(clades.len());
+    let mut parent_ids = Vec::with_capacity(clades.len());
+    let mut names = Vec::with_capacity(clades.len());
+    let mut ranks = Vec::with_capacity(clades.len());
+    let mut dists = Vec::with_capacity(clades.len());
+    let mut data = Vec::with_capacity(clades.len());
+    let mut supports = Vec::with_capacity(clades.len());
+    let mut other_names = Vec::with_capacity(clades.len());
+    for clade in clades {
+        tax_ids.push(clade.tax_id);
+        parent_ids.push(clade.parent);
+        // the taxonomy name is preferred to the clade one
+        names.push(clade.scientific_name.or(clade.name).unwrap_or_default());
+        ranks.push(clade.rank);
+        dists.push(clade.branch_length);
+        data.push(clade.data);
+        supports.push(clade.support);
+        other_names.push(clade.other_names);
+    }
 
-    GeneralTaxonomy::from_arrays(
+    let mut tax = GeneralTaxonomy::from_arrays(
         tax_ids,
         parent_ids,
-        cleaned_names,
+        Some(names),
         Some(ranks),
         Some(dists),
-        None,
-    )
+        Some(data),
+    )?;
+    if let Some(prefix) = &options.id_prefix {
+        tax.synthesize_ids(prefix, options.names_as_ids);
+    }
+    tax.support_values = supports;
+    for (idx, node_names) in other_names.into_iter().enumerate() {
+        // the nodes without tax ID can't keep their names
+        let tax_id = &tax.tax_ids[idx];
+        if tax_id.is_empty() {
+            continue;
+        }
+        if !node_names.is_empty() {
+            let mut all_names = vec![TaxName {
+                name: tax.names[idx].clone(),
+                unique_name: String::new(),
+                class: SCIENTIFIC_NAME.to_string(),
+            }];
+            all_names.extend(node_names);
+            tax.all_names.insert(tax_id.clone(), all_names);
+        }
+    }
+    trees[position] = tax;
+    Ok(())
+}
+
+/// Reads the `<phylogeny>`s of a document, stopping after the first one if `first_only`.
+fn read_phylogenies<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+    first_only: bool,
+) -> TaxonomyResult<Vec<GeneralTaxonomy>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let mut xml = PhyloXmlReader::new(&buffer);
+
+    let mut trees = Vec::new();
+    loop {
+        match xml.next_event()? {
+            Event::Start(ref e) if e.name().as_ref() == b"phylogeny" => {
+                read_phylogeny(&mut xml, &mut trees, options)?;
+                if first_only {
+                    break;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if first_only && trees.is_empty() {
+        return Err(xml.error_at(buffer.len(), "No valid phyloxml taxonomy found"));
+    }
+    Ok(trees)
+}
+
+/// Read PhyloXML format into a Taxonomy object out of a `reader`.
+///
+/// Only the first `<phylogeny>` is read if there are several, see [load_all] to read all of
+/// them. For each clade:
+/// - the tax ID is the `<id>` of its `<taxonomy>` (or of the clade itself)
+/// - the name is the `<scientific_name>` of its `<taxonomy>`, or the `<name>` of the clade
+/// - the `<common_name>`s and `<synonym>`s are in [Taxonomy::all_names]
+/// - the first `<confidence>` is the support value
+/// - the `<code>`, `<authority>` and `<uri>` of its `<taxonomy>` and its `<property>`s
+///   (by the name of their `ref`, e.g. `size` for `ref="NCBI:size"`) are in [Taxonomy::data],
+///   along with its `<sequence>`s (as a `sequences` list) and `<events>` (as `events`).
+///
+/// The clades without `<id>` get an empty tax ID, see [LoadOptions::id_prefix] to give them
+/// one. Malformed documents return an [ErrorKind::ImportError] with the line and byte offset
+/// of the element at fault.
+pub fn load<R: Read>(reader: &mut R) -> TaxonomyResult<GeneralTaxonomy> {
+    load_with_options(reader, &LoadOptions::default())
+}
+
+/// Same as [load] but with the given [LoadOptions].
+pub fn load_with_options<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> TaxonomyResult<GeneralTaxonomy> {
+    let mut trees = read_phylogenies(reader, options, true)?;
+    Ok(trees.swap_remove(0))
+}
+
+/// Read all the `<phylogeny>`s of a PhyloXML document out of a `reader`, see [load].
+/// The phylogenies nested in another one are read as separate trees, after it.
+pub fn load_all<R: Read>(reader: &mut R) -> TaxonomyResult<Vec<GeneralTaxonomy>> {
+    load_all_with_options(reader, &LoadOptions::default())
+}
+
+/// Same as [load_all] but with the given [LoadOptions].
+pub fn load_all_with_options<R: Read>(
+    reader: &mut R,
+    options: &LoadOptions,
+) -> TaxonomyResult<Vec<GeneralTaxonomy>> {
+    read_phylogenies(reader, options, false)
+}
+
+/// The `datatype` of a `<property>` and its value, or [None] if that data cannot be written
+/// as a property (lists, objects and nulls).
+fn property_value(value: &Value) -> Option<(&'static str, String)> {
+    match value {
+        Value::String(s) => Some(("xsd:string", s.clone())),
+        Value::Bool(b) => Some(("xsd:boolean", b.to_string())),
+        Value::Number(n) if n.is_f64() => Some(("xsd:double", n.to_string())),
+        Value::Number(n) => Some(("xsd:integer", n.to_string())),
+        _ => None,
+    }
+}
+
+/// Write a single text element, e.g. `<id>562</id>`.
+fn write_text<W: Write>(
+    writer: &mut Writer<W>,
+    start: BytesStart,
+    text: &str,
+) -> TaxonomyResult<()> {
+    let end = start.to_end().into_owned();
+    writer.write_event(Event::Start(start))?;
+    writer.write_event(Event::Text(BytesText::new(text)))?;
+    writer.write_event(Event::End(end))?;
+    Ok(())
+}
+
+/// Write a Taxonomy in the PhyloXML format, as a single `<phylogeny>`.
+///
+/// Each node is a `<clade>` with its `<branch_length>`, its support value as a `<confidence>`
+/// and a `<taxonomy>` with its `<id>`, `<scientific_name>`, `<common_name>`s, `<synonym>`s
+/// and `<rank>` (if it has one). The node data is written as `<property ref="taxonomy:key">`
+/// elements, except for the lists and objects that cannot be written as properties.
+pub fn save<'t, T, W: Write>(
+    writer: &mut W,
+    taxonomy: &'t impl Taxonomy<'t, T>,
+    root_node: Option<T>,
+) -> TaxonomyResult<()>
+where
+    T: 't + Clone + Debug + Display + PartialEq,
+{
+    let root_node = root_node.unwrap_or_else(|| taxonomy.root());
+    let mut writer = Writer::new_with_indent(writer, b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer.write_event(Event::Start(
+        BytesStart::new("phyloxml").with_attributes([("xmlns", PHYLOXML_NAMESPACE)]),
+    ))?;
+    writer.write_event(Event::Start(
+        BytesStart::new("phylogeny").with_attributes([("rooted", "true")]),
+    ))?;
+
+    for (node, pre) in taxonomy.traverse(root_node.clone())? {
+        if !pre {
+            writer.write_event(Event::End(BytesEnd::new("clade")))?;
+            continue;
+        }
+        writer.write_event(Event::Start(BytesStart::new("clade")))?;
+        if node != root_node {
+            if let Some((_, dist)) = taxonomy.parent(node.clone())? {
+                write_text(
+                    &mut writer,
+                    BytesStart::new("branch_length"),
+                    &dist.to_string(),
+                )?;
+            }
+        }
+        if let Some(support) = taxonomy.support(node.clone())? {
+            write_text(
+                &mut writer,
+                BytesStart::new("confidence").with_attributes([("type", CONFIDENCE_TYPE)]),
+                &support.to_string(),
+            )?;
+        }
+
+        writer.write_event(Event::Start(BytesStart::new("taxonomy")))?;
+        write_text(&mut writer, BytesStart::new("id"), &node.to_string())?;
+        let name = taxonomy.name(node.clone())?;
+        if !name.is_empty() {
+            write_text(&mut writer, BytesStart::new("scientific_name"), name)?;
+        }
+        let all_names = taxonomy.all_names(node.clone())?;
+        for (class, tag) in [(COMMON_NAME, "common_name"), (SYNONYM, "synonym")] {
+            for other_name in all_names.iter().filter(|n| n.class == class) {
+                write_text(&mut writer, BytesStart::new(tag), &other_name.name)?;
+            }
+        }
+        let rank = taxonomy.rank(node.clone())?;
+        if rank != TaxRank::Unspecified {
+            write_text(&mut writer, BytesStart::new("rank"), rank.to_ncbi_rank())?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("taxonomy")))?;
+
+        let data = taxonomy.data(node)?;
+        let mut keys: Vec<&String> = data.keys().collect();
+        keys.sort_unstable();
+        for key in keys {
+            if let Some((datatype, value)) = property_value(&data[key]) {
+                let reference = format!("{}{}", PROPERTY_PREFIX, key);
+                let start = BytesStart::new("property").with_attributes([
+                    ("ref", reference.as_str()),
+                    ("datatype", datatype),
+                    ("applies_to", "clade"),
+                ]);
+                write_text(&mut writer, start, &value)?;
+            }
+        }
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("phylogeny")))?;
+    writer.write_event(Event::End(BytesEnd::new("phyloxml")))?;
+    writer.inner().write_all(b"\n")?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -195,6 +641,8 @@ mod test {
         let mut text_cursor = Cursor::new(text_xml);
         let tax = load(&mut text_cursor).unwrap();
         assert_eq!(Taxonomy::<&str>::len(&tax), 5);
+        assert_eq!(tax.parent("B").unwrap(), Some(("D", 0.2)));
+        assert_eq!(tax.parent("C").unwrap(), Some(("E", 0.4)));
 
         let text_xml = r#"
         <phylogeny rooted="true">
@@ -219,6 +667,344 @@ mod test {
         assert_eq!(Taxonomy::<&str>::len(&tax), 5);
     }
 
+    #[test]
+    fn test_load_phyloxml_confidence() {
+        let text_xml = r#"
+        <phylogeny rooted="true">
+          <confidence type="probability">0.5</confidence>
+          <clade>
+            <id>D</id>
+            <clade>
+              <id>C</id>
+              <confidence type="bootstrap">87</confidence>
+              <confidence type="probability">0.99</confidence>
+              <clade><id>A</id></clade>
+              <clade><id>B</id></clade>
+            </clade>
+          </clade>
+        </phylogeny>
+        "#;
+        let tax = load(&mut Cursor::new(text_xml)).unwrap();
+        assert_eq!(tax.support("C").unwrap(), Some(87.));
+        assert_eq!(tax.support("D").unwrap(), None);
+        assert_eq!(tax.support("A").unwrap(), None);
+
+        let text_xml = r#"<phylogeny><clade><confidence>x</confidence></clade></phylogeny>"#;
+        assert!(load(&mut Cursor::new(text_xml)).is_err());
+    }
+
+    #[test]
+    fn test_save_phyloxml() {
+        let newick_str = b"((A:0.5[&&NHX:S=Escherichia coli:rank=species:count=3:gram=negative:x=1.5],B:0.25)C:1[&&NHX:B=95])D;";
+        let mut tax = crate::newick::load(&mut newick_str.as_ref()).unwrap();
+        let idx = tax.to_internal_index("B").unwrap();
+        tax.data[idx].insert("list".to_string(), serde_json::json!([1, 2]));
+        tax.names[idx] = "<B & co>".to_string();
+        let idx = tax.to_internal_index("C").unwrap();
+        tax.data[idx].insert("flag".to_string(), true.into());
+
+        let mut bytes = Vec::new();
+        save::<&str, _>(&mut bytes, &tax, Some("C")).unwrap();
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<phyloxml xmlns="http://www.phyloxml.org">
+  <phylogeny rooted="true">
+    <clade>
+      <confidence type="bootstrap">95</confidence>
+      <taxonomy>
+        <id>C</id>
+      </taxonomy>
+      <property ref="taxonomy:flag" datatype="xsd:boolean" applies_to="clade">true</property>
+      <clade>
+        <branch_length>0.25</branch_length>
+        <taxonomy>
+          <id>B</id>
+          <scientific_name>&lt;B &amp; co&gt;</scientific_name>
+        </taxonomy>
+      </clade>
+      <clade>
+        <branch_length>0.5</branch_length>
+        <taxonomy>
+          <id>A</id>
+          <scientific_name>Escherichia coli</scientific_name>
+          <rank>species</rank>
+        </taxonomy>
+        <property ref="taxonomy:count" datatype="xsd:integer" applies_to="clade">3</property>
+        <property ref="taxonomy:gram" datatype="xsd:string" applies_to="clade">negative</property>
+        <property ref="taxonomy:x" datatype="xsd:double" applies_to="clade">1.5</property>
+      </clade>
+    </clade>
+  </phylogeny>
+</phyloxml>
+"#;
+        assert_eq!(std::str::from_utf8(&bytes).unwrap(), expected);
+
+        // the tree structure survives a round trip
+        let mut bytes = Vec::new();
+        save::<&str, _>(&mut bytes, &tax, None).unwrap();
+        let tax2 = load(&mut bytes.as_slice()).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax2), 4);
+        assert_eq!(tax2.parent("A").unwrap(), Some(("C", 0.5)));
+        assert_eq!(tax2.rank("A").unwrap(), TaxRank::Species);
+        assert_eq!(tax2.support("C").unwrap(), Some(95.));
+        assert_eq!(tax2.name("A").unwrap(), "Escherichia coli");
+        assert_eq!(tax2.name("B").unwrap(), "<B & co>");
+        assert_eq!(tax2.data("A").unwrap(), tax.data("A").unwrap());
+        assert_eq!(tax2.data("C").unwrap(), tax.data("C").unwrap());
+    }
+
+    #[test]
+    fn test_load_phyloxml_annotations() {
+        let text_xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <phyloxml xmlns="http://www.phyloxml.org">
+          <phylogeny rooted="true">
+            <name>annotated</name>
+            <clade>
+              <name>root</name>
+              <clade branch_length="0.5">
+                <name>E. coli clade</name>
+                <taxonomy>
+                  <id provider="ncbi">562</id>
+                  <code>ECOLI</code>
+                  <scientific_name>Escherichia coli</scientific_name>
+                  <authority>(Migula 1895) Castellani and Chalmers 1919</authority>
+                  <common_name>E. coli</common_name>
+                  <synonym>Bacillus coli</synonym>
+                  <synonym>Bacterium coli</synonym>
+                  <rank>species</rank>
+                </taxonomy>
+                <sequence type="protein">
+                  <symbol>lacZ</symbol>
+                  <accession source="UniProtKB">P00722</accession>
+                  <name>Beta-galactosidase</name>
+                </sequence>
+                <events>
+                  <type>speciation_or_duplication</type>
+                  <duplications>1</duplications>
+                  <confidence type="probability">0.7</confidence>
+                </events>
+                <property ref="NCBI:size" datatype="xsd:integer" applies_to="clade">4641652</property>
+                <property ref="gc" datatype="xsd:double" applies_to="clade">50.8</property>
+                <property ref="NCBI:pathogen" datatype="xsd:boolean" applies_to="clade">false</property>
+              </clade>
+              <clade>
+                <name>B</name>
+                <id>B</id>
+              </clade>
+              <clade/>
+            </clade>
+          </phylogeny>
+        </phyloxml>
+        "#;
+        let tax = load(&mut Cursor::new(text_xml)).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax), 4);
+        assert_eq!(tax.name("562").unwrap(), "Escherichia coli");
+        assert_eq!(tax.name("B").unwrap(), "B");
+        assert_eq!(tax.rank("562").unwrap(), TaxRank::Species);
+        assert_eq!(tax.parent("562").unwrap(), Some(("", 0.5)));
+        // the confidence of the events isn't a support value
+        assert_eq!(tax.support("562").unwrap(), None);
+        assert_eq!(
+            tax.names_of("562", "synonym").unwrap(),
+            vec!["Bacillus coli", "Bacterium coli"]
+        );
+        assert_eq!(tax.names_of("562", "common name").unwrap(), vec!["E. coli"]);
+        assert_eq!(
+            tax.find_all_by_name_class("E. coli", &["common name"]),
+            vec!["562"]
+        );
+
+        let data = tax.data("562").unwrap();
+        assert_eq!(data["code"], "ECOLI");
+        assert_eq!(
+            data["authority"],
+            "(Migula 1895) Castellani and Chalmers 1919"
+        );
+        assert_eq!(data["size"], 4641652);
+        assert_eq!(data["gc"], 50.8);
+        assert_eq!(data["pathogen"], false);
+        assert_eq!(
+            data["sequences"],
+            serde_json::json!([{
+                "type": "protein",
+                "symbol": "lacZ",
+                "accession": "P00722",
+                "accession_source": "UniProtKB",
+                "name": "Beta-galactosidase",
+            }])
+        );
+        assert_eq!(
+            data["events"],
+            serde_json::json!({
+                "type": "speciation_or_duplication",
+                "duplications": 1,
+                "confidence": 0.7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_phyloxml_synthesized_ids() {
+        let text_xml = r#"
+        <phylogeny>
+          <clade>
+            <clade>
+              <confidence type="bootstrap">90</confidence>
+              <clade><name>A</name></clade>
+              <clade><id>B</id></clade>
+            </clade>
+            <clade>
+              <taxonomy><scientific_name>B</scientific_name></taxonomy>
+            </clade>
+          </clade>
+        </phylogeny>
+        "#;
+        let tax = load(&mut Cursor::new(text_xml)).unwrap();
+        assert!(tax.validate_uniqueness().is_err());
+        // the clade without id keeps its support value
+        assert_eq!(tax.support_values[1], Some(90.));
+
+        let options = LoadOptions {
+            id_prefix: Some("clade".to_string()),
+            ..Default::default()
+        };
+        let tax = load_with_options(&mut Cursor::new(text_xml), &options).unwrap();
+        assert_eq!(
+            tax.tax_ids,
+            vec!["clade1", "clade2", "clade3", "B", "clade4"]
+        );
+        assert_eq!(tax.support("clade2").unwrap(), Some(90.));
+        assert_eq!(tax.name("clade3").unwrap(), "A");
+
+        let options = LoadOptions {
+            id_prefix: Some("clade".to_string()),
+            names_as_ids: true,
+        };
+        let trees = load_all_with_options(&mut Cursor::new(text_xml), &options).unwrap();
+        assert_eq!(
+            trees[0].tax_ids,
+            vec!["clade1", "clade2", "A", "B", "clade3"]
+        );
+        assert!(trees[0].synthesized_ids.contains("A"));
+        assert!(!trees[0].synthesized_ids.contains("B"));
+    }
+
+    #[test]
+    fn test_load_all_phyloxml() {
+        let text_xml = r#"
+        <phyloxml>
+          <phylogeny>
+            <clade>
+              <id>A</id>
+              <clade><id>B</id></clade>
+              <phylogeny>
+                <clade><id>N</id></clade>
+              </phylogeny>
+              <clade><id>C</id></clade>
+            </clade>
+          </phylogeny>
+          <phylogeny>
+            <clade>
+              <id>D</id>
+              <clade><id>E</id></clade>
+            </clade>
+          </phylogeny>
+        </phyloxml>
+        "#;
+        let trees = load_all(&mut Cursor::new(text_xml)).unwrap();
+        assert_eq!(trees.len(), 3);
+        assert_eq!(Taxonomy::<&str>::len(&trees[0]), 3);
+        assert_eq!(trees[0].parent("C").unwrap(), Some(("A", 1.)));
+        assert_eq!(Taxonomy::<&str>::root(&trees[1]), "N");
+        assert_eq!(trees[2].parent("E").unwrap(), Some(("D", 1.)));
+
+        // load only reads the first one
+        let tax = load(&mut Cursor::new(text_xml)).unwrap();
+        assert_eq!(Taxonomy::<&str>::len(&tax), 3);
+
+        assert!(load_all(&mut Cursor::new("<phyloxml/>"))
+            .unwrap()
+            .is_empty());
+    }
+
+    fn error_at(text_xml: &str) -> (usize, String) {
+        match load(&mut Cursor::new(text_xml)) {
+            Err(e) => match e.kind {
+                ErrorKind::ImportError { line, msg } => (line, msg),
+                _ => panic!("Unexpected error {:?}", e),
+            },
+            Ok(_) => panic!("{} should not load", text_xml),
+        }
+    }
+
+    #[test]
+    fn test_load_invalid_phyloxml() {
+        let (line, msg) = error_at("<phylogeny>\n  <clade branch_length=\"x\"/>\n</phylogeny>");
+        assert_eq!(line, 2);
+        assert!(msg.starts_with("Could not interpret branch length \"x\" as a number"));
+        assert!(msg.ends_with("(byte offset 14, column 3)"));
+
+        // invalid attributes used to panic
+        let (line, msg) = error_at("<phylogeny>\n<clade>\n<clade a=\">\n</clade></phylogeny>");
+        assert_eq!(line, 3);
+        assert!(msg.starts_with("Invalid XML"));
+        let (_, msg) = error_at("<phylogeny><clade a=\"1\" a=\"2\"></clade></phylogeny>");
+        assert!(msg.starts_with("Invalid XML"));
+        let (_, msg) = error_at("<phylogeny><clade branch_length=\"&bogus;\"/></phylogeny>");
+        assert!(msg.starts_with("Invalid XML"));
+
+        let (line, msg) =
+            error_at("<phylogeny>\n<clade>\n<name>&bogus;</name></clade></phylogeny>");
+        assert_eq!(line, 3);
+        assert!(msg.starts_with("Invalid XML"));
+        assert!(error_at("<phylogeny><clade></taxonomy></phylogeny>")
+            .1
+            .starts_with("Invalid XML"));
+        assert!(
+            error_at("<phylogeny><clade><rank>unknown-rank</rank></clade></phylogeny>")
+                .1
+                .starts_with("Unknown rank \"unknown-rank\"")
+        );
+        assert!(error_at("<phylogeny><clade>")
+            .1
+            .starts_with("Missing closing </phylogeny>"));
+        assert!(error_at("<phylogeny><name>empty</name></phylogeny>")
+            .1
+            .starts_with("Phylogeny without any clade"));
+    }
+
+    #[test]
+    fn test_load_malformed_phyloxml() {
+        let newick_str =
+            b"((A:0.5[&&NHX:S=Escherichia coli:rank=species:count=3],B:0.25)C:1[&&NHX:B=95])D;";
+        let tax = crate::newick::load(&mut newick_str.as_ref()).unwrap();
+        let mut bytes = Vec::new();
+        save::<&str, _>(&mut bytes, &tax, None).unwrap();
+
+        // every truncation and corruption of a valid document either loads or returns an
+        // import error with its position, but never panics
+        let check = |text_xml: &[u8]| {
+            if let Err(e) = load(&mut &text_xml[..]) {
+                match e.kind {
+                    ErrorKind::ImportError { line, msg } => {
+                        assert!(line >= 1);
+                        assert!(msg.contains("(byte offset"), "{}", msg);
+                    }
+                    _ => panic!("Unexpected error {:?}", e),
+                }
+            }
+        };
+        for end in 0..bytes.len() {
+            check(&bytes[..end]);
+        }
+        for ix in 0..bytes.len() {
+            for replacement in b"<>/=\"'&;x9 " {
+                let mut corrupted = bytes.clone();
+                corrupted[ix] = *replacement;
+                check(&corrupted);
+            }
+        }
+    }
+
     #[test]
     fn test_no_valid_phyloxml() {
         let text_xml = r#"
@@ -2,6 +2,7 @@
 extern crate pyo3;
 
 mod base;
+mod crosswalk;
 mod edit;
 pub mod errors;
 mod formats;
@@ -11,9 +12,12 @@ mod rank;
 mod taxonomy;
 
 pub use crate::taxonomy::Taxonomy;
-pub use base::GeneralTaxonomy;
-pub use edit::{prune_away, prune_to};
+pub use base::{GeneralTaxonomy, TaxName, SCIENTIFIC_NAME};
+pub use crosswalk::{Crosswalk, Direction, TranslatedAbundances, Translation};
+pub use edit::{collapse_low_support, prune_away, prune_to};
 pub use errors::{Error, ErrorKind};
+pub use formats::accession2taxid;
+pub use formats::binary;
 pub use formats::gtdb;
 pub use formats::json;
 pub use formats::ncbi;
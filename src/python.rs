@@ -12,11 +12,15 @@ use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyDict, PyList, PyType};
 use serde_json::Value;
 
-use crate::base::InternalIndex;
+use crate::base::{InternalIndex, NodeInfo};
+use crate::errors::{Error, ErrorKind};
 use crate::json::JsonFormat;
 use crate::rank::TaxRank;
 use crate::Taxonomy as TaxonomyTrait;
-use crate::{gtdb, json, ncbi, newick, phyloxml, prune_away, prune_to, GeneralTaxonomy};
+use crate::{
+    accession2taxid, binary, collapse_low_support, gtdb, json, ncbi, newick, phyloxml, prune_away,
+    prune_to, Direction, GeneralTaxonomy,
+};
 
 create_exception!(taxonomy, TaxonomyError, pyo3::exceptions::PyException);
 
@@ -148,31 +152,88 @@ impl Taxonomy {
     }
 
     pub(crate) fn as_node(&self, tax_id: &str) -> PyResult<TaxonomyNode> {
+        // Merged tax ids are reported as the node they were merged into
+        let idx = py_try!(self.tax.to_internal_index(tax_id));
+        let tax_id = py_try!(self.tax.from_internal_index(idx));
         let name = self.get_name(tax_id)?;
         let rank = self.get_rank(tax_id)?;
         let parent = py_try!(self.tax.parent(tax_id)).map(|(p, _)| p.to_string());
-        let extra = py_try!(self.tax.data(tax_id));
+        let mut extra = py_try!(self.tax.data(tax_id)).into_owned();
+        if let Some(info) = py_try!(self.tax.node_info(tax_id)) {
+            extra.extend(self.ncbi_fields(tax_id, &info)?);
+        }
 
         Ok(TaxonomyNode {
             id: tax_id.to_string(),
             name: name.to_string(),
             rank,
-            extra: (*extra).to_owned(),
+            extra,
             parent,
         })
     }
+
+    /// The other `nodes.dmp` columns of a NCBI node, along with the names of its division
+    /// and genetic codes if known, so they can be accessed by indexing the node.
+    fn ncbi_fields(&self, tax_id: &str, info: &NodeInfo) -> PyResult<HashMap<String, Value>> {
+        // Going through serde so we don't have to list all the fields again
+        let mut fields: HashMap<String, Value> = match serde_json::to_value(info) {
+            Ok(Value::Object(fields)) => fields.into_iter().filter(|(_, v)| !v.is_null()).collect(),
+            _ => HashMap::new(),
+        };
+        if let Some(division) = py_try!(self.tax.division(tax_id)) {
+            fields.insert("division".to_string(), Value::from(division.name.clone()));
+        }
+        if let Some(code) = py_try!(self.tax.genetic_code(tax_id)) {
+            fields.insert("genetic_code".to_string(), Value::from(code.name.clone()));
+        }
+        if let Some(code) = py_try!(self.tax.mitochondrial_genetic_code(tax_id)) {
+            fields.insert(
+                "mitochondrial_genetic_code".to_string(),
+                Value::from(code.name.clone()),
+            );
+        }
+        Ok(fields)
+    }
 }
 
 #[pymethods]
 impl Taxonomy {
-    /// from_gtdb(cls, value: str)
+    /// from_gtdb(cls, value: str, /, include_genomes: bool)
     /// --
     ///
     /// Load a Taxonomy from a GTDB-encoded string.
+    ///
+    /// If `include_genomes` is true, each genome is added as a leaf node below its species,
+    /// with the "strain" rank and its accession without the `RS_`/`GB_` prefix as id.
     #[classmethod]
-    fn from_gtdb(_cls: &PyType, value: &str) -> PyResult<Taxonomy> {
+    fn from_gtdb(_cls: &PyType, value: &str, include_genomes: Option<bool>) -> PyResult<Taxonomy> {
         let mut c = Cursor::new(value);
-        let tax = py_try!(gtdb::load(&mut c));
+        let options = gtdb::LoadOptions {
+            include_genomes: include_genomes.unwrap_or(false),
+        };
+        let tax = py_try!(gtdb::load_with_options(&mut c, &options));
+        Ok(Taxonomy { tax })
+    }
+
+    /// from_gtdb_metadata(cls, path: str, /, columns: List[str])
+    /// --
+    ///
+    /// Load a Taxonomy from a GTDB metadata file (e.g. `bac120_metadata.tsv`), plain or gzipped.
+    ///
+    /// The tree is built from the `gtdb_taxonomy` column with each genome as a leaf node,
+    /// like `from_gtdb` with `include_genomes`. The given `columns` (by default the CheckM
+    /// completeness and contamination, genome size, GC percentage, representative flag,
+    /// NCBI tax id and NCBI lineage) are available on the genome nodes, e.g.
+    /// `node["checkm_completeness"]`.
+    #[classmethod]
+    fn from_gtdb_metadata(
+        _cls: &PyType,
+        path: &str,
+        columns: Option<Vec<&str>>,
+    ) -> PyResult<Taxonomy> {
+        let mut file = py_try!(std::fs::File::open(path));
+        let columns = columns.unwrap_or_else(|| gtdb::DEFAULT_METADATA_COLUMNS.to_vec());
+        let tax = py_try!(gtdb::load_metadata(&mut file, &columns));
         Ok(Taxonomy { tax })
     }
 
@@ -183,6 +244,7 @@ impl Taxonomy {
     /// of the tree or node_link_data types and will be automatically detected.
     /// If `path` is specified, the JSON will be traversed to that sub-object
     /// before being parsed as a taxonomy. `path` has to be a valid JSON path string.
+    /// Branch lengths are read from the `distance` field of the nodes, 1 if missing.
     #[classmethod]
     fn from_json(_cls: &PyType, value: &str, json_pointer: Option<&str>) -> PyResult<Taxonomy> {
         let mut c = Cursor::new(value);
@@ -190,14 +252,30 @@ impl Taxonomy {
         Ok(Taxonomy { tax })
     }
 
-    /// from_newick(cls, value: str)
+    /// from_newick(cls, value: str, /, internal_support: bool, id_prefix: str, names_as_ids: bool)
     /// --
     ///
     /// Load a Taxonomy from a Newick-encoded string.
+    /// If `internal_support` is true, the numeric labels of the internal nodes are read as
+    /// their support value (see `support`) rather than as their id.
+    /// If `id_prefix` is set, the nodes without label get an id made of that prefix and a
+    /// counter (e.g. "node1") instead of an empty one, or their name if `names_as_ids` is true
+    /// and it isn't already used. These ids are listed in `synthesized_ids`.
     #[classmethod]
-    fn from_newick(_cls: &PyType, value: &str) -> PyResult<Taxonomy> {
+    fn from_newick(
+        _cls: &PyType,
+        value: &str,
+        internal_support: Option<bool>,
+        id_prefix: Option<String>,
+        names_as_ids: Option<bool>,
+    ) -> PyResult<Taxonomy> {
         let mut c = Cursor::new(value);
-        let tax = py_try!(newick::load(&mut c));
+        let options = newick::LoadOptions {
+            internal_support: internal_support.unwrap_or(false),
+            id_prefix,
+            names_as_ids: names_as_ids.unwrap_or(false),
+        };
+        let tax = py_try!(newick::load_with_options(&mut c, &options));
         Ok(Taxonomy { tax })
     }
 
@@ -206,22 +284,72 @@ impl Taxonomy {
     ///
     /// Load a Taxonomy from a directory.
     /// The directory must contain the `nodes.dmp` and `names.dmp` files.
+    /// If a `merged.dmp` file is present, merged tax ids will resolve to their new node.
+    /// If a `delnodes.dmp` file is present, looking up a deleted tax id raises an error
+    /// saying it was deleted.
+    /// The other `nodes.dmp` columns are available by indexing the nodes,
+    /// e.g. `node["genetic_code_id"]`.
+    /// If the `host.dmp` and `typematerial.dmp` files are present, they are available
+    /// as `node["hosts"]` and `node["type_material"]`.
     #[classmethod]
     fn from_ncbi(_cls: &PyType, dump_dir: &str) -> PyResult<Taxonomy> {
         let tax = py_try!(ncbi::load(dump_dir));
         Ok(Taxonomy { tax })
     }
 
-    /// from_phyloxml(cls, value: str)
+    /// from_ncbi_archive(cls, path: str)
+    /// --
+    ///
+    /// Load a Taxonomy from a NCBI `taxdump.tar.gz` (or `new_taxdump.tar.gz`) archive
+    /// without extracting it. Uncompressed tar archives are also accepted.
+    /// The archive must contain the `nodes.dmp` and `names.dmp` files, the other files
+    /// are handled like in `from_ncbi`.
+    #[classmethod]
+    fn from_ncbi_archive(_cls: &PyType, path: &str) -> PyResult<Taxonomy> {
+        let file = py_try!(std::fs::File::open(path));
+        let tax = py_try!(ncbi::load_archive(file));
+        Ok(Taxonomy { tax })
+    }
+
+    /// from_phyloxml(cls, value: str, /, id_prefix: str, names_as_ids: bool)
     /// --
     ///
     /// Load a Taxonomy from a PhyloXML-encoded string.
+    /// Only the first phylogeny is loaded. The common names and synonyms of the clades are
+    /// available with `names_of`, and their properties, sequences and events by indexing
+    /// the nodes.
+    /// If `id_prefix` is set, the clades without id get an id made of that prefix and a
+    /// counter (e.g. "node1") instead of an empty one, or their name if `names_as_ids` is true
+    /// and it isn't already used. These ids are listed in `synthesized_ids`.
     ///
     /// Experimental.
     #[classmethod]
-    fn from_phyloxml(_cls: &PyType, value: &str) -> PyResult<Taxonomy> {
+    fn from_phyloxml(
+        _cls: &PyType,
+        value: &str,
+        id_prefix: Option<String>,
+        names_as_ids: Option<bool>,
+    ) -> PyResult<Taxonomy> {
         let mut c = Cursor::new(value);
-        let tax = py_try!(phyloxml::load(&mut c));
+        let options = phyloxml::LoadOptions {
+            id_prefix,
+            names_as_ids: names_as_ids.unwrap_or(false),
+        };
+        let tax = py_try!(phyloxml::load_with_options(&mut c, &options));
+        Ok(Taxonomy { tax })
+    }
+
+    /// from_binary(cls, path: str)
+    /// --
+    ///
+    /// Load a Taxonomy from a file in the binary format written by `to_binary`.
+    /// The whole file is read and its checksum verified, like the other formats it is
+    /// then fully loaded in memory.
+    #[classmethod]
+    fn from_binary(_cls: &PyType, path: &str) -> PyResult<Taxonomy> {
+        let binary_tax = py_try!(binary::BinaryTaxonomy::open(path));
+        py_try!(binary_tax.verify());
+        let tax = py_try!(binary_tax.to_general_taxonomy());
         Ok(Taxonomy { tax })
     }
 
@@ -276,20 +404,123 @@ impl Taxonomy {
         Ok(())
     }
 
-    /// to_newick(self)
+    /// to_ncbi_lineages(self, output_dir: str)
+    /// --
+    ///
+    /// Export the lineage files of the NCBI new_taxdump format (rankedlineage.dmp,
+    /// fullnamelineage.dmp and taxidlineage.dmp), computed from the lineage of each node.
+    /// The output directory will be created if it doesn't exist.
+    ///
+    /// Args:
+    ///     output_dir: Path to the directory where the lineage files will be written
+    fn to_ncbi_lineages(&self, output_dir: &str) -> PyResult<()> {
+        py_try!(ncbi::save_lineages::<&str, _, _>(&self.tax, output_dir));
+        Ok(())
+    }
+
+    /// to_binary(self, path: str)
+    /// --
+    ///
+    /// Export a Taxonomy to a file in a compact, versioned binary format that can
+    /// be loaded back with `Taxonomy.from_binary`.
+    fn to_binary(&self, path: &str) -> PyResult<()> {
+        let mut writer = std::io::BufWriter::new(py_try!(std::fs::File::create(path)));
+        py_try!(binary::save(&mut writer, &self.tax));
+        py_try!(std::io::Write::flush(&mut writer));
+        Ok(())
+    }
+
+    /// to_newick(self, /, label: Union[str, Callable[[TaxonomyNode], str]], internal_labels: bool, branch_lengths: bool, precision: int, nhx: bool, support_labels: bool)
     /// --
     ///
     /// Export a Taxonomy as a Newick-encoded byte string.
-    fn to_newick(&self, py: Python<'_>) -> PyResult<PyObject> {
+    ///
+    /// The nodes are labelled by `label`: "id" (the default), "name", "id|name" or a function
+    /// returning the label of a node. Labels are quoted if needed.
+    /// If `internal_labels` is false only the leaves are labelled, and if `branch_lengths`
+    /// is false no branch length is written. `precision` sets the number of decimals of the
+    /// branch lengths and `nhx` writes the name, rank, support value and data of the nodes as
+    /// NHX attributes. If `support_labels` is true the internal nodes are labelled by their
+    /// support value instead.
+    #[allow(clippy::too_many_arguments)]
+    fn to_newick(
+        slf: &PyCell<Self>,
+        py: Python<'_>,
+        label: Option<PyObject>,
+        internal_labels: Option<bool>,
+        branch_lengths: Option<bool>,
+        precision: Option<usize>,
+        nhx: Option<bool>,
+        support_labels: Option<bool>,
+    ) -> PyResult<PyObject> {
+        let label = match label {
+            None => newick::NodeLabel::TaxId,
+            Some(label) => match label.extract::<&str>(py) {
+                Ok("id") => newick::NodeLabel::TaxId,
+                Ok("name") => newick::NodeLabel::Name,
+                Ok("id|name") => newick::NodeLabel::TaxIdAndName,
+                Ok(other) => {
+                    return Err(PyErr::new::<TaxonomyError, _>(format!(
+                        "Unknown label {}, expected \"id\", \"name\", \"id|name\" or a function",
+                        other
+                    )))
+                }
+                Err(_) => {
+                    let tax: Py<Taxonomy> = slf.into();
+                    newick::NodeLabel::Custom(std::sync::Arc::new(move |tax_id, _| {
+                        Python::with_gil(|py| {
+                            let node = tax.borrow(py).as_node(tax_id)?;
+                            label.call1(py, (node,))?.extract::<String>(py)
+                        })
+                        .map_err(|e| {
+                            Error::new(ErrorKind::OperationNotAllowed(format!(
+                                "Could not label node {}: {}",
+                                tax_id, e
+                            )))
+                        })
+                    }))
+                }
+            },
+        };
+        let options = newick::SaveOptions {
+            label,
+            omit_internal_labels: !internal_labels.unwrap_or(true),
+            omit_branch_lengths: !branch_lengths.unwrap_or(true),
+            precision,
+            nhx: nhx.unwrap_or(false),
+            support_labels: support_labels.unwrap_or(false),
+        };
+        let this = slf.borrow();
         let mut bytes = Vec::new();
-        py_try!(newick::save(
+        py_try!(newick::save_with_options(
             &mut bytes,
-            &self.tax,
-            Some(TaxonomyTrait::<&str>::root(&self.tax))
+            &this.tax,
+            Some(TaxonomyTrait::<&str>::root(&this.tax)),
+            &options
         ));
         Ok(PyBytes::new(py, &bytes).into())
     }
 
+    /// to_phyloxml(self)
+    /// --
+    ///
+    /// Export a Taxonomy as a PhyloXML-encoded byte string.
+    fn to_phyloxml(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut bytes = Vec::new();
+        py_try!(phyloxml::save::<&str, _>(&mut bytes, &self.tax, None));
+        Ok(PyBytes::new(py, &bytes).into())
+    }
+
+    /// to_gtdb(self)
+    /// --
+    ///
+    /// Export a Taxonomy as a GTDB-encoded byte string, with one line per leaf.
+    fn to_gtdb(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut bytes = Vec::new();
+        py_try!(gtdb::save::<&str, _, _>(&mut bytes, &self.tax, None));
+        Ok(PyBytes::new(py, &bytes).into())
+    }
+
     /// node(self, tax_id: str) -> Optional[TaxonomyNode]
     /// --
     ///
@@ -298,20 +529,76 @@ impl Taxonomy {
         self.as_node(tax_id).ok()
     }
 
-    /// find_all_by_name(self, name: str) -> List[TaxonomyNode]
+    /// merged_into(self, tax_id: str) -> Optional[str]
+    /// --
+    ///
+    /// Return the tax id that this tax id was merged into, or `None` if it wasn't merged.
+    fn merged_into(&self, tax_id: &str) -> Option<String> {
+        self.tax.merged_into(tax_id).map(|x| x.to_string())
+    }
+
+    /// support(self, tax_id: str) -> Optional[float]
+    /// --
+    ///
+    /// Return the support value (e.g. the bootstrap) of the node, or `None` if it doesn't
+    /// have one. Support values are read from phylogenetic trees.
+    fn support(&self, tax_id: &str) -> PyResult<Option<f32>> {
+        Ok(py_try!(self.tax.support(tax_id)))
+    }
+
+    /// is_deleted(self, tax_id: str) -> bool
+    /// --
+    ///
+    /// Return whether this tax id has been deleted from the taxonomy.
+    fn is_deleted(&self, tax_id: &str) -> bool {
+        self.tax.is_deleted(tax_id)
+    }
+
+    /// find_all_by_name(self, name: str, /, name_classes: List[str]) -> List[TaxonomyNode]
     /// --
     ///
     /// Find a node by its name, Raises an exception if not found.
-    fn find_all_by_name(&self, name: &str) -> PyResult<Vec<TaxonomyNode>> {
-        let res = self
-            .tax
-            .find_all_by_name(name)
+    ///
+    /// If `name_classes` is provided, search the names of those classes
+    /// (e.g. `["scientific name", "synonym"]`) instead of only the main name.
+    fn find_all_by_name(
+        &self,
+        name: &str,
+        name_classes: Option<Vec<&str>>,
+    ) -> PyResult<Vec<TaxonomyNode>> {
+        let tax_ids = if let Some(classes) = name_classes {
+            self.tax.find_all_by_name_class(name, &classes)
+        } else {
+            self.tax.find_all_by_name(name)
+        };
+        let res = tax_ids
             .into_iter()
             .map(|tax_id| self.as_node(tax_id))
             .collect::<PyResult<Vec<TaxonomyNode>>>()?;
         Ok(res)
     }
 
+    /// find_gtdb_variants(self, name: str) -> List[TaxonomyNode]
+    /// --
+    ///
+    /// Find all the nodes of a GTDB taxonomy that only differ from that name by their
+    /// suffixes, e.g. `g__Escherichia` and `g__Escherichia_A` for `g__Escherichia`.
+    fn find_gtdb_variants(&self, name: &str) -> PyResult<Vec<TaxonomyNode>> {
+        gtdb::find_suffixed_variants(&self.tax, name)
+            .into_iter()
+            .map(|tax_id| self.as_node(tax_id))
+            .collect()
+    }
+
+    /// names_of(self, tax_id: str, name_class: str) -> List[str]
+    /// --
+    ///
+    /// Return all the names of that class for the node id provided, e.g. its synonyms.
+    fn names_of(&self, tax_id: &str, name_class: &str) -> PyResult<Vec<String>> {
+        let names = py_try!(self.tax.names_of(tax_id, name_class));
+        Ok(names.into_iter().map(|n| n.to_string()).collect())
+    }
+
     /// parent_with_distance(self, tax_id: str, /, at_rank: str)
     /// --
     ///
@@ -439,6 +726,16 @@ impl Taxonomy {
         Ok(Taxonomy { tax })
     }
 
+    /// collapse_low_support(self, threshold: float)
+    /// --
+    ///
+    /// Return a copy of the taxonomy where the internal nodes with a support value below
+    /// `threshold` are removed, their children being attached to their parent.
+    fn collapse_low_support(&self, threshold: f32) -> PyResult<Taxonomy> {
+        let tax = py_try!(collapse_low_support::<&str>(&self.tax, threshold));
+        Ok(Taxonomy { tax })
+    }
+
     /// remove_node(self, tax_id: str)
     /// --
     ///
@@ -530,6 +827,14 @@ impl Taxonomy {
         self.as_node(key).unwrap()
     }
 
+    /// The ids that were made up for the nodes without any when loading the taxonomy
+    #[getter]
+    fn synthesized_ids(&self) -> Vec<String> {
+        let mut tax_ids: Vec<String> = self.tax.synthesized_ids.iter().cloned().collect();
+        tax_ids.sort();
+        tax_ids
+    }
+
     fn __repr__(&self) -> PyResult<String> {
         Ok(format!(
             "<Taxonomy ({} nodes)>",
@@ -627,11 +932,166 @@ impl TaxonomyIterator {
     }
 }
 
+/// An index of the accession -> tax id mappings from NCBI `accession2taxid` files
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct AccessionIndex {
+    index: accession2taxid::AccessionIndex,
+}
+
+#[pymethods]
+impl AccessionIndex {
+    /// from_file(cls, path: str, taxonomy: Taxonomy)
+    /// --
+    ///
+    /// Load an `accession2taxid` file, plain or gzipped. Tax ids are checked against
+    /// the taxonomy: merged ones are replaced by the tax id they were merged into and
+    /// the accessions of the tax ids not in the taxonomy are skipped.
+    #[classmethod]
+    fn from_file(_cls: &PyType, path: &str, taxonomy: &Taxonomy) -> PyResult<AccessionIndex> {
+        let file = py_try!(std::fs::File::open(path));
+        let index = py_try!(accession2taxid::AccessionIndex::load(file, &taxonomy.tax));
+        Ok(AccessionIndex { index })
+    }
+
+    /// add_file(self, path: str, taxonomy: Taxonomy)
+    /// --
+    ///
+    /// Add the content of another `accession2taxid` file, plain or gzipped, to the index.
+    fn add_file(&mut self, path: &str, taxonomy: &Taxonomy) -> PyResult<()> {
+        let file = py_try!(std::fs::File::open(path));
+        py_try!(self.index.add(file, &taxonomy.tax));
+        Ok(())
+    }
+
+    /// tax_id(self, accession: str) -> Optional[str]
+    /// --
+    ///
+    /// Return the tax id of the accession, with or without its version, or `None` if it
+    /// isn't in the index.
+    fn tax_id(&self, accession: &str) -> Option<String> {
+        self.index.tax_id(accession).map(|x| x.to_string())
+    }
+
+    /// node(self, accession: str, taxonomy: Taxonomy) -> Optional[TaxonomyNode]
+    /// --
+    ///
+    /// Return the node of the accession in the taxonomy, or `None` if not found.
+    fn node(&self, accession: &str, taxonomy: &Taxonomy) -> Option<TaxonomyNode> {
+        self.index
+            .tax_id(accession)
+            .and_then(|tax_id| taxonomy.node(tax_id))
+    }
+
+    /// The tax ids of the files that were not found in the taxonomy
+    #[getter]
+    fn unknown_tax_ids(&self) -> Vec<String> {
+        let mut tax_ids: Vec<String> = self.index.unknown_tax_ids.iter().cloned().collect();
+        tax_ids.sort();
+        tax_ids
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.len()
+    }
+
+    fn __contains__(&self, accession: &str) -> bool {
+        self.index.tax_id(accession).is_some()
+    }
+}
+
+/// Parses the `to` argument of the Crosswalk methods
+fn crosswalk_direction(to: &str) -> PyResult<Direction> {
+    match to {
+        "ncbi" => Ok(Direction::GtdbToNcbi),
+        "gtdb" => Ok(Direction::NcbiToGtdb),
+        _ => Err(PyErr::new::<TaxonomyError, _>(format!(
+            "Unknown taxonomy {}, expected \"ncbi\" or \"gtdb\"",
+            to
+        ))),
+    }
+}
+
+/// The abundances, confidences and untranslated abundances of `translate_abundances`
+type PyTranslatedAbundances = (
+    HashMap<String, f64>,
+    HashMap<String, f32>,
+    HashMap<String, f64>,
+);
+
+/// A many-to-many mapping between the GTDB and NCBI taxonomies, built from their genomes
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct Crosswalk {
+    crosswalk: crate::Crosswalk,
+}
+
+#[pymethods]
+impl Crosswalk {
+    /// Crosswalk(ncbi: Taxonomy, gtdb: Taxonomy)
+    /// --
+    ///
+    /// Build the crosswalk out of the genomes of a GTDB taxonomy loaded with
+    /// `Taxonomy.from_gtdb_metadata` with their `ncbi_taxid`. Genomes with tax ids that are
+    /// not in the NCBI taxonomy are ignored.
+    #[new]
+    fn new(ncbi: &Taxonomy, gtdb: &Taxonomy) -> PyResult<Crosswalk> {
+        let crosswalk = py_try!(crate::Crosswalk::new(&ncbi.tax, &gtdb.tax));
+        Ok(Crosswalk { crosswalk })
+    }
+
+    /// mappings(self, tax_id: str, to: str) -> List[Tuple[str, int]]
+    /// --
+    ///
+    /// Return all the nodes of the `to` taxonomy ("ncbi" or "gtdb") sharing genomes with
+    /// that node, with the number of genomes. The best supported ones come first.
+    fn mappings(&self, tax_id: &str, to: &str) -> PyResult<Vec<(String, usize)>> {
+        let direction = crosswalk_direction(to)?;
+        Ok(self
+            .crosswalk
+            .mappings(tax_id, direction)
+            .into_iter()
+            .map(|(t, n)| (t.to_string(), n))
+            .collect())
+    }
+
+    /// translate(self, tax_id: str, to: str) -> Optional[Tuple[str, float]]
+    /// --
+    ///
+    /// Return the node of the `to` taxonomy ("ncbi" or "gtdb") supported by the most
+    /// genomes, with the fraction of the genomes of the node supporting it.
+    fn translate(&self, tax_id: &str, to: &str) -> PyResult<Option<(String, f32)>> {
+        let direction = crosswalk_direction(to)?;
+        Ok(self
+            .crosswalk
+            .translate(tax_id, direction)
+            .map(|t| (t.tax_id, t.confidence)))
+    }
+
+    /// translate_abundances(self, abundances: Dict[str, float], to: str) -> Tuple[Dict[str, float], Dict[str, float], Dict[str, float]]
+    /// --
+    ///
+    /// Translate an abundance table keyed by tax id into the `to` taxonomy ("ncbi" or "gtdb").
+    /// Return the translated abundances, the confidence of each translated node (weighted
+    /// by abundance) and the abundances of the nodes that could not be translated.
+    fn translate_abundances(
+        &self,
+        abundances: HashMap<String, f64>,
+        to: &str,
+    ) -> PyResult<PyTranslatedAbundances> {
+        let direction = crosswalk_direction(to)?;
+        let result = self.crosswalk.translate_abundances(&abundances, direction);
+        Ok((result.abundances, result.confidences, result.untranslated))
+    }
+}
+
 /// The taxonomy module
 #[pymodule]
 fn taxonomy(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Taxonomy>()?;
     m.add_class::<TaxonomyNode>()?;
+    m.add_class::<AccessionIndex>()?;
+    m.add_class::<Crosswalk>()?;
     m.add("TaxonomyError", py.get_type::<TaxonomyError>())?;
 
     Ok(())
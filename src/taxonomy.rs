@@ -1,3 +1,4 @@
+use crate::base::{Division, GeneticCode, NodeInfo, TaxName};
 use crate::errors::TaxonomyResult;
 use crate::rank::TaxRank;
 use serde_json::Value;
@@ -85,7 +86,7 @@ where
 
         // find the lowest common ancestor
         let mut common = self.root();
-        for (pid1, pid2) in id1_parents.into_iter().zip(id2_parents.into_iter()) {
+        for (pid1, pid2) in id1_parents.into_iter().zip(id2_parents) {
             if pid1 != pid2 {
                 break;
             }
@@ -95,7 +96,7 @@ where
     }
 
     /// Returns the name of the tax_id provided.
-    fn name(&'t self, tax_id: T) -> TaxonomyResult<&str>;
+    fn name(&'t self, tax_id: T) -> TaxonomyResult<&'t str>;
 
     /// Returns the additional data at the given tax id node
     /// This is only used by the json taxonomy so the value is serde_json::Value
@@ -104,6 +105,55 @@ where
         Ok(Cow::Owned(HashMap::new()))
     }
 
+    /// Returns all the names of the given tax id with their class, e.g. synonyms or
+    /// common names. This is only used by the NCBI taxonomy.
+    /// By default it just returns an empty list
+    fn all_names(&'t self, _tax_id: T) -> TaxonomyResult<Cow<'t, [TaxName]>> {
+        Ok(Cow::Owned(Vec::new()))
+    }
+
+    /// Returns the support value (e.g. the bootstrap) of the tax_id provided, if it has one.
+    /// This is only used by phylogenetic trees.
+    /// By default it just returns [None]
+    fn support(&'t self, _tax_id: T) -> TaxonomyResult<Option<f32>> {
+        Ok(None)
+    }
+
+    /// Returns the extra NCBI `nodes.dmp` columns of the tax_id provided, if it has them.
+    /// This is only used by the NCBI taxonomy.
+    /// By default it just returns [None]
+    fn node_info(&'t self, _tax_id: T) -> TaxonomyResult<Option<Cow<'t, NodeInfo>>> {
+        Ok(None)
+    }
+
+    /// Returns the NCBI division with that ID, as referred to by [NodeInfo::division_id].
+    /// This is only used by the NCBI taxonomy.
+    /// By default it just returns [None]
+    fn division_by_id(&'t self, _id: u32) -> TaxonomyResult<Option<Cow<'t, Division>>> {
+        Ok(None)
+    }
+
+    /// Returns the NCBI genetic code with that ID, as referred to by [NodeInfo::genetic_code_id]
+    /// and the other genetic code IDs. This is only used by the NCBI taxonomy.
+    /// By default it just returns [None]
+    fn genetic_code_by_id(&'t self, _id: u32) -> TaxonomyResult<Option<Cow<'t, GeneticCode>>> {
+        Ok(None)
+    }
+
+    /// Returns the tax IDs that were merged into another node, with the tax ID of that node.
+    /// This is only used by the NCBI taxonomy.
+    /// By default it just returns an empty list
+    fn merged_tax_ids(&'t self) -> TaxonomyResult<Vec<(&'t str, &'t str)>> {
+        Ok(Vec::new())
+    }
+
+    /// Returns the tax IDs that were deleted from the taxonomy.
+    /// This is only used by the NCBI taxonomy.
+    /// By default it just returns an empty list
+    fn deleted_tax_ids(&'t self) -> TaxonomyResult<Vec<&'t str>> {
+        Ok(Vec::new())
+    }
+
     /// Returns the taxonomic rank of the tax_id provided.
     fn rank(&'t self, tax_id: T) -> TaxonomyResult<TaxRank>;
 
@@ -276,7 +326,7 @@ pub(crate) mod tests {
         let tax = MockTax;
         assert_eq!(tax.root(), 1);
         assert_eq!(tax.len(), 14);
-        assert_eq!(tax.is_empty(), false);
+        assert!(!tax.is_empty());
     }
 
     #[test]
@@ -335,11 +385,11 @@ pub(crate) mod tests {
             .map(|(ix, (tid, pre))| match ix {
                 0 => {
                     assert_eq!(tid, 1, "root is first");
-                    assert_eq!(pre, true, "preorder visits happen first");
+                    assert!(pre, "preorder visits happen first");
                 }
                 27 => {
                     assert_eq!(tid, 1, "root is last too");
-                    assert_eq!(pre, false, "postorder visits happen last");
+                    assert!(!pre, "postorder visits happen last");
                 }
                 _ => {
                     if pre {
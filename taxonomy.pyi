@@ -1,4 +1,4 @@
-from typing import Any, List, Optional, Tuple, Iterator
+from typing import Any, Callable, Dict, List, Optional, Tuple, Iterator, Union
 
 class TaxonomyError(Exception):
     """Raised when an error occurs in the taxonomy library."""
@@ -26,9 +26,31 @@ class Taxonomy:
     """
     @property
     def root(self) -> TaxonomyNode: ...
+    @property
+    def synthesized_ids(self) -> List[str]:
+        """The ids that were made up for the nodes without any when loading the taxonomy."""
+        ...
+
     @classmethod
-    def from_gtdb(cls, value: str) -> "Taxonomy":
-        """Load a Taxonomy from a GTDB-encoded string."""
+    def from_gtdb(cls, value: str, include_genomes: bool = False) -> "Taxonomy":
+        """
+        Load a Taxonomy from a GTDB-encoded string.
+        If `include_genomes` is true, each genome is added as a leaf node below its
+        species, with the "strain" rank and its accession without the `RS_`/`GB_`
+        prefix as id.
+        """
+        ...
+
+    @classmethod
+    def from_gtdb_metadata(
+        cls, path: str, columns: Optional[List[str]] = None
+    ) -> "Taxonomy":
+        """
+        Load a Taxonomy from a GTDB metadata file (e.g. `bac120_metadata.tsv`), plain or
+        gzipped. The tree is built from the `gtdb_taxonomy` column with each genome as a
+        leaf node, and the given `columns` are available on the genome nodes, e.g.
+        `node["checkm_completeness"]`.
+        """
         ...
 
     @classmethod
@@ -38,12 +60,26 @@ class Taxonomy:
         of the tree or node_link_data types and will be automatically detected.
         If `path` is specified, the JSON will be traversed to that sub-object
         before being parsed as a taxonomy. `path` has to be a valid JSON path string.
+        Branch lengths are read from the `distance` field of the nodes, 1 if missing.
         """
         ...
 
     @classmethod
-    def from_newick(cls, value: str) -> "Taxonomy":
-        """Load a Taxonomy from a Newick-encoded string."""
+    def from_newick(
+        cls,
+        value: str,
+        internal_support: bool = False,
+        id_prefix: Optional[str] = None,
+        names_as_ids: bool = False,
+    ) -> "Taxonomy":
+        """
+        Load a Taxonomy from a Newick-encoded string.
+        If `internal_support` is true, the numeric labels of the internal nodes are read as
+        their support value (see `support`) rather than as their id.
+        If `id_prefix` is set, the nodes without label get an id made of that prefix and a
+        counter (e.g. "node1") instead of an empty one, or their name if `names_as_ids` is
+        true and it isn't already used. These ids are listed in `synthesized_ids`.
+        """
         ...
 
     @classmethod
@@ -51,12 +87,48 @@ class Taxonomy:
         """
         Load a Taxonomy from a directory.
         The directory must contain the `nodes.dmp` and `names.dmp` files.
+        If a `merged.dmp` file is present, merged tax ids will resolve to their new node.
+        If a `delnodes.dmp` file is present, looking up a deleted tax id raises an error
+        saying it was deleted.
+        The other `nodes.dmp` columns are available by indexing the nodes,
+        e.g. `node["genetic_code_id"]`.
+        If the `host.dmp` and `typematerial.dmp` files are present, they are available
+        as `node["hosts"]` and `node["type_material"]`.
         """
         ...
 
     @classmethod
-    def from_phyloxml(cls, value: str) -> "Taxonomy":
-        """Load a Taxonomy from a PhyloXML-encoded string. Experimental."""
+    def from_ncbi_archive(cls, path: str) -> "Taxonomy":
+        """
+        Load a Taxonomy from a NCBI `taxdump.tar.gz` (or `new_taxdump.tar.gz`) archive
+        without extracting it. Uncompressed tar archives are also accepted.
+        The archive must contain the `nodes.dmp` and `names.dmp` files, the other files
+        are handled like in `from_ncbi`.
+        """
+        ...
+
+    @classmethod
+    def from_phyloxml(
+        cls, value: str, id_prefix: Optional[str] = None, names_as_ids: bool = False
+    ) -> "Taxonomy":
+        """
+        Load a Taxonomy from a PhyloXML-encoded string. Experimental.
+        Only the first phylogeny is loaded. The common names and synonyms of the clades are
+        available with `names_of`, and their properties, sequences and events by indexing
+        the nodes.
+        If `id_prefix` is set, the clades without id get an id made of that prefix and a
+        counter (e.g. "node1") instead of an empty one, or their name if `names_as_ids` is
+        true and it isn't already used. These ids are listed in `synthesized_ids`.
+        """
+        ...
+
+    @classmethod
+    def from_binary(cls, path: str) -> "Taxonomy":
+        """
+        Load a Taxonomy from a file in the binary format written by `to_binary`.
+        The whole file is read and its checksum verified, like the other formats it is
+        then fully loaded in memory.
+        """
         ...
 
     def clone(self) -> "Taxonomy":
@@ -71,8 +143,39 @@ class Taxonomy:
         """Export a Taxonomy as a JSON-encoded byte string in a node link format"""
         ...
 
-    def to_newick(self) -> bytes:
-        """Export a Taxonomy as a Newick-encoded byte string."""
+    def to_newick(
+        self,
+        label: Union[str, Callable[[TaxonomyNode], str]] = "id",
+        internal_labels: bool = True,
+        branch_lengths: bool = True,
+        precision: Optional[int] = None,
+        nhx: bool = False,
+        support_labels: bool = False,
+    ) -> bytes:
+        """
+        Export a Taxonomy as a Newick-encoded byte string.
+        The nodes are labelled by `label`: "id", "name", "id|name" or a function returning
+        the label of a node. Labels are quoted if needed.
+        If `internal_labels` is false only the leaves are labelled, and if `branch_lengths`
+        is false no branch length is written. `precision` sets the number of decimals of
+        the branch lengths and `nhx` writes the name, rank, support value and data of the
+        nodes as NHX attributes. If `support_labels` is true the internal nodes are labelled
+        by their support value instead.
+        """
+        ...
+
+    def to_phyloxml(self) -> bytes:
+        """
+        Export a Taxonomy as a PhyloXML-encoded byte string. Each node is a `<clade>` with a
+        `<taxonomy>` holding its id, name and rank, and its data as `<property>` elements.
+        """
+        ...
+
+    def to_gtdb(self) -> bytes:
+        """
+        Export a Taxonomy as a GTDB-encoded byte string, with one
+        `accession<TAB>d__...;p__...;...;s__...` line per leaf.
+        """
         ...
 
     def to_ncbi(self, output_dir: str) -> None:
@@ -82,12 +185,59 @@ class Taxonomy:
         """
         ...
 
+    def to_ncbi_lineages(self, output_dir: str) -> None:
+        """
+        Export the lineage files of the NCBI new_taxdump format (rankedlineage.dmp,
+        fullnamelineage.dmp and taxidlineage.dmp), computed from the lineage of each node.
+        The output directory will be created if it doesn't exist.
+        """
+        ...
+
+    def to_binary(self, path: str) -> None:
+        """
+        Export a Taxonomy to a file in a compact, versioned binary format that can
+        be loaded back with `Taxonomy.from_binary`.
+        """
+        ...
+
     def node(self, tax_id: str) -> Optional[TaxonomyNode]:
         """Find a node by its id. Returns `None` if not found"""
         ...
 
-    def find_all_by_name(self, name: str) -> List[TaxonomyNode]:
-        """Find a node by its name, Raises an exception if not found."""
+    def merged_into(self, tax_id: str) -> Optional[str]:
+        """Return the tax id that this tax id was merged into, or `None` if it wasn't merged."""
+        ...
+
+    def support(self, tax_id: str) -> Optional[float]:
+        """
+        Return the support value (e.g. the bootstrap) of the node, or `None` if it doesn't
+        have one. Support values are read from phylogenetic trees.
+        """
+        ...
+
+    def is_deleted(self, tax_id: str) -> bool:
+        """Return whether this tax id has been deleted from the taxonomy."""
+        ...
+
+    def find_all_by_name(
+        self, name: str, name_classes: Optional[List[str]] = None
+    ) -> List[TaxonomyNode]:
+        """
+        Find a node by its name, Raises an exception if not found.
+        If `name_classes` is provided, search the names of those classes
+        (e.g. `["scientific name", "synonym"]`) instead of only the main name.
+        """
+        ...
+
+    def find_gtdb_variants(self, name: str) -> List[TaxonomyNode]:
+        """
+        Find all the nodes of a GTDB taxonomy that only differ from that name by their
+        suffixes, e.g. `g__Escherichia` and `g__Escherichia_A` for `g__Escherichia`.
+        """
+        ...
+
+    def names_of(self, tax_id: str, name_class: str) -> List[str]:
+        """Return all the names of that class for the node id provided, e.g. its synonyms."""
         ...
 
     def parent_with_distance(
@@ -148,6 +298,13 @@ class Taxonomy:
         """
         ...
 
+    def collapse_low_support(self, threshold: float) -> "Taxonomy":
+        """
+        Return a copy of the taxonomy where the internal nodes with a support value below
+        `threshold` are removed, their children being attached to their parent.
+        """
+        ...
+
     def remove_node(self, tax_id: str) -> None:
         """Remove the node from the tree."""
         ...
@@ -177,3 +334,75 @@ class Taxonomy:
 class TaxonomyIterator:
     def __next__(self) -> Optional[str]: ...
     def __iter__(self) -> "TaxonomyIterator": ...
+
+class AccessionIndex:
+    """
+    An index of the accession -> tax id mappings from NCBI `accession2taxid` files.
+    Accessions can be looked up with or without their version.
+    """
+    @classmethod
+    def from_file(cls, path: str, taxonomy: Taxonomy) -> "AccessionIndex":
+        """
+        Load an `accession2taxid` file, plain or gzipped. Tax ids are checked against
+        the taxonomy: merged ones are replaced by the tax id they were merged into and
+        the accessions of the tax ids not in the taxonomy are skipped.
+        """
+        ...
+
+    def add_file(self, path: str, taxonomy: Taxonomy) -> None:
+        """Add the content of another `accession2taxid` file to the index."""
+        ...
+
+    def tax_id(self, accession: str) -> Optional[str]:
+        """Return the tax id of the accession, or `None` if it isn't in the index."""
+        ...
+
+    def node(self, accession: str, taxonomy: Taxonomy) -> Optional[TaxonomyNode]:
+        """Return the node of the accession in the taxonomy, or `None` if not found."""
+        ...
+
+    @property
+    def unknown_tax_ids(self) -> List[str]:
+        """The tax ids of the files that were not found in the taxonomy."""
+        ...
+
+    def __len__(self) -> int: ...
+    def __contains__(self, accession: str) -> bool: ...
+
+class Crosswalk:
+    """
+    A many-to-many mapping between the GTDB and NCBI taxonomies, at each GTDB rank,
+    built from the genomes they have in common.
+    """
+
+    def __init__(self, ncbi: Taxonomy, gtdb: Taxonomy) -> None:
+        """
+        Build the crosswalk out of the genomes of a GTDB taxonomy loaded with
+        `Taxonomy.from_gtdb_metadata` with their `ncbi_taxid`. Genomes with tax ids
+        that are not in the NCBI taxonomy are ignored.
+        """
+        ...
+
+    def mappings(self, tax_id: str, to: str) -> List[Tuple[str, int]]:
+        """
+        Return all the nodes of the `to` taxonomy ("ncbi" or "gtdb") sharing genomes
+        with that node, with the number of genomes. The best supported ones come first.
+        """
+        ...
+
+    def translate(self, tax_id: str, to: str) -> Optional[Tuple[str, float]]:
+        """
+        Return the node of the `to` taxonomy ("ncbi" or "gtdb") supported by the most
+        genomes, with the fraction of the genomes of the node supporting it.
+        """
+        ...
+
+    def translate_abundances(
+        self, abundances: Dict[str, float], to: str
+    ) -> Tuple[Dict[str, float], Dict[str, float], Dict[str, float]]:
+        """
+        Translate an abundance table keyed by tax id into the `to` taxonomy. Return the
+        translated abundances, the confidence of each translated node (weighted by
+        abundance) and the abundances of the nodes that could not be translated.
+        """
+        ...
@@ -1,10 +1,11 @@
 import json
 import pytest
 
-from taxonomy import Taxonomy, TaxonomyError
+from taxonomy import AccessionIndex, Crosswalk, Taxonomy, TaxonomyError
 from downloads import download
 import os
 import subprocess
+import tarfile
 
 JSON_DATA = """
 {
@@ -266,6 +267,16 @@ def test_newick_parent_with_distance(newick_tax: Taxonomy):
     assert abs(distance - 0.4) < 1e-6
 
 
+def test_newick_json_round_trip_distances(newick_tax: Taxonomy):
+    tree = newick_tax.to_json_tree()
+    assert json.loads(tree)["children"][0]["distance"] == pytest.approx(0.1)
+    tax = Taxonomy.from_json(tree.decode())
+    parent, distance = tax.parent_with_distance("D")
+    assert parent is not None
+    assert parent.id == "E"
+    assert abs(distance - 0.4) < 1e-6
+
+
 def test_newick_children(newick_tax: Taxonomy):
     children = newick_tax.children("E")
     assert len(children) == 2
@@ -347,6 +358,98 @@ def test_newick_output_uses_tax_ids(newick_tax: Taxonomy):
         assert tax_id in res
 
 
+def test_newick_output_options(ncbi_tax: Taxonomy):
+    tax = ncbi_tax.prune(keep=["562"])
+    assert tax.to_newick(label="name", internal_labels=False, branch_lengths=False) == (
+        b"(((((((('Escherichia coli'))))))));"
+    )
+    assert tax.to_newick(label="id|name", precision=2).startswith(
+        b"(((((((('562|Escherichia coli':1.00)561|Escherichia:1.00)"
+    )
+    assert tax.to_newick(label=lambda node: node.rank).startswith(
+        b"((((((((species:1)genus:1)"
+    )
+    assert b"[&&NHX:S=Escherichia coli:rank=species" in tax.to_newick(nhx=True)
+
+    with pytest.raises(TaxonomyError):
+        tax.to_newick(label="unknown")
+    with pytest.raises(TaxonomyError):
+        tax.to_newick(label=lambda node: 1)
+
+
+def test_newick_support_values():
+    tax = Taxonomy.from_newick(
+        "((A:1,B:1)C:1[&&NHX:B=40],(D:1,E:1)95:1)F;", internal_support=True
+    )
+    assert tax.support("C") == 40
+    assert tax.support("A") is None
+    # the unlabelled node keeps its support value
+    assert tax.to_newick(support_labels=True) == b"((E:1,D:1)95:1,(B:1,A:1)40:1);"
+
+    collapsed = tax.collapse_low_support(50)
+    assert collapsed.node("C") is None
+    assert collapsed.parent_with_distance("A") == (collapsed["F"], 2)
+    assert len(collapsed) == 6
+    assert len(tax) == 7
+
+
+def test_phyloxml_output(newick_tax: Taxonomy):
+    xml = newick_tax.to_phyloxml()
+    assert xml.startswith(b'<?xml version="1.0" encoding="UTF-8"?>')
+    assert b"<id>A</id>" in xml
+
+    tax = Taxonomy.from_phyloxml(xml.decode("utf-8"))
+    assert len(tax) == len(newick_tax)
+    parent, distance = tax.parent_with_distance("D")
+    assert parent.id == "E"
+    assert abs(distance - 0.4) < 1e-6
+
+
+def test_phyloxml_annotations():
+    tax = Taxonomy.from_phyloxml(
+        """<phyloxml><phylogeny><clade><id>1</id><clade>
+        <taxonomy>
+          <id>562</id>
+          <code>ECOLI</code>
+          <scientific_name>Escherichia coli</scientific_name>
+          <synonym>Bacillus coli</synonym>
+        </taxonomy>
+        <property ref="NCBI:size" datatype="xsd:integer">4641652</property>
+        </clade></clade></phylogeny></phyloxml>"""
+    )
+    node = tax["562"]
+    assert node.name == "Escherichia coli"
+    assert node["code"] == "ECOLI"
+    assert node["size"] == 4641652
+    assert tax.names_of("562", "synonym") == ["Bacillus coli"]
+
+
+def test_phyloxml_invalid():
+    with pytest.raises(TaxonomyError) as e:
+        Taxonomy.from_phyloxml('<phylogeny>\n<clade a=">\n</clade></phylogeny>')
+    assert "at line 2" in str(e.value)
+
+
+def test_synthesized_ids():
+    tax = Taxonomy.from_newick("((A,B),(C,D)[&&NHX:S=Inner]);", id_prefix="node")
+    assert tax.root.id == "node1"
+    assert tax["A"].parent == "node2"
+    assert tax.synthesized_ids == ["node1", "node2", "node3"]
+
+    tax = Taxonomy.from_newick(
+        "((A,B),(C,D)[&&NHX:S=Inner]);", id_prefix="node", names_as_ids=True
+    )
+    assert tax["C"].parent == "Inner"
+    assert Taxonomy.from_newick("(A,B);").synthesized_ids == []
+
+    tax = Taxonomy.from_phyloxml(
+        "<phylogeny><clade><clade><name>A</name></clade></clade></phylogeny>",
+        id_prefix="clade",
+        names_as_ids=True,
+    )
+    assert tax.synthesized_ids == ["A", "clade1"]
+
+
 def test_ncbi_root(ncbi_tax: Taxonomy):
     root = ncbi_tax.root
     assert root.id == "1"
@@ -374,6 +477,35 @@ def test_ncbi_index(ncbi_tax: Taxonomy):
         _ = ncbi_tax["unknown"]
 
 
+def test_ncbi_merged_ids(ncbi_tax: Taxonomy):
+    assert ncbi_tax.merged_into("469598") == "562"
+    assert ncbi_tax.merged_into("562") is None
+    node = ncbi_tax["469598"]
+    assert node.id == "562"
+    assert node.name == "Escherichia coli"
+    assert "469598" in ncbi_tax
+
+
+def test_ncbi_deleted_ids(ncbi_tax: Taxonomy):
+    assert ncbi_tax.is_deleted("2795424")
+    assert not ncbi_tax.is_deleted("562")
+    assert ncbi_tax.node("2795424") is None
+    with pytest.raises(TaxonomyError, match="deleted"):
+        _ = ncbi_tax["2795424"]
+
+
+def test_ncbi_archive(ncbi_tax: Taxonomy, tmp_path):
+    path = tmp_path / "taxdump.tar.gz"
+    with tarfile.open(path, "w:gz") as archive:
+        for filename in ["nodes.dmp", "names.dmp", "merged.dmp"]:
+            archive.add(os.path.join("tests/data", filename), arcname=filename)
+
+    tax = Taxonomy.from_ncbi_archive(str(path))
+    assert len(tax) == len(ncbi_tax)
+    assert tax["562"].name == "Escherichia coli"
+    assert tax["469598"].id == "562"
+
+
 def test_ncbi_find_all_by_name(ncbi_tax: Taxonomy):
     nodes = ncbi_tax.find_all_by_name("Escherichia coli")
     assert [n.id for n in nodes] == ["562"]
@@ -381,6 +513,74 @@ def test_ncbi_find_all_by_name(ncbi_tax: Taxonomy):
     assert [n.parent for n in nodes] == ["561"]
 
 
+def test_ncbi_name_classes(ncbi_tax: Taxonomy):
+    assert "Bacterium coli" in ncbi_tax.names_of("562", "synonym")
+    assert ncbi_tax.names_of("562", "common name") == ["E. coli"]
+    assert ncbi_tax.find_all_by_name("E. coli") == []
+    nodes = ncbi_tax.find_all_by_name("E. coli", name_classes=["common name"])
+    assert [n.id for n in nodes] == ["562"]
+
+
+def test_ncbi_node_info(ncbi_tax: Taxonomy):
+    node = ncbi_tax["562"]
+    assert node["genetic_code_id"] == 11
+    assert node["genetic_code"] == "Bacterial, Archaeal and Plant Plastid"
+    assert node["division"] == "Bacteria"
+    assert node["embl_code"] == "EC"
+    assert ncbi_tax.prune(keep=["562"])["562"]["division"] == "Bacteria"
+    # the nodes.dmp columns are not part of the JSON exports
+    nodes = json.loads(ncbi_tax.to_json_node_links())["nodes"]
+    assert all("genetic_code_id" not in n for n in nodes)
+
+
+def test_binary_round_trip(ncbi_tax: Taxonomy, tmp_path):
+    path = str(tmp_path / "taxonomy.bin")
+    ncbi_tax.to_binary(path)
+    tax = Taxonomy.from_binary(path)
+    assert len(tax) == len(ncbi_tax)
+    assert tax["562"].name == "Escherichia coli"
+    assert tax["562"]["genetic_code_id"] == 11
+    assert tax["469598"].id == "562"
+
+
+def test_accession_index(ncbi_tax: Taxonomy, tmp_path):
+    index = AccessionIndex.from_file("tests/data/nucl.accession2taxid", ncbi_tax)
+    assert len(index) == 3
+    assert index.tax_id("NC_000913.3") == "562"
+    assert index.tax_id("NC_000913") == "562"
+    # 469598 was merged into 562
+    assert index.tax_id("U00096.2") == "562"
+    assert index.tax_id("XX000001.1") is None
+    assert "NC_001416" in index
+    assert index.node("NC_001416.1", ncbi_tax).name == "Viruses"
+    assert index.node("unknown", ncbi_tax) is None
+    assert index.unknown_tax_ids == ["2795424", "999999999"]
+
+    path = tmp_path / "prot.accession2taxid"
+    path.write_text("accession.version\ttaxid\nWP_000000001.1\t2\n")
+    index.add_file(str(path), ncbi_tax)
+    assert len(index) == 4
+    assert index.tax_id("WP_000000001") == "2"
+
+
+def test_ncbi_host_and_type_material(ncbi_tax: Taxonomy):
+    assert ncbi_tax["10239"]["hosts"] == ["bacteria", "vertebrates"]
+    type_material = ncbi_tax["562"]["type_material"]
+    assert type_material[0] == {
+        "name": "Escherichia coli",
+        "type": "neotype",
+        "identifier": "ATCC 11775",
+    }
+
+
+def test_ncbi_lineages(ncbi_tax: Taxonomy, tmp_path):
+    ncbi_tax.to_ncbi_lineages(str(tmp_path))
+    with open(tmp_path / "taxidlineage.dmp") as f:
+        assert "562\t|\t131567 2 1224 1236 91347 543 561 \t|\n" in f.read()
+    with open(tmp_path / "rankedlineage.dmp") as f:
+        assert "561\t|\tEscherichia\t|\t\t|\t\t|\tEnterobacteriaceae\t|" in f.read()
+
+
 def test_ncbi_parent(ncbi_tax: Taxonomy):
     parent = ncbi_tax.parent("562")
     assert parent is not None
@@ -513,6 +713,100 @@ def test_gtdb_lineage(gtdb_tax: Taxonomy):
     ]
 
 
+def test_gtdb_genomes():
+    with open("tests/data/gtdb_sample.tsv") as file:
+        tax = Taxonomy.from_gtdb(file.read(), include_genomes=True)
+    node = tax.node("GCF_012278395.1")
+    assert node.rank == "strain"
+    assert node.parent == "s__Staphylococcus aureus"
+    assert node["gtdb_accession"] == "RS_GCF_012278395.1"
+    assert [n.id for n in tax.lineage("GCA_001420305.1")][:2] == [
+        "GCA_001420305.1",
+        "s__Acinetobacter baumannii",
+    ]
+
+
+def test_gtdb_round_trip():
+    with open("tests/data/gtdb_sample.tsv") as file:
+        content = file.read()
+    tax = Taxonomy.from_gtdb(content, include_genomes=True)
+    lines = tax.to_gtdb().decode().splitlines()
+    assert sorted(lines) == sorted(content.splitlines())
+
+    pruned = tax.prune(keep=["GCF_012278395.1"])
+    assert pruned.to_gtdb() == (
+        b"RS_GCF_012278395.1\td__Bacteria;p__Firmicutes;c__Bacilli;o__Staphylococcales;"
+        b"f__Staphylococcaceae;g__Staphylococcus;s__Staphylococcus aureus\n"
+    )
+
+
+def test_gtdb_metadata():
+    tax = Taxonomy.from_gtdb_metadata("tests/data/gtdb_metadata_sample.tsv")
+    node = tax["GCF_000005845.2"]
+    assert node.parent == "s__Escherichia coli"
+    assert node["checkm_completeness"] == 100.0
+    assert node["genome_size"] == 4641652
+    assert node["gtdb_representative"] is False
+    assert node["ncbi_taxid"] == "562"
+
+    tax = Taxonomy.from_gtdb_metadata(
+        "tests/data/gtdb_metadata_sample.tsv", columns=["ncbi_organism_name"]
+    )
+    node = tax["GCF_000195955.2"]
+    assert node["ncbi_organism_name"] == "Mycobacterium tuberculosis H37Rv"
+    with pytest.raises(KeyError):
+        node["genome_size"]
+
+
+def test_gtdb_ncbi_crosswalk(ncbi_tax: Taxonomy):
+    gtdb_tax = Taxonomy.from_gtdb_metadata("tests/data/gtdb_metadata_sample.tsv")
+    crosswalk = Crosswalk(ncbi_tax, gtdb_tax)
+    assert crosswalk.mappings("562", to="gtdb") == [
+        ("s__Escherichia coli", 2),
+        ("s__Escherichia albertii", 1),
+    ]
+    tax_id, confidence = crosswalk.translate("562", to="gtdb")
+    assert tax_id == "s__Escherichia coli"
+    assert confidence == pytest.approx(2 / 3)
+    assert crosswalk.translate("g__Escherichia", to="ncbi") == ("561", 1.0)
+    assert crosswalk.translate("unknown", to="ncbi") is None
+
+    abundances, confidences, untranslated = crosswalk.translate_abundances(
+        {"s__Escherichia coli": 3.0, "s__Escherichia albertii": 1.0, "unknown": 0.5},
+        to="ncbi",
+    )
+    assert abundances == {"562": 4.0}
+    assert confidences == {"562": 1.0}
+    assert untranslated == {"unknown": 0.5}
+
+    with pytest.raises(TaxonomyError):
+        crosswalk.translate("562", to="silva")
+
+
+def test_gtdb_variants(gtdb_tax: Taxonomy):
+    assert sorted(n.id for n in gtdb_tax.find_gtdb_variants("g__Pseudomonas")) == [
+        "g__Pseudomonas",
+        "g__Pseudomonas_A",
+        "g__Pseudomonas_B",
+        "g__Pseudomonas_D",
+        "g__Pseudomonas_E",
+    ]
+    assert gtdb_tax["g__Pseudomonas_E"]["gtdb_base_name"] == "g__Pseudomonas"
+    assert gtdb_tax.find_gtdb_variants("g__Unknown") == []
+
+
+def test_gtdb_messy_lineages():
+    tax = Taxonomy.from_gtdb(
+        " A \td__Bacteria; p__Firmicutes ;s__\n\nB\td__Bacteria;p__Firmicutes;c__Bacilli\n"
+    )
+    assert [n.id for n in tax.lineage("c__Bacilli")] == [
+        "c__Bacilli",
+        "p__Firmicutes",
+        "d__Bacteria",
+    ]
+    assert "s__" not in tax
+
+
 def test_gtdb_invalid_format():
     with open("tests/data/gtdb_invalid.tsv") as file:
         with pytest.raises(TaxonomyError):